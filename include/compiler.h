@@ -0,0 +1,223 @@
+/* The C API of the compiler library. Generated by cbindgen from src/ffi.rs: do not edit. */
+
+#ifndef COMPILER_H
+#define COMPILER_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * How deeply calls may nest unless the limits say otherwise.
+ */
+#define DEFAULT_CALL_DEPTH 1000
+
+/**
+ * What `compiler_value_type` reports.
+ */
+typedef enum CompilerType {
+  COMPILER_TYPE_NULL,
+  COMPILER_TYPE_BOOLEAN,
+  COMPILER_TYPE_INTEGER,
+  COMPILER_TYPE_NUMBER,
+  COMPILER_TYPE_STRING,
+  /**
+   * Lists, objects, functions and anything else C cannot read directly;
+   * `compiler_value_display` still shows them.
+   */
+  COMPILER_TYPE_OTHER,
+  COMPILER_TYPE_ERROR,
+} CompilerType;
+
+/**
+ * An interpreter, with the globals its programs defined.
+ */
+typedef struct CompilerEngine CompilerEngine;
+
+/**
+ * A value of the language, or, returned by a native callback, the error
+ * it failed with.
+ */
+typedef struct CompilerValue CompilerValue;
+
+/**
+ * A native function written in C. It gets the arguments of the call,
+ * which stay owned by the engine, and `user_data` as it was registered,
+ * and returns a value it allocated, or `NULL` to fail the call. It is
+ * called from the thread that called `compiler_eval`, except in `work`
+ * blocks and generator bodies, which run on threads the engine starts.
+ */
+typedef struct CompilerValue *(*CompilerNative)(const struct CompilerValue *const *arguments,
+                                                size_t count,
+                                                void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * A new engine with the builtins and no natives.
+ */
+struct CompilerEngine *compiler_engine_new(void);
+
+/**
+ * # Safety
+ * `engine` must be `NULL` or come from `compiler_engine_new`, and is not
+ * used afterwards.
+ */
+void compiler_engine_free(struct CompilerEngine *engine);
+
+/**
+ * Runs `source` and returns the value of its last statement, or `NULL` if
+ * it failed; `compiler_last_error` then tells why.
+ *
+ * # Safety
+ * `engine` must come from `compiler_engine_new` and `source` be a
+ * NUL-terminated string.
+ */
+struct CompilerValue *compiler_eval(struct CompilerEngine *engine, const char *source);
+
+/**
+ * The message of the last failed call on `engine`, or `NULL` if the last
+ * call succeeded. It stays valid until the next call on `engine`.
+ *
+ * # Safety
+ * `engine` must come from `compiler_engine_new`.
+ */
+const char *compiler_last_error(const struct CompilerEngine *engine);
+
+/**
+ * Defines the global `name` as a copy of `value`.
+ *
+ * # Safety
+ * `engine` must come from `compiler_engine_new`, `name` be a
+ * NUL-terminated string and `value` a value from this API.
+ */
+bool compiler_set_global(struct CompilerEngine *engine,
+                         const char *name,
+                         const struct CompilerValue *value);
+
+/**
+ * Caps what each `compiler_eval` may use: steps, nested calls, bytes a
+ * single value may take, bytes the lists and objects of the program may
+ * take together and milliseconds of wall-clock time. `0` leaves a
+ * limit off, except for calls, which always have one: `0` keeps them to
+ * `DEFAULT_CALL_DEPTH`.
+ *
+ * # Safety
+ * `engine` must come from `compiler_engine_new`.
+ */
+void compiler_set_limits(struct CompilerEngine *engine,
+                         uint64_t fuel,
+                         size_t call_depth,
+                         size_t value_size,
+                         size_t heap,
+                         uint64_t timeout_ms);
+
+/**
+ * Registers `callback` as `namespace.name`, for scripts to import with
+ * `interop`.
+ *
+ * # Safety
+ * `engine` must come from `compiler_engine_new` and `namespace` and
+ * `name` be NUL-terminated strings. `user_data` is passed to `callback`
+ * as is and must stay valid as long as the engine.
+ */
+bool compiler_register_native(struct CompilerEngine *engine,
+                              const char *namespace_,
+                              const char *name,
+                              CompilerNative callback,
+                              void *user_data);
+
+/**
+ * # Safety
+ * `value` must be `NULL` or a value from this API, and is not used
+ * afterwards.
+ */
+void compiler_value_free(struct CompilerValue *value);
+
+/**
+ * # Safety
+ * `value` must be a value from this API.
+ */
+enum CompilerType compiler_value_type(const struct CompilerValue *value);
+
+/**
+ * Stores a boolean value in `out`; `false` for other values.
+ *
+ * # Safety
+ * `value` must be a value from this API and `out` point to a `bool`.
+ */
+bool compiler_value_bool(const struct CompilerValue *value, bool *out);
+
+/**
+ * Stores an integer value in `out`; `false` for other values.
+ *
+ * # Safety
+ * `value` must be a value from this API and `out` point to an `int64_t`.
+ */
+bool compiler_value_integer(const struct CompilerValue *value, int64_t *out);
+
+/**
+ * Stores a number in `out`, integers included; `false` for other values.
+ *
+ * # Safety
+ * `value` must be a value from this API and `out` point to a `double`.
+ */
+bool compiler_value_number(const struct CompilerValue *value, double *out);
+
+/**
+ * The text of a string value, or `NULL` for other values. Free it with
+ * `compiler_string_free`.
+ *
+ * # Safety
+ * `value` must be a value from this API.
+ */
+char *compiler_value_string(const struct CompilerValue *value);
+
+/**
+ * Any value as the REPL prints it. Free it with `compiler_string_free`.
+ *
+ * # Safety
+ * `value` must be a value from this API.
+ */
+char *compiler_value_display(const struct CompilerValue *value);
+
+/**
+ * # Safety
+ * `text` must be `NULL` or a string from this API, and is not used
+ * afterwards.
+ */
+void compiler_string_free(char *text);
+
+struct CompilerValue *compiler_value_new_null(void);
+
+struct CompilerValue *compiler_value_new_bool(bool b);
+
+struct CompilerValue *compiler_value_new_integer(int64_t i);
+
+struct CompilerValue *compiler_value_new_number(double n);
+
+/**
+ * A copy of `text`, or `NULL` if it is not valid UTF-8.
+ *
+ * # Safety
+ * `text` must be a NUL-terminated string.
+ */
+struct CompilerValue *compiler_value_new_string(const char *text);
+
+/**
+ * For a native callback to return: fails the call with `message`.
+ *
+ * # Safety
+ * `message` must be a NUL-terminated string.
+ */
+struct CompilerValue *compiler_value_new_error(const char *message);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* COMPILER_H */
@@ -0,0 +1,454 @@
+//! C API over `Engine`, built into the `cdylib`. The declarations C code
+//! includes are in `include/compiler.h`, generated from this module by
+//! cbindgen; `tests/ffi.rs` fails when the header is out of date.
+//!
+//! An engine is used from one thread at a time. Values handed out are owned
+//! by the caller and freed with `compiler_value_free`, strings with
+//! `compiler_string_free`.
+
+use std::any::Any;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::time::Duration;
+use crate::runtime::engine::Engine;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::limits::{Limits, DEFAULT_CALL_DEPTH};
+use crate::runtime::value::Value;
+
+/// An interpreter, with the globals its programs defined.
+pub struct CompilerEngine {
+    engine: Engine,
+    last_error: Option<CString>,
+}
+
+/// A value of the language, or, returned by a native callback, the error
+/// it failed with.
+pub struct CompilerValue(Result<Value, String>);
+
+/// What `compiler_value_type` reports.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompilerType {
+    Null,
+    Boolean,
+    Integer,
+    Number,
+    String,
+    /// Lists, objects, functions and anything else C cannot read directly;
+    /// `compiler_value_display` still shows them.
+    Other,
+    Error,
+}
+
+/// A native function written in C. It gets the arguments of the call,
+/// which stay owned by the engine, and `user_data` as it was registered,
+/// and returns a value it allocated, or `NULL` to fail the call. It is
+/// called from the thread that called `compiler_eval`, except in `work`
+/// blocks and generator bodies, which run on threads the engine starts.
+pub type CompilerNative = Option<
+    unsafe extern "C" fn(arguments: *const *const CompilerValue, count: usize, user_data: *mut c_void) -> *mut CompilerValue,
+>;
+
+/// The callback and its data, which C promises may cross threads.
+struct Callback {
+    function: unsafe extern "C" fn(*const *const CompilerValue, usize, *mut c_void) -> *mut CompilerValue,
+    user_data: *mut c_void,
+}
+
+unsafe impl Send for Callback {}
+unsafe impl Sync for Callback {}
+
+impl Callback {
+    fn call(&self, name: &str, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        let arguments: Vec<CompilerValue> = arguments.iter().map(|argument| CompilerValue(Ok(argument.clone()))).collect();
+        let pointers: Vec<*const CompilerValue> = arguments.iter().map(|argument| argument as *const CompilerValue).collect();
+        // SAFETY: the pointers are valid for the duration of the call, and
+        // the result is a value the callback allocated with this API.
+        let result = unsafe { (self.function)(pointers.as_ptr(), pointers.len(), self.user_data) };
+        if result.is_null() {
+            return Err(RuntimeError::TypeError { message: format!("Native function '{}' failed.", name) });
+        }
+        let result = unsafe { Box::from_raw(result) };
+        result.0.map_err(|message| RuntimeError::TypeError { message })
+    }
+}
+
+fn boxed(value: Result<Value, String>) -> *mut CompilerValue {
+    Box::into_raw(Box::new(CompilerValue(value)))
+}
+
+/// Interior NULs cannot cross into C; the text is cut at the first one.
+fn c_string(mut text: String) -> CString {
+    if let Some(end) = text.find('\0') {
+        text.truncate(end);
+    }
+    CString::new(text).unwrap_or_default()
+}
+
+fn owned_string(text: String) -> *mut c_char {
+    c_string(text).into_raw()
+}
+
+/// # Safety
+/// `text` must be `NULL` or a NUL-terminated string.
+unsafe fn borrowed_str<'a>(text: *const c_char) -> Option<&'a str> {
+    if text.is_null() {
+        return None;
+    }
+    CStr::from_ptr(text).to_str().ok()
+}
+
+impl CompilerEngine {
+    fn fail(&mut self, message: String) {
+        self.last_error = Some(c_string(message));
+    }
+}
+
+/// Runs the body of an entry point, which must not unwind into C, and
+/// returns `failed` if it panics.
+fn shielded<T>(failed: T, body: impl FnOnce() -> T) -> T {
+    unwound(body).unwrap_or(failed)
+}
+
+/// Like `shielded`, also keeping the panic as the last error of `engine`.
+///
+/// # Safety
+/// `engine` must be `NULL` or come from `compiler_engine_new`, and not be
+/// borrowed by `body` once it returns.
+unsafe fn guarded<T>(engine: *mut CompilerEngine, failed: T, body: impl FnOnce() -> T) -> T {
+    unwound(body).unwrap_or_else(|message| {
+        if let Some(engine) = engine.as_mut() {
+            engine.fail(format!("internal error: {}", message));
+        }
+        failed
+    })
+}
+
+fn unwound<T>(body: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(body)).map_err(|payload| panic_message(payload.as_ref()).to_string())
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload.downcast_ref::<&str>().copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("the engine panicked")
+}
+
+/// A new engine with the builtins and no natives.
+#[no_mangle]
+pub extern "C" fn compiler_engine_new() -> *mut CompilerEngine {
+    shielded(ptr::null_mut(), || {
+        Box::into_raw(Box::new(CompilerEngine { engine: Engine::new(), last_error: None }))
+    })
+}
+
+/// # Safety
+/// `engine` must be `NULL` or come from `compiler_engine_new`, and is not
+/// used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_engine_free(engine: *mut CompilerEngine) {
+    shielded((), || {
+        if !engine.is_null() {
+            drop(Box::from_raw(engine));
+        }
+    })
+}
+
+/// Runs `source` and returns the value of its last statement, or `NULL` if
+/// it failed; `compiler_last_error` then tells why.
+///
+/// # Safety
+/// `engine` must come from `compiler_engine_new` and `source` be a
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_eval(engine: *mut CompilerEngine, source: *const c_char) -> *mut CompilerValue {
+    guarded(engine, ptr::null_mut(), || {
+        let Some(engine) = engine.as_mut() else {
+            return ptr::null_mut();
+        };
+        engine.last_error = None;
+        let Some(source) = borrowed_str(source) else {
+            engine.fail("source is not valid UTF-8".to_string());
+            return ptr::null_mut();
+        };
+        match engine.engine.eval(source) {
+            Ok(value) => boxed(Ok(value)),
+            Err(error) => {
+                engine.fail(error.to_string());
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// The message of the last failed call on `engine`, or `NULL` if the last
+/// call succeeded. It stays valid until the next call on `engine`.
+///
+/// # Safety
+/// `engine` must come from `compiler_engine_new`.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_last_error(engine: *const CompilerEngine) -> *const c_char {
+    shielded(ptr::null(), || {
+        engine.as_ref()
+            .and_then(|engine| engine.last_error.as_ref())
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Defines the global `name` as a copy of `value`.
+///
+/// # Safety
+/// `engine` must come from `compiler_engine_new`, `name` be a
+/// NUL-terminated string and `value` a value from this API.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_set_global(engine: *mut CompilerEngine, name: *const c_char, value: *const CompilerValue) -> bool {
+    guarded(engine, false, || {
+        let Some(engine) = engine.as_mut() else {
+            return false;
+        };
+        engine.last_error = None;
+        match (borrowed_str(name), value.as_ref()) {
+            (Some(name), Some(CompilerValue(Ok(value)))) => {
+                engine.engine.set_global(name, value.clone());
+                true
+            },
+            _ => {
+                engine.fail("invalid global name or value".to_string());
+                false
+            },
+        }
+    })
+}
+
+/// Caps what each `compiler_eval` may use: steps, nested calls, bytes a
+/// single value may take, bytes the lists and objects of the program may
+/// take together and milliseconds of wall-clock time. `0` leaves a
+/// limit off, except for calls, which always have one: `0` keeps them to
+/// `DEFAULT_CALL_DEPTH`.
+///
+/// # Safety
+/// `engine` must come from `compiler_engine_new`.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_set_limits(engine: *mut CompilerEngine, fuel: u64, call_depth: usize, value_size: usize, heap: usize, timeout_ms: u64) {
+    guarded(engine, (), || {
+        if let Some(engine) = engine.as_mut() {
+            engine.engine.set_limits(Limits {
+                fuel: (fuel > 0).then_some(fuel),
+                call_depth: if call_depth > 0 { call_depth } else { DEFAULT_CALL_DEPTH },
+                value_size: (value_size > 0).then_some(value_size),
+                heap: (heap > 0).then_some(heap),
+                timeout: (timeout_ms > 0).then(|| Duration::from_millis(timeout_ms)),
+            });
+        }
+    })
+}
+
+/// Registers `callback` as `namespace.name`, for scripts to import with
+/// `interop`.
+///
+/// # Safety
+/// `engine` must come from `compiler_engine_new` and `namespace` and
+/// `name` be NUL-terminated strings. `user_data` is passed to `callback`
+/// as is and must stay valid as long as the engine.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_register_native(
+    engine: *mut CompilerEngine,
+    namespace: *const c_char,
+    name: *const c_char,
+    callback: CompilerNative,
+    user_data: *mut c_void,
+) -> bool {
+    guarded(engine, false, || {
+        let Some(engine) = engine.as_mut() else {
+            return false;
+        };
+        engine.last_error = None;
+        let (Some(namespace), Some(name), Some(function)) = (borrowed_str(namespace), borrowed_str(name), callback) else {
+            engine.fail("invalid native namespace, name or callback".to_string());
+            return false;
+        };
+        let callback = Callback { function, user_data };
+        let full_name = format!("{}.{}", namespace, name);
+        engine.engine.register_native(namespace, name, move |arguments| callback.call(&full_name, arguments));
+        true
+    })
+}
+
+/// # Safety
+/// `value` must be `NULL` or a value from this API, and is not used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_value_free(value: *mut CompilerValue) {
+    shielded((), || {
+        if !value.is_null() {
+            drop(Box::from_raw(value));
+        }
+    })
+}
+
+/// # Safety
+/// `value` must be a value from this API.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_value_type(value: *const CompilerValue) -> CompilerType {
+    shielded(CompilerType::Error, || {
+        match value.as_ref() {
+            Some(CompilerValue(Ok(Value::Null))) => CompilerType::Null,
+            Some(CompilerValue(Ok(Value::Boolean(_)))) => CompilerType::Boolean,
+            Some(CompilerValue(Ok(Value::Integer(_)))) => CompilerType::Integer,
+            Some(CompilerValue(Ok(Value::Number(_)))) => CompilerType::Number,
+            Some(CompilerValue(Ok(Value::String(_)))) => CompilerType::String,
+            Some(CompilerValue(Ok(_))) => CompilerType::Other,
+            Some(CompilerValue(Err(_))) | None => CompilerType::Error,
+        }
+    })
+}
+
+/// Stores a boolean value in `out`; `false` for other values.
+///
+/// # Safety
+/// `value` must be a value from this API and `out` point to a `bool`.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_value_bool(value: *const CompilerValue, out: *mut bool) -> bool {
+    shielded(false, || {
+        match (value.as_ref(), out.as_mut()) {
+            (Some(CompilerValue(Ok(Value::Boolean(b)))), Some(out)) => {
+                *out = *b;
+                true
+            },
+            _ => false,
+        }
+    })
+}
+
+/// Stores an integer value in `out`; `false` for other values.
+///
+/// # Safety
+/// `value` must be a value from this API and `out` point to an `int64_t`.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_value_integer(value: *const CompilerValue, out: *mut i64) -> bool {
+    shielded(false, || {
+        match (value.as_ref(), out.as_mut()) {
+            (Some(CompilerValue(Ok(Value::Integer(i)))), Some(out)) => {
+                *out = *i;
+                true
+            },
+            _ => false,
+        }
+    })
+}
+
+/// Stores a number in `out`, integers included; `false` for other values.
+///
+/// # Safety
+/// `value` must be a value from this API and `out` point to a `double`.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_value_number(value: *const CompilerValue, out: *mut f64) -> bool {
+    shielded(false, || {
+        let number = match value.as_ref() {
+            Some(CompilerValue(Ok(Value::Number(n)))) => *n,
+            Some(CompilerValue(Ok(Value::Integer(i)))) => *i as f64,
+            _ => return false,
+        };
+        match out.as_mut() {
+            Some(out) => {
+                *out = number;
+                true
+            },
+            None => false,
+        }
+    })
+}
+
+/// The text of a string value, or `NULL` for other values. Free it with
+/// `compiler_string_free`.
+///
+/// # Safety
+/// `value` must be a value from this API.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_value_string(value: *const CompilerValue) -> *mut c_char {
+    shielded(ptr::null_mut(), || {
+        match value.as_ref() {
+            Some(CompilerValue(Ok(Value::String(text)))) => owned_string(text.clone()),
+            _ => ptr::null_mut(),
+        }
+    })
+}
+
+/// Any value as the REPL prints it. Free it with `compiler_string_free`.
+///
+/// # Safety
+/// `value` must be a value from this API.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_value_display(value: *const CompilerValue) -> *mut c_char {
+    shielded(ptr::null_mut(), || {
+        match value.as_ref() {
+            Some(CompilerValue(Ok(value))) => owned_string(value.to_string()),
+            Some(CompilerValue(Err(message))) => owned_string(message.clone()),
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+/// # Safety
+/// `text` must be `NULL` or a string from this API, and is not used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_string_free(text: *mut c_char) {
+    shielded((), || {
+        if !text.is_null() {
+            drop(CString::from_raw(text));
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn compiler_value_new_null() -> *mut CompilerValue {
+    shielded(ptr::null_mut(), || {
+        boxed(Ok(Value::Null))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn compiler_value_new_bool(b: bool) -> *mut CompilerValue {
+    shielded(ptr::null_mut(), || {
+        boxed(Ok(Value::Boolean(b)))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn compiler_value_new_integer(i: i64) -> *mut CompilerValue {
+    shielded(ptr::null_mut(), || {
+        boxed(Ok(Value::Integer(i)))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn compiler_value_new_number(n: f64) -> *mut CompilerValue {
+    shielded(ptr::null_mut(), || {
+        boxed(Ok(Value::Number(n)))
+    })
+}
+
+/// A copy of `text`, or `NULL` if it is not valid UTF-8.
+///
+/// # Safety
+/// `text` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_value_new_string(text: *const c_char) -> *mut CompilerValue {
+    shielded(ptr::null_mut(), || {
+        borrowed_str(text).map_or(ptr::null_mut(), |text| boxed(Ok(Value::String(text.to_string()))))
+    })
+}
+
+/// For a native callback to return: fails the call with `message`.
+///
+/// # Safety
+/// `message` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn compiler_value_new_error(message: *const c_char) -> *mut CompilerValue {
+    shielded(ptr::null_mut(), || {
+        let message = borrowed_str(message).unwrap_or("native function failed").to_string();
+        boxed(Err(message))
+    })
+}
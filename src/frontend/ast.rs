@@ -1,9 +1,18 @@
+use std::fmt;
+
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum StmtType {
     Program,
     NumericLiteral,
+    IntegerLiteral,
+    RationalLiteral,
+    BooleanLiteral,
+    NullLiteral,
     BinaryExpr,
+    UnaryExpr,
+    CoalesceExpr,
+    MemberExpr,
     Identifier,
     BlockStmt,
     IfStmt,
@@ -11,11 +20,26 @@ pub enum StmtType {
     UntilStmt,
     UnlessStmt,
     Assignment,
+    PostfixUpdate,
     ReturnStmt,
     FunctionDeclaration,
     CallExpression,
     VariableDeclaration,
     FunctionExpression,
+    ListLiteral,
+    IndexExpr,
+    NamedArgument,
+    Spread,
+    ObjectLiteral,
+    Property,
+    ListPattern,
+    ObjectPattern,
+    MemberAssignment,
+    YieldExpr,
+    ForInStmt,
+    AwaitExpr,
+    WorkExpr,
+    InteropExpr,
 }
 
 #[allow(dead_code)]
@@ -25,6 +49,45 @@ pub struct Program {
     pub body: Vec<Stmt>
 }
 
+/// Indented tree dump of the program, one node per line.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:?}", self.kind)?;
+        for stmt in &self.body {
+            write_tree(f, stmt, 1, "")?;
+        }
+        Ok(())
+    }
+}
+
+fn write_tree(f: &mut fmt::Formatter, stmt: &Stmt, depth: usize, label: &str) -> fmt::Result {
+    write!(f, "{}{}{:?}", "  ".repeat(depth), label, stmt.kind)?;
+    if let Some(operator) = &stmt.operator {
+        write!(f, " {}", operator)?;
+    }
+    if let Some(value) = &stmt.value {
+        write!(f, " {}", value)?;
+    }
+    if let Some(slot) = stmt.slot {
+        write!(f, " @{}:{}", slot.depth, slot.index)?;
+    }
+    writeln!(f, " (line {})", stmt.line)?;
+
+    if let Some(left) = &stmt.left {
+        write_tree(f, left, depth + 1, "left: ")?;
+    }
+    if let Some(consequent) = &stmt.consequent {
+        write_tree(f, consequent, depth + 1, "consequent: ")?;
+    }
+    if let Some(right) = &stmt.right {
+        write_tree(f, right, depth + 1, "right: ")?;
+    }
+    for child in stmt.body.iter().flatten() {
+        write_tree(f, child, depth + 1, "")?;
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Stmt {
     pub kind: StmtType,
@@ -33,7 +96,20 @@ pub struct Stmt {
     pub value: Option<String>,
     pub body: Option<Vec<Stmt>>,
     pub consequent: Option<Box<Stmt>>,
-    pub operator: Option<String>
+    pub operator: Option<String>,
+    pub line: usize,
+    /// Filled in by the resolver for identifiers, assignments and
+    /// declarations that refer to a block-local variable. `None` means the
+    /// name lives in the global table.
+    pub slot: Option<Slot>
+}
+
+/// Location of a local variable: how many scopes up from the current one and
+/// its index inside that scope.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -46,15 +122,28 @@ pub enum TokenType {
     GreaterThan,
     GreaterThanEquals,
     Comma,
+    Semicolon,
+    Pipeline,
     OpenParen,
     CloseParen,
     OpenBracket,
     CloseBracket,
+    OpenSquareBracket,
+    CloseSquareBracket,
     Binary,
+    CompoundAssignment,
+    Update,
     Number,
+    Integer,
+    Rational,
     Identifier,
     Eof,
     Dot,
+    Ellipsis,
+    Question,
+    Colon,
+    NullCoalescing,
+    OptionalChain,
     Null,
     String,
     If,
@@ -62,13 +151,19 @@ pub enum TokenType {
     Until,
     Unless,
     While,
+    For,
+    In,
+    Yield,
     Work,
     Interop,
     Return,
     Async,
+    Await,
     Function,
     Break,
     Def,
+    Const,
+    Freeze,
     Import,
     Continue
 }
@@ -76,5 +171,6 @@ pub enum TokenType {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub kind: TokenType,
-    pub value: String
+    pub value: String,
+    pub line: usize
 }
@@ -1,11 +1,36 @@
 use std::iter::Peekable;
 use std::str::Chars;
+use std::fmt;
 use crate::frontend::ast::{Token, TokenType};
 
-fn gen_token(token_type: TokenType, value: String) -> Token {
+#[derive(Debug, Clone)]
+pub enum LexerError {
+    UnrecognizedCharacter { character: char, line: usize },
+    UnterminatedString { line: usize },
+    MalformedNumber { literal: String, reason: String, line: usize },
+}
+
+impl fmt::Display for LexerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexerError::UnrecognizedCharacter { character, line } => {
+                write!(f, "Lexing error (line {}): unrecognized character '{}'", line, character)
+            },
+            LexerError::UnterminatedString { line } => {
+                write!(f, "Lexing error (line {}): unterminated string literal", line)
+            },
+            LexerError::MalformedNumber { literal, reason, line } => {
+                write!(f, "Lexing error (line {}): malformed number '{}': {}", line, literal, reason)
+            },
+        }
+    }
+}
+
+fn gen_token(token_type: TokenType, value: String, line: usize) -> Token {
     Token {
         kind: token_type,
-        value
+        value,
+        line
     }
 }
 
@@ -29,16 +54,129 @@ fn is_whitespace(c: char) -> bool {
     c.is_ascii_whitespace()
 }
 
-fn build_number(src: &mut Peekable<Chars>, first_char: char) -> String {
-    let mut number_str = first_char.to_string();
-    number_str.push_str(consume_while(src, is_digit).as_str());
+/// Reads a numeric literal starting at `first_char` (a digit, or the `.` of
+/// a leading-dot decimal such as `.5`).
+///
+/// Decimal literals without a fraction or exponent are integers, `0x`, `0o`
+/// and `0b` introduce integers in other bases, and a `d` suffix (`0.1d`)
+/// makes a decimal literal an exact rational. Digits may be grouped with
+/// single underscores (`1_000_000`), which are dropped from the token.
+fn build_number(src: &mut Peekable<Chars>, first_char: char, line: usize) -> Result<(TokenType, String), LexerError> {
+    let malformed = |literal: &str, reason: &str| LexerError::MalformedNumber {
+        literal: literal.to_string(),
+        reason: reason.to_string(),
+        line,
+    };
+
+    if first_char == '0' {
+        let radix = match src.peek() {
+            Some('x') | Some('X') => Some((16, "hexadecimal")),
+            Some('o') | Some('O') => Some((8, "octal")),
+            Some('b') | Some('B') => Some((2, "binary")),
+            _ => None,
+        };
+        if let Some((radix, name)) = radix {
+            let prefix = src.next().unwrap_or_default().to_ascii_lowercase();
+            let literal = format!("0{}", prefix);
+            let digits = read_digits(src, String::new(), |c| c.is_digit(radix))
+                .map_err(|reason| malformed(&literal, &reason))?;
+            let literal = format!("{}{}", literal, digits);
+            if digits.is_empty() {
+                return Err(malformed(&literal, &format!("expected {} digits after '{}'", name, literal)));
+            }
+            if let Some(&c) = src.peek().filter(|&&c| is_alphanumeric(c)) {
+                return Err(malformed(&literal, &format!("invalid digit '{}' in {} literal", c, name)));
+            }
+            return Ok((TokenType::Integer, literal));
+        }
+    }
+
+    let mut kind = TokenType::Integer;
+    let mut number_str = if first_char == '.' {
+        "0".to_string()
+    } else {
+        read_digits(src, first_char.to_string(), is_digit).map_err(|reason| malformed(&first_char.to_string(), &reason))?
+    };
+
+    let has_fraction = first_char == '.' || (src.peek() == Some(&'.') && {
+        let mut lookahead = src.clone();
+        lookahead.next();
+        lookahead.peek().is_some_and(|&c| is_digit(c))
+    });
+    if has_fraction {
+        if first_char != '.' {
+            src.next();
+        }
+        let fraction = read_digits(src, String::new(), is_digit).map_err(|reason| malformed(&number_str, &reason))?;
+        number_str = format!("{}.{}", number_str, fraction);
+        kind = TokenType::Number;
+    }
 
-    if let Some(&'.') = src.peek() {
+    if let Some('e') | Some('E') = src.peek() {
         src.next();
-        number_str.push('.');
-        number_str.push_str(consume_while(src, is_digit).as_str());
+        number_str.push('e');
+        if let Some(&sign) = src.peek().filter(|&&c| c == '+' || c == '-') {
+            src.next();
+            number_str.push(sign);
+        }
+        let exponent = read_digits(src, String::new(), is_digit).map_err(|reason| malformed(&number_str, &reason))?;
+        if exponent.is_empty() {
+            return Err(malformed(&number_str, "expected digits in the exponent"));
+        }
+        number_str.push_str(&exponent);
+        kind = TokenType::Number;
+    } else if let Some(&'d') = src.peek() {
+        let mut lookahead = src.clone();
+        lookahead.next();
+        if !lookahead.peek().is_some_and(|&c| is_alphanumeric(c) || c == '_') {
+            src.next();
+            kind = TokenType::Rational;
+        }
+    }
+
+    if let Some(&c) = src.peek().filter(|&&c| is_alphanumeric(c) || c == '_') {
+        return Err(malformed(&number_str, &format!("unexpected '{}' after the number", c)));
+    }
+    Ok((kind, number_str))
+}
+
+/// Appends to `digits` a run of digits accepted by `is_valid_digit`,
+/// allowing single underscores between digits. The underscores are not part
+/// of the result.
+fn read_digits<F>(src: &mut Peekable<Chars>, mut digits: String, is_valid_digit: F) -> Result<String, String>
+where
+    F: Fn(char) -> bool,
+{
+    let mut after_separator = false;
+    while let Some(&c) = src.peek() {
+        if c == '_' {
+            if digits.is_empty() || after_separator {
+                return Err("digit separators must sit between digits".to_string());
+            }
+            after_separator = true;
+        } else if is_valid_digit(c) {
+            digits.push(c);
+            after_separator = false;
+        } else {
+            break;
+        }
+        src.next();
+    }
+    if after_separator {
+        return Err("digit separators must sit between digits".to_string());
+    }
+    Ok(digits)
+}
+
+/// Reads the rest of an operator that may be followed by `=` to form a
+/// compound assignment (`+=`, `**=`, `<<=`, ...).
+fn binary_or_compound(src: &mut Peekable<Chars>, operator: &str, line: usize) -> Token {
+    if let Some(&'=') = src.peek() {
+        src.next();
+        gen_token(TokenType::CompoundAssignment, format!("{}=", operator), line)
+    } else {
+        gen_token(TokenType::Binary, operator.to_string(), line)
     }
-    number_str
 }
 
 fn build_identifier(src: &mut Peekable<Chars>, first_char: char) -> String {
@@ -47,85 +185,158 @@ fn build_identifier(src: &mut Peekable<Chars>, first_char: char) -> String {
     identifier_str
 }
 
-fn next_token(src: &mut Peekable<Chars>) -> Option<Token> {
-    let current_char = src.next()?;
+fn next_token(src: &mut Peekable<Chars>, line: &mut usize) -> Result<Option<Token>, LexerError> {
+    let current_char = match src.next() {
+        Some(c) => c,
+        None => return Ok(None),
+    };
     
-    match current_char {
-        '*' | '/' | '-' | '+' | '%' => Some(gen_token(TokenType::Binary, current_char.to_string())),
-        '(' => Some(gen_token(TokenType::OpenParen, current_char.to_string())),
-        ')' => Some(gen_token(TokenType::CloseParen, current_char.to_string())),
-        '{' => Some(gen_token(TokenType::OpenBracket, current_char.to_string())),
-        '}' => Some(gen_token(TokenType::CloseBracket, current_char.to_string())),
-        '.' => Some(gen_token(TokenType::Dot, current_char.to_string())),
-        ';' => Some(gen_token(TokenType::Comma, current_char.to_string())),
+    let token = match current_char {
+        '/' | '*' => {
+            if src.peek() == Some(&current_char) {
+                src.next();
+                binary_or_compound(src, &current_char.to_string().repeat(2), *line)
+            } else {
+                binary_or_compound(src, &current_char.to_string(), *line)
+            }
+        },
+        '+' | '-' => {
+            if src.peek() == Some(&current_char) {
+                src.next();
+                gen_token(TokenType::Update, current_char.to_string().repeat(2), *line)
+            } else {
+                binary_or_compound(src, &current_char.to_string(), *line)
+            }
+        },
+        '|' if src.peek() == Some(&'>') => {
+            src.next();
+            gen_token(TokenType::Pipeline, "|>".to_string(), *line)
+        },
+        '%' | '&' | '|' | '^' => binary_or_compound(src, &current_char.to_string(), *line),
+        '~' => gen_token(TokenType::Binary, current_char.to_string(), *line),
+        '(' => gen_token(TokenType::OpenParen, current_char.to_string(), *line),
+        ')' => gen_token(TokenType::CloseParen, current_char.to_string(), *line),
+        '{' => gen_token(TokenType::OpenBracket, current_char.to_string(), *line),
+        '}' => gen_token(TokenType::CloseBracket, current_char.to_string(), *line),
+        '[' => gen_token(TokenType::OpenSquareBracket, current_char.to_string(), *line),
+        ']' => gen_token(TokenType::CloseSquareBracket, current_char.to_string(), *line),
+        '.' if src.peek() == Some(&'.') => {
+            src.next();
+            if src.next_if_eq(&'.').is_none() {
+                return Err(LexerError::UnrecognizedCharacter { character: '.', line: *line });
+            }
+            gen_token(TokenType::Ellipsis, "...".to_string(), *line)
+        },
+        '.' if src.peek().is_some_and(|&c| is_digit(c)) => {
+            let (kind, number_str) = build_number(src, current_char, *line)?;
+            gen_token(kind, number_str, *line)
+        },
+        '.' => gen_token(TokenType::Dot, current_char.to_string(), *line),
+        ',' => gen_token(TokenType::Comma, current_char.to_string(), *line),
+        ';' => gen_token(TokenType::Semicolon, current_char.to_string(), *line),
+        ':' => gen_token(TokenType::Colon, current_char.to_string(), *line),
+        '?' => {
+            let mut lookahead = src.clone();
+            match (lookahead.next(), lookahead.next()) {
+                (Some('?'), _) => {
+                    src.next();
+                    gen_token(TokenType::NullCoalescing, "??".to_string(), *line)
+                },
+                // `a ? .5 : b` is a conditional, not an optional chain.
+                (Some('.'), next) if !next.is_some_and(is_digit) => {
+                    src.next();
+                    gen_token(TokenType::OptionalChain, "?.".to_string(), *line)
+                },
+                _ => gen_token(TokenType::Question, current_char.to_string(), *line),
+            }
+        },
         '"' => {
             let literal = consume_while(src, |c| c != '"');
+            let start_line = *line;
+            *line += literal.matches('\n').count();
             if src.next().is_none() {
-                panic!("Unterminated string literal");
+                return Err(LexerError::UnterminatedString { line: start_line });
             }
-            Some(gen_token(TokenType::String, literal))
+            gen_token(TokenType::String, literal, start_line)
         },
         '=' => {
             if let Some(&'=') = src.peek() {
                 src.next();
-                Some(gen_token(TokenType::EqualsEquals, "==".to_string()))
+                gen_token(TokenType::EqualsEquals, "==".to_string(), *line)
             } else {
-                Some(gen_token(TokenType::Equals, "=".to_string())) // 'single equals'
+                gen_token(TokenType::Equals, "=".to_string(), *line) // 'single equals'
             }
         },
         '!' => {
             if let Some(&'=') = src.peek() {
                 src.next();
-                Some(gen_token(TokenType::NotEquals, "!=".to_string()))
+                gen_token(TokenType::NotEquals, "!=".to_string(), *line)
             } else {
-                panic!("Unrecognized character: '!' (expected !=)");
+                return Err(LexerError::UnrecognizedCharacter { character: '!', line: *line });
             }
         },
         '<' => {
             if let Some(&'=') = src.peek() {
                 src.next();
-                Some(gen_token(TokenType::LessThanEquals, "<=".to_string()))
+                gen_token(TokenType::LessThanEquals, "<=".to_string(), *line)
+            } else if let Some(&'<') = src.peek() {
+                src.next();
+                binary_or_compound(src, "<<", *line)
             } else {
-                Some(gen_token(TokenType::LessThan, "<".to_string()))
+                gen_token(TokenType::LessThan, "<".to_string(), *line)
             }
         },
         '>' => {
             if let Some(&'=') = src.peek() {
                 src.next();
-                Some(gen_token(TokenType::GreaterThanEquals, ">=".to_string()))
+                gen_token(TokenType::GreaterThanEquals, ">=".to_string(), *line)
+            } else if let Some(&'>') = src.peek() {
+                src.next();
+                binary_or_compound(src, ">>", *line)
             } else {
-                Some(gen_token(TokenType::GreaterThan, ">".to_string()))
+                gen_token(TokenType::GreaterThan, ">".to_string(), *line)
             }
         },
         _ if is_digit(current_char) => {
-            Some(gen_token(TokenType::Number, build_number(src, current_char)))
+            let (kind, number_str) = build_number(src, current_char, *line)?;
+            gen_token(kind, number_str, *line)
         }
         _ if is_alpha(current_char) => {
             let identifier_str = build_identifier(src, current_char);
             match identifier_str.as_str() {
-                "null" => Some(gen_token(TokenType::Null, identifier_str)),
-                "if" => Some(gen_token(TokenType::If, identifier_str)),
-                "else" => Some(gen_token(TokenType::Else, identifier_str)),
-                "until" => Some(gen_token(TokenType::Until, identifier_str)),
-                "unless" => Some(gen_token(TokenType::Unless, identifier_str)),
-                "while" => Some(gen_token(TokenType::While, identifier_str)),
-                "work" => Some(gen_token(TokenType::Work, identifier_str)),
-                "interop" => Some(gen_token(TokenType::Interop, identifier_str)),
-                "return" => Some(gen_token(TokenType::Return, identifier_str)),
-                "continue" => Some(gen_token(TokenType::Continue, identifier_str)),
-                "break" => Some(gen_token(TokenType::Break, identifier_str)),
-                "def" => Some(gen_token(TokenType::Def, identifier_str)),
-                "fn" => Some(gen_token(TokenType::Function, identifier_str)),
-                "async" => Some(gen_token(TokenType::Async, identifier_str)),
-                "import" => Some(gen_token(TokenType::Import, identifier_str)),
-                _ => Some(gen_token(TokenType::Identifier, identifier_str)),
+                "null" => gen_token(TokenType::Null, identifier_str, *line),
+                "if" => gen_token(TokenType::If, identifier_str, *line),
+                "else" => gen_token(TokenType::Else, identifier_str, *line),
+                "until" => gen_token(TokenType::Until, identifier_str, *line),
+                "unless" => gen_token(TokenType::Unless, identifier_str, *line),
+                "while" => gen_token(TokenType::While, identifier_str, *line),
+                "for" => gen_token(TokenType::For, identifier_str, *line),
+                "in" => gen_token(TokenType::In, identifier_str, *line),
+                "yield" => gen_token(TokenType::Yield, identifier_str, *line),
+                "work" => gen_token(TokenType::Work, identifier_str, *line),
+                "interop" => gen_token(TokenType::Interop, identifier_str, *line),
+                "return" => gen_token(TokenType::Return, identifier_str, *line),
+                "continue" => gen_token(TokenType::Continue, identifier_str, *line),
+                "break" => gen_token(TokenType::Break, identifier_str, *line),
+                "def" => gen_token(TokenType::Def, identifier_str, *line),
+                "const" => gen_token(TokenType::Const, identifier_str, *line),
+                "freeze" => gen_token(TokenType::Freeze, identifier_str, *line),
+                "fn" => gen_token(TokenType::Function, identifier_str, *line),
+                "async" => gen_token(TokenType::Async, identifier_str, *line),
+                "await" => gen_token(TokenType::Await, identifier_str, *line),
+                "import" => gen_token(TokenType::Import, identifier_str, *line),
+                _ => gen_token(TokenType::Identifier, identifier_str, *line),
             }
         }
         _ if is_eof(current_char) || is_whitespace(current_char) => {
-            next_token(src)
+            if current_char == '\n' {
+                *line += 1;
+            }
+            return next_token(src, line);
         }
-        _ => panic!("Unrecognized character: '{}'", current_char),
-    }
+        _ => return Err(LexerError::UnrecognizedCharacter { character: current_char, line: *line }),
+    };
+    Ok(Some(token))
 }
 
 fn consume_while<F>(src: &mut Peekable<Chars>, test: F) -> String
@@ -143,14 +354,15 @@ where
     result
 }
 
-pub fn tokenize(source_code: &str) -> Vec<Token> {
+pub fn tokenize(source_code: &str) -> Result<Vec<Token>, LexerError> {
     let mut src: Peekable<Chars> = source_code.chars().peekable();
 
     let mut tokens = Vec::new();
+    let mut line = 1;
 
-    while let Some(token) = next_token(&mut src) {
+    while let Some(token) = next_token(&mut src, &mut line)? {
         tokens.push(token);
     }
 
-    tokens
+    Ok(tokens)
 }
\ No newline at end of file
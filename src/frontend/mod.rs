@@ -1,3 +1,3 @@
 pub mod lexer;
 pub mod ast;
-pub mod parser;
\ No newline at end of file
+pub mod parser;pub mod resolver;
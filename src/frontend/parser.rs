@@ -2,7 +2,7 @@ use crate::frontend::ast::{Stmt, StmtType};
 
 use super::{ast::{Program, Token, TokenType}};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ParserError {
     UnexpectedToken { expected: String, found: Token },
     MissingExpression,
@@ -29,12 +29,12 @@ impl From<std::io::Error> for ParserError {
     }
 }
 
-fn not_eof(tokens: &mut Vec<Token>) -> bool {
-    tokens.get(0).map_or(false, |token| token.kind != TokenType::Eof)
+fn not_eof(tokens: &[Token]) -> bool {
+    tokens.first().is_some_and(|token| token.kind != TokenType::Eof)
 }
 
-fn first(tokens: &mut Vec<Token>) -> Result<Token, ParserError> {
-    tokens.get(0)
+fn first(tokens: &[Token]) -> Result<Token, ParserError> {
+    tokens.first()
         .cloned()
         .ok_or(ParserError::EndOfFileUnexpected)
 }
@@ -69,7 +69,7 @@ pub fn produce_ast(tokens: &mut Vec<Token>) -> Result<Program, ParserError> {
 }
 
 fn parse_block_stmt(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
-    expect(tokens, TokenType::OpenBracket)?;
+    let open = expect(tokens, TokenType::OpenBracket)?;
 
     let mut body_stmts = Vec::new();
     while not_eof(tokens) && first(tokens)?.kind != TokenType::CloseBracket {
@@ -86,14 +86,16 @@ fn parse_block_stmt(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
         operator: None,
         value: None,
         consequent: None,
+        line: open.line,
+        slot: None,
     })
 }
 
 fn parse_if_stmt(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
-    expect(tokens, TokenType::If)?;
+    let if_token = expect(tokens, TokenType::If)?;
     expect(tokens, TokenType::OpenParen)?;
 
-    let condition = parse_comparison_expr(tokens)?;
+    let condition = parse_expr(tokens)?;
     expect(tokens, TokenType::CloseParen)?;
 
     let consequent = parse_block_stmt(tokens)?;
@@ -124,14 +126,16 @@ fn parse_if_stmt(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
         body: None,
         operator: None,
         value: None,
+        line: if_token.line,
+        slot: None,
     })
 }
 
 fn parse_while_stmt(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
-    expect(tokens, TokenType::While)?;
+    let while_token = expect(tokens, TokenType::While)?;
     expect(tokens, TokenType::OpenParen)?;
 
-    let condition = parse_comparison_expr(tokens)?;
+    let condition = parse_expr(tokens)?;
     expect(tokens, TokenType::CloseParen)?;
 
     let consequent = parse_block_stmt(tokens)?;
@@ -144,6 +148,38 @@ fn parse_while_stmt(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
         body: None,
         operator: None,
         value: None,
+        line: while_token.line,
+        slot: None,
+    })
+}
+
+/// `for (name in iterable) { ... }` runs the block once per item of a list
+/// or per value a generator yields. The loop variable, or a pattern in
+/// `left`, is declared in a scope of its own for each iteration.
+fn parse_for_in_stmt(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let for_token = expect(tokens, TokenType::For)?;
+    expect(tokens, TokenType::OpenParen)?;
+
+    let (name, pattern) = match first(tokens)?.kind {
+        TokenType::OpenSquareBracket | TokenType::OpenBracket => (None, Some(Box::new(parse_pattern(tokens)?))),
+        _ => (Some(expect(tokens, TokenType::Identifier)?.value), None),
+    };
+    expect(tokens, TokenType::In)?;
+    let iterable = parse_expr(tokens)?;
+    expect(tokens, TokenType::CloseParen)?;
+
+    let consequent = parse_block_stmt(tokens)?;
+
+    Ok(Stmt {
+        kind: StmtType::ForInStmt,
+        value: name,
+        left: pattern,
+        right: Some(Box::new(iterable)),
+        consequent: Some(Box::new(consequent)),
+        body: None,
+        operator: None,
+        line: for_token.line,
+        slot: None,
     })
 }
 
@@ -153,12 +189,659 @@ fn parse_stmt(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
     match current_token_kind {
         TokenType::If => parse_if_stmt(tokens),
         TokenType::While => parse_while_stmt(tokens),
-        _ => parse_comparison_expr(tokens),
+        TokenType::For => parse_for_in_stmt(tokens),
+        TokenType::Def | TokenType::Const => parse_variable_declaration(tokens),
+        TokenType::Interop => parse_interop_declaration(tokens),
+        TokenType::Function | TokenType::Async if is_function_declaration(tokens) => parse_function(tokens),
+        TokenType::Return => parse_return_stmt(tokens),
+        _ => parse_expr(tokens),
+    }
+}
+
+/// `fn name`, `fn* name` or `async fn name`, as opposed to a function
+/// expression.
+fn is_function_declaration(tokens: &[Token]) -> bool {
+    let tokens = match tokens.first() {
+        Some(token) if token.kind == TokenType::Async => &tokens[1..],
+        _ => tokens,
+    };
+    let name = match tokens.get(1) {
+        Some(token) if is_generator_star(token) => tokens.get(2),
+        token => token,
+    };
+    name.is_some_and(|token| token.kind == TokenType::Identifier)
+}
+
+fn is_generator_star(token: &Token) -> bool {
+    token.kind == TokenType::Binary && token.value == "*"
+}
+
+/// `fn name(a, b) { ... }` declares `name` in the current scope; without a
+/// name, `fn (a, b) { ... }` is an expression. Parameters are kept as
+/// identifier nodes in `body` and the block in `consequent`. A parameter's
+/// default value is in its `right`, and the rest parameter, `...xs`, which
+/// has to come last, has the operator `...`. A parameter can also be a
+/// pattern, taken apart as the body starts. `fn*` declares a generator and
+/// `async fn` an async function; the operator of the function node is then
+/// `*` or `async`.
+fn parse_function(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let async_token = match first(tokens)?.kind {
+        TokenType::Async => Some(consume(tokens)?),
+        _ => None,
+    };
+    let fn_token = expect(tokens, TokenType::Function)?;
+    let generator = match first(tokens) {
+        Ok(token) if is_generator_star(&token) => Some(consume(tokens)?.value),
+        _ => None,
+    };
+    let marker = match (async_token, generator) {
+        (Some(_), Some(_)) => return Err(ParserError::Custom(format!("async generators are not supported (line {})", fn_token.line))),
+        (Some(async_token), None) => Some(async_token.value),
+        (None, generator) => generator,
+    };
+    let name = match first(tokens)?.kind {
+        TokenType::Identifier => Some(consume(tokens)?.value),
+        _ => None,
+    };
+
+    expect(tokens, TokenType::OpenParen)?;
+    let (params, unpacking) = parse_params(tokens)?;
+    expect(tokens, TokenType::CloseParen)?;
+
+    let mut body = parse_block_stmt(tokens)?;
+    if !unpacking.is_empty() {
+        let statements = body.body.get_or_insert_with(Vec::new);
+        if statements.is_empty() {
+            statements.push(null_literal(body.line));
+        }
+        statements.splice(0..0, unpacking);
+    }
+
+    Ok(Stmt {
+        kind: if name.is_some() { StmtType::FunctionDeclaration } else { StmtType::FunctionExpression },
+        value: name,
+        body: Some(params),
+        consequent: Some(Box::new(body)),
+        left: None,
+        right: None,
+        operator: marker,
+        line: fn_token.line,
+        slot: None,
+    })
+}
+
+/// `work { ... }`: the block, in `right`, is kept as a function expression
+/// named `work` without parameters, which runs on a worker thread. Its
+/// operator is `work`, since unlike other functions it may `await`.
+fn parse_work_expr(tokens: &mut Vec<Token>, work_token: Token) -> Result<Stmt, ParserError> {
+    let body = parse_block_stmt(tokens)?;
+    let function = Stmt {
+        kind: StmtType::FunctionExpression,
+        value: Some(work_token.value.clone()),
+        body: Some(vec![]),
+        consequent: Some(Box::new(body)),
+        left: None,
+        right: None,
+        operator: Some(work_token.value),
+        line: work_token.line,
+        slot: None,
+    };
+    Ok(Stmt {
+        kind: StmtType::WorkExpr,
+        left: None,
+        right: Some(Box::new(function)),
+        value: None,
+        body: None,
+        consequent: None,
+        operator: None,
+        line: work_token.line,
+        slot: None,
+    })
+}
+
+/// Parameters up to `)`, each with an optional default and the last one
+/// possibly a rest name. A parameter may also be a pattern, `fn f([a, b])`:
+/// it is then passed under the text of the pattern, which no argument can
+/// name, and taken apart by a `def` returned along with the parameters, to
+/// run first in the body.
+fn parse_params(tokens: &mut Vec<Token>) -> Result<(Vec<Stmt>, Vec<Stmt>), ParserError> {
+    let mut params = Vec::new();
+    let mut unpacking = Vec::new();
+    for element in parse_elements(tokens, StmtType::ListPattern, TokenType::CloseParen)? {
+        if element.kind == StmtType::Identifier {
+            params.push(element);
+            continue;
+        }
+        let mut pattern = element;
+        let line = pattern.line;
+        let name = pattern_text(&pattern);
+        params.push(Stmt {
+            kind: StmtType::Identifier,
+            left: None,
+            right: pattern.right.take(),
+            value: Some(name.clone()),
+            operator: None,
+            consequent: None,
+            body: None,
+            line,
+            slot: None,
+        });
+        unpacking.push(Stmt {
+            kind: StmtType::VariableDeclaration,
+            value: None,
+            right: Some(Box::new(identifier(name, line))),
+            left: Some(Box::new(pattern)),
+            body: None,
+            operator: None,
+            consequent: None,
+            line,
+            slot: None,
+        });
+    }
+    Ok((params, unpacking))
+}
+
+/// `[a, b = 1, ...rest]` or `{ x, y = 0 }` after `def`. Patterns nest, so
+/// an item can be taken apart in turn, `[[a, b], c]`, and so can a field,
+/// `{ position: [x, y] }`.
+fn parse_pattern(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let open = consume(tokens)?;
+    let (kind, close) = match open.kind {
+        TokenType::OpenSquareBracket => (StmtType::ListPattern, TokenType::CloseSquareBracket),
+        TokenType::OpenBracket => (StmtType::ObjectPattern, TokenType::CloseBracket),
+        _ => return Err(ParserError::UnexpectedToken { expected: "identifier, '[' or '{'".to_string(), found: open }),
+    };
+    let elements = parse_elements(tokens, kind.clone(), close.clone())?;
+    expect(tokens, close)?;
+
+    Ok(Stmt {
+        kind,
+        line: open.line,
+        body: Some(elements),
+        left: None,
+        right: None,
+        value: None,
+        operator: None,
+        consequent: None,
+        slot: None,
+    })
+}
+
+/// Elements of a pattern, or parameters, up to `close`. A name is kept as
+/// an identifier node and a nested pattern as a pattern node, whose `value`
+/// is the field it takes apart in an object pattern. Either keeps its
+/// default in `right`; the rest name, which has to come last, has the
+/// operator `...`.
+fn parse_elements(tokens: &mut Vec<Token>, kind: StmtType, close: TokenType) -> Result<Vec<Stmt>, ParserError> {
+    let mut elements = Vec::new();
+    while first(tokens)?.kind != close {
+        let rest = match first(tokens)?.kind {
+            TokenType::Ellipsis => Some(consume(tokens)?.value),
+            _ => None,
+        };
+        let starts_pattern = matches!(first(tokens)?.kind, TokenType::OpenSquareBracket | TokenType::OpenBracket);
+        let mut element = match (&rest, &kind) {
+            (None, StmtType::ListPattern) if starts_pattern => parse_pattern(tokens)?,
+            _ => {
+                let name = expect(tokens, TokenType::Identifier)?;
+                match first(tokens)?.kind {
+                    TokenType::Colon if rest.is_none() && kind == StmtType::ObjectPattern => {
+                        consume(tokens)?;
+                        let mut pattern = parse_pattern(tokens)?;
+                        pattern.value = Some(name.value);
+                        pattern
+                    },
+                    _ => identifier(name.value, name.line),
+                }
+            },
+        };
+        if rest.is_none() && first(tokens)?.kind == TokenType::Equals {
+            consume(tokens)?;
+            element.right = Some(Box::new(parse_expr(tokens)?));
+        }
+        let is_rest = rest.is_some();
+        element.operator = rest;
+        elements.push(element);
+        if first(tokens)?.kind != TokenType::Comma {
+            break;
+        }
+        let comma = consume(tokens)?;
+        if is_rest {
+            return Err(ParserError::UnexpectedToken { expected: format!("{:?} after the rest name", close), found: comma });
+        }
+    }
+    Ok(elements)
+}
+
+/// How a pattern reads, as the name of the parameter it takes apart.
+fn pattern_text(pattern: &Stmt) -> String {
+    let elements: Vec<String> = pattern.body.iter().flatten()
+        .map(|element| {
+            let text = match (&element.kind, &element.value) {
+                (StmtType::Identifier, Some(name)) => name.clone(),
+                (_, Some(field)) => format!("{}: {}", field, pattern_text(element)),
+                (_, None) => pattern_text(element),
+            };
+            match (&element.operator, &element.right) {
+                (Some(rest), _) => format!("{}{}", rest, text),
+                (None, Some(_)) => format!("{}?", text),
+                (None, None) => text,
+            }
+        })
+        .collect();
+    match pattern.kind {
+        StmtType::ObjectPattern => format!("{{{}}}", elements.join(", ")),
+        _ => format!("[{}]", elements.join(", ")),
+    }
+}
+
+fn identifier(name: String, line: usize) -> Stmt {
+    Stmt {
+        kind: StmtType::Identifier,
+        left: None,
+        right: None,
+        value: Some(name),
+        operator: None,
+        consequent: None,
+        body: None,
+        line,
+        slot: None,
+    }
+}
+
+/// Reads a list or object literal on the left of `=` as a pattern:
+/// `[a, b] = [b, a]`. Items have to be names or nested list and object
+/// literals, optionally with a default (`a = 1`), and a list may end with
+/// `...rest`. A field is either `x` or a nested pattern, `position: [x, y]`.
+fn into_pattern(target: Stmt) -> Result<Stmt, ParserError> {
+    let kind = match target.kind {
+        StmtType::ListLiteral => StmtType::ListPattern,
+        StmtType::ObjectLiteral => StmtType::ObjectPattern,
+        _ => return Err(ParserError::Custom(format!("cannot assign to {:?} (line {})", target.kind, target.line))),
+    };
+
+    let items = target.body.unwrap_or_default();
+    let count = items.len();
+    let mut elements = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        let element = match item.kind {
+            StmtType::Spread if index + 1 == count && item.right.as_ref().is_some_and(|name| name.kind == StmtType::Identifier) => {
+                let mut rest = item.right.map(|name| *name).unwrap_or_else(|| identifier(String::new(), item.line));
+                rest.operator = Some("...".to_string());
+                rest
+            },
+            StmtType::Property if item.right.as_ref().is_some_and(|value| value.kind == StmtType::Identifier && value.value == item.value) => {
+                identifier(item.value.unwrap_or_default(), item.line)
+            },
+            StmtType::Property => {
+                let (field, line) = (item.value, item.line);
+                let mut element = item.right.map(|value| into_element(*value)).transpose()?.unwrap_or_else(|| identifier(String::new(), line));
+                if element.kind == StmtType::Identifier {
+                    return Err(ParserError::Custom(format!("invalid destructuring target {:?} (line {})", StmtType::Property, line)));
+                }
+                element.value = field;
+                element
+            },
+            _ => into_element(item)?,
+        };
+        elements.push(element);
+    }
+
+    Ok(Stmt {
+        kind,
+        line: target.line,
+        body: Some(elements),
+        left: None,
+        right: None,
+        value: None,
+        operator: None,
+        consequent: None,
+        slot: None,
+    })
+}
+
+/// One name or nested pattern of a pattern read from a literal, with its
+/// default if it is assigned one.
+fn into_element(item: Stmt) -> Result<Stmt, ParserError> {
+    match item.kind {
+        StmtType::Identifier => Ok(identifier(item.value.unwrap_or_default(), item.line)),
+        StmtType::ListLiteral | StmtType::ObjectLiteral => into_pattern(item),
+        StmtType::Assignment => {
+            let mut element = match item.left {
+                Some(pattern) => *pattern,
+                None => identifier(item.value.unwrap_or_default(), item.line),
+            };
+            element.right = item.right;
+            Ok(element)
+        },
+        _ => Err(ParserError::Custom(format!("invalid destructuring target {:?} (line {})", item.kind, item.line))),
+    }
+}
+
+/// `return` takes a value unless it is the last thing in its block.
+fn parse_return_stmt(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let return_token = expect(tokens, TokenType::Return)?;
+
+    let value = if not_eof(tokens) && first(tokens)?.kind != TokenType::CloseBracket {
+        Some(Box::new(parse_expr(tokens)?))
+    } else {
+        None
+    };
+
+    Ok(Stmt {
+        kind: StmtType::ReturnStmt,
+        right: value,
+        left: None,
+        body: None,
+        value: None,
+        operator: None,
+        consequent: None,
+        line: return_token.line,
+        slot: None,
+    })
+}
+
+/// `def name = value`, or `def [a, b] = value` and `def { x, y } = value`
+/// to declare several variables from the parts of a list or object. The
+/// pattern, if any, is in `left`. With `const` instead of `def` the
+/// variables cannot be assigned again; the operator is then `const`.
+fn parse_variable_declaration(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let def_token = consume(tokens)?;
+    let constant = match def_token.kind {
+        TokenType::Def => None,
+        TokenType::Const => Some(def_token.value.clone()),
+        _ => return Err(ParserError::UnexpectedToken { expected: "'def' or 'const'".to_string(), found: def_token }),
+    };
+    let (name, pattern) = match first(tokens)?.kind {
+        TokenType::OpenSquareBracket | TokenType::OpenBracket => (None, Some(Box::new(parse_pattern(tokens)?))),
+        _ => (Some(expect(tokens, TokenType::Identifier)?.value), None),
+    };
+    expect(tokens, TokenType::Equals)?;
+
+    let initializer = parse_expr(tokens)?;
+
+    Ok(Stmt {
+        kind: StmtType::VariableDeclaration,
+        value: name,
+        right: Some(Box::new(initializer)),
+        left: pattern,
+        body: None,
+        operator: constant,
+        consequent: None,
+        line: def_token.line,
+        slot: None,
+    })
+}
+
+/// `interop math` declares the constant `math`, an object with the native
+/// functions the host registered under that namespace, and
+/// `interop math { sqrt, max }` declares only the constants it names. Both
+/// are kept as a `const` declaration of an `InteropExpr`, whose value is
+/// the namespace.
+fn parse_interop_declaration(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let interop_token = expect(tokens, TokenType::Interop)?;
+    let namespace = expect(tokens, TokenType::Identifier)?.value;
+    let (name, pattern) = match first(tokens) {
+        Ok(token) if token.kind == TokenType::OpenBracket => (None, Some(Box::new(parse_pattern(tokens)?))),
+        _ => (Some(namespace.clone()), None),
+    };
+    let import = Stmt {
+        kind: StmtType::InteropExpr,
+        value: Some(namespace),
+        left: None,
+        right: None,
+        body: None,
+        consequent: None,
+        operator: None,
+        line: interop_token.line,
+        slot: None,
+    };
+
+    Ok(Stmt {
+        kind: StmtType::VariableDeclaration,
+        value: name,
+        right: Some(Box::new(import)),
+        left: pattern,
+        body: None,
+        operator: Some("const".to_string()),
+        consequent: None,
+        line: interop_token.line,
+        slot: None,
+    })
+}
+
+fn parse_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    parse_assignment_expr(tokens)
+}
+
+/// `=` assigns to a variable, to the names of a pattern, or to a field or
+/// item of an object or list: `p.x = 1`, `xs[0] = 1`.
+fn parse_assignment_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    if first(tokens).is_ok_and(|token| token.kind == TokenType::Yield) {
+        return parse_yield_expr(tokens);
+    }
+    let target = parse_conditional_expr(tokens)?;
+
+    if first(tokens).is_ok_and(|token| token.kind == TokenType::Equals) {
+        let equals = consume(tokens)?;
+        let line = target.line;
+        if is_member_target(&target) {
+            let value = parse_assignment_expr(tokens)?;
+            return Ok(Stmt {
+                kind: StmtType::MemberAssignment,
+                line,
+                left: Some(Box::new(target)),
+                right: Some(Box::new(value)),
+                value: None,
+                body: None,
+                operator: None,
+                consequent: None,
+                slot: None,
+            });
+        }
+        let (name, pattern) = match target.kind {
+            StmtType::Identifier => (target.value, None),
+            StmtType::ListLiteral | StmtType::ObjectLiteral => (None, Some(Box::new(into_pattern(target)?))),
+            _ => {
+                return Err(ParserError::UnexpectedToken { expected: "identifier or pattern before '='".to_string(), found: equals });
+            },
+        };
+
+        let value = parse_assignment_expr(tokens)?;
+        return Ok(Stmt {
+            kind: StmtType::Assignment,
+            line,
+            value: name,
+            right: Some(Box::new(value)),
+            left: pattern,
+            body: None,
+            operator: None,
+            consequent: None,
+            slot: None,
+        });
+    }
+
+    if first(tokens).is_ok_and(|token| token.kind == TokenType::CompoundAssignment) {
+        let compound = consume(tokens)?;
+        let operator = compound.value.trim_end_matches('=').to_string();
+        if is_member_target(&target) {
+            // Kept whole rather than desugared, so the object and the index
+            // are only evaluated once.
+            let value = parse_assignment_expr(tokens)?;
+            return Ok(Stmt {
+                kind: StmtType::MemberAssignment,
+                line: target.line,
+                left: Some(Box::new(target)),
+                right: Some(Box::new(value)),
+                value: None,
+                body: None,
+                operator: Some(operator),
+                consequent: None,
+                slot: None,
+            });
+        }
+        if target.kind != StmtType::Identifier {
+            let expected = format!("identifier, field or item before '{}'", compound.value);
+            return Err(ParserError::UnexpectedToken { expected, found: compound });
+        }
+
+        let value = parse_assignment_expr(tokens)?;
+        return Ok(desugar_update(target, operator, value));
+    }
+
+    Ok(target)
+}
+
+/// `yield value` hands `value` to whoever resumes the generator, and is
+/// worth what they pass to `.next()` in turn. Without a value on the same
+/// line it yields `null`.
+fn parse_yield_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let yield_token = expect(tokens, TokenType::Yield)?;
+
+    let has_value = tokens.first().is_some_and(|token| {
+        token.line == yield_token.line && !matches!(token.kind,
+            TokenType::Eof
+            | TokenType::CloseBracket
+            | TokenType::CloseParen
+            | TokenType::CloseSquareBracket
+            | TokenType::Comma
+            | TokenType::Colon)
+    });
+    let value = if has_value { Some(Box::new(parse_assignment_expr(tokens)?)) } else { None };
+
+    Ok(Stmt {
+        kind: StmtType::YieldExpr,
+        right: value,
+        left: None,
+        body: None,
+        value: None,
+        operator: None,
+        consequent: None,
+        line: yield_token.line,
+        slot: None,
+    })
+}
+
+/// `cond ? a : b` is the expression form of `if (cond) { a } else { b }` and
+/// becomes the same node, so both backends treat it exactly like an `if`.
+fn parse_conditional_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let condition = parse_coalesce_expr(tokens)?;
+
+    if !first(tokens).is_ok_and(|token| token.kind == TokenType::Question) {
+        return Ok(condition);
+    }
+    consume(tokens)?;
+    let consequent = parse_expr(tokens)?;
+    expect(tokens, TokenType::Colon)?;
+    let alternate = parse_conditional_expr(tokens)?;
+
+    Ok(Stmt {
+        kind: StmtType::IfStmt,
+        line: condition.line,
+        left: Some(Box::new(condition)),
+        consequent: Some(Box::new(consequent)),
+        right: Some(Box::new(alternate)),
+        body: None,
+        operator: None,
+        value: None,
+        slot: None,
+    })
+}
+
+/// `a ?? b` evaluates `b` only when `a` is `null`.
+fn parse_coalesce_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let mut left = parse_comparison_expr(tokens)?;
+
+    while first(tokens).is_ok_and(|token| token.kind == TokenType::NullCoalescing) {
+        let operator = consume(tokens)?;
+        let right = parse_comparison_expr(tokens)?;
+        left = Stmt {
+            kind: StmtType::CoalesceExpr,
+            line: left.line,
+            slot: None,
+            left: Some(Box::new(left)),
+            operator: Some(operator.value),
+            right: Some(Box::new(right)),
+            value: None,
+            body: None,
+            consequent: None,
+        };
+    }
+    Ok(left)
+}
+
+/// `x op= value` is `x = x op value`. The target is a plain identifier, so
+/// reading it again has no side effects and it is evaluated only once.
+fn desugar_update(target: Stmt, operator: String, value: Stmt) -> Stmt {
+    Stmt {
+        kind: StmtType::Assignment,
+        line: target.line,
+        value: target.value.clone(),
+        right: Some(Box::new(Stmt {
+            kind: StmtType::BinaryExpr,
+            line: target.line,
+            slot: None,
+            left: Some(Box::new(target)),
+            operator: Some(operator),
+            right: Some(Box::new(value)),
+            value: None,
+            body: None,
+            consequent: None,
+        })),
+        left: None,
+        body: None,
+        operator: None,
+        consequent: None,
+        slot: None,
+    }
+}
+
+/// What a function with an empty body returns, kept when parameter
+/// patterns are taken apart at the start of the body.
+fn null_literal(line: usize) -> Stmt {
+    Stmt {
+        kind: StmtType::NullLiteral,
+        left: None,
+        right: None,
+        value: Some("null".to_string()),
+        operator: None,
+        consequent: None,
+        body: None,
+        line,
+        slot: None,
+    }
+}
+
+/// A field or an item that can be assigned to; a chain with `?.` in it
+/// only reads.
+fn is_member_target(target: &Stmt) -> bool {
+    matches!(target.kind, StmtType::MemberExpr | StmtType::IndexExpr) && !is_optional_chain(target)
+}
+
+/// Whether `?.` appears in the chain of members, indexes and calls that
+/// ends with `stmt`.
+fn is_optional_chain(stmt: &Stmt) -> bool {
+    matches!(stmt.kind, StmtType::MemberExpr | StmtType::IndexExpr | StmtType::CallExpression)
+        && (stmt.operator.as_deref() == Some("?.") || stmt.left.as_deref().is_some_and(is_optional_chain))
+}
+
+/// The `1` added or subtracted by `++` and `--`.
+fn one(line: usize) -> Stmt {
+    Stmt {
+        kind: StmtType::IntegerLiteral,
+        left: None,
+        right: None,
+        value: Some("1".to_string()),
+        operator: None,
+        consequent: None,
+        body: None,
+        line,
+        slot: None,
     }
 }
 
 fn parse_comparison_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
-    let mut left = parse_addition_expr(tokens)?;
+    let mut left = parse_pipeline_expr(tokens)?;
 
     while let Ok(operator) = first(tokens) {
         let op_kind = &operator.kind;
@@ -170,9 +853,11 @@ fn parse_comparison_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
             op_kind == &TokenType::GreaterThanEquals
         {
             consume(tokens)?;
-            let right = parse_addition_expr(tokens)?;
+            let right = parse_pipeline_expr(tokens)?;
             left = Stmt {
                 kind: StmtType::BinaryExpr,
+                line: left.line,
+                slot: None,
                 left: Some(Box::new(left)),
                 operator: Some(operator.value),
                 right: Some(Box::new(right)),
@@ -187,15 +872,53 @@ fn parse_comparison_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
     Ok(left)
 }
 
-fn parse_addition_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
-    let mut left = parse_multiplication_expr(tokens)?;
+/// `x |> f` is `f(x)` and `x |> f(a)` is `f(x, a)`: the left value becomes
+/// the first argument of the call on the right.
+fn parse_pipeline_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let mut value = parse_bitwise_or_expr(tokens)?;
+
+    while first(tokens).is_ok_and(|token| token.kind == TokenType::Pipeline) {
+        consume(tokens)?;
+        let stage = parse_bitwise_or_expr(tokens)?;
+        value = match stage.kind {
+            StmtType::CallExpression => {
+                let mut call = stage;
+                call.body.get_or_insert_with(Vec::new).insert(0, value);
+                call
+            },
+            _ => Stmt {
+                kind: StmtType::CallExpression,
+                line: stage.line,
+                left: Some(Box::new(stage)),
+                body: Some(vec![value]),
+                right: None,
+                value: None,
+                operator: None,
+                consequent: None,
+                slot: None,
+            },
+        };
+    }
+    Ok(value)
+}
+
+/// Parses one left-associative level of binary operators: `operand`
+/// parses the next tighter level.
+fn parse_binary_level(
+    tokens: &mut Vec<Token>,
+    operators: &[&str],
+    operand: fn(&mut Vec<Token>) -> Result<Stmt, ParserError>,
+) -> Result<Stmt, ParserError> {
+    let mut left = operand(tokens)?;
 
     while let Ok(operator) = first(tokens) {
-        if operator.value == "+" || operator.value == "-" {
+        if operator.kind == TokenType::Binary && operators.contains(&operator.value.as_str()) {
             consume(tokens)?;
-            let right = parse_multiplication_expr(tokens)?;
+            let right = operand(tokens)?;
             left = Stmt {
                 kind: StmtType::BinaryExpr,
+                line: left.line,
+                slot: None,
                 left: Some(Box::new(left)),
                 operator: Some(operator.value),
                 right: Some(Box::new(right)),
@@ -210,27 +933,354 @@ fn parse_addition_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
     Ok(left)
 }
 
+fn parse_bitwise_or_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    parse_binary_level(tokens, &["|"], parse_bitwise_xor_expr)
+}
+
+fn parse_bitwise_xor_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    parse_binary_level(tokens, &["^"], parse_bitwise_and_expr)
+}
+
+fn parse_bitwise_and_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    parse_binary_level(tokens, &["&"], parse_shift_expr)
+}
+
+fn parse_shift_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    parse_binary_level(tokens, &["<<", ">>"], parse_addition_expr)
+}
+
+fn parse_addition_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    parse_binary_level(tokens, &["+", "-"], parse_multiplication_expr)
+}
+
 fn parse_multiplication_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
-    let mut left = parse_primary_expr(tokens)?;
+    parse_binary_level(tokens, &["*", "/", "//", "%"], parse_unary_expr)
+}
 
-    while let Ok(operator) = first(tokens) {
-        if operator.value == "*" || operator.value == "/" || operator.value == "%" {
+/// Prefix `-`, `~` and `freeze` bind tighter than `*` but looser than `**`,
+/// so `-2 ** 2` is `-(2 ** 2)`.
+fn parse_unary_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let token = first(tokens)?;
+    if token.kind == TokenType::Update {
+        consume(tokens)?;
+        let start = first(tokens)?;
+        if start.kind != TokenType::Identifier {
+            let expected = format!("identifier, field or item after '{}'", token.value);
+            return Err(ParserError::UnexpectedToken { expected, found: start });
+        }
+        let target = parse_member_expr(tokens)?;
+        let operator = token.value[..1].to_string();
+        if is_member_target(&target) {
+            return Ok(Stmt {
+                kind: StmtType::MemberAssignment,
+                line: token.line,
+                left: Some(Box::new(target)),
+                right: Some(Box::new(one(token.line))),
+                value: None,
+                body: None,
+                operator: Some(operator),
+                consequent: None,
+                slot: None,
+            });
+        }
+        if target.kind != StmtType::Identifier {
+            let expected = format!("identifier, field or item after '{}'", token.value);
+            return Err(ParserError::UnexpectedToken { expected, found: start });
+        }
+        return Ok(desugar_update(target, operator, one(token.line)));
+    }
+    if token.kind == TokenType::Await {
+        consume(tokens)?;
+        let operand = parse_unary_expr(tokens)?;
+        return Ok(Stmt {
+            kind: StmtType::AwaitExpr,
+            line: token.line,
+            slot: None,
+            left: None,
+            operator: None,
+            right: Some(Box::new(operand)),
+            value: None,
+            body: None,
+            consequent: None,
+        });
+    }
+    let is_prefix = token.kind == TokenType::Freeze
+        || (token.kind == TokenType::Binary && (token.value == "-" || token.value == "~"));
+    if is_prefix {
+        consume(tokens)?;
+        let operand = parse_unary_expr(tokens)?;
+        return Ok(Stmt {
+            kind: StmtType::UnaryExpr,
+            line: token.line,
+            slot: None,
+            left: None,
+            operator: Some(token.value),
+            right: Some(Box::new(operand)),
+            value: None,
+            body: None,
+            consequent: None,
+        });
+    }
+    parse_power_expr(tokens)
+}
+
+/// `**` is right-associative and its exponent may carry a sign: `2 ** -1`.
+fn parse_power_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let base = parse_postfix_expr(tokens)?;
+
+    if first(tokens).is_ok_and(|token| token.kind == TokenType::Binary && token.value == "**") {
+        let operator = consume(tokens)?;
+        let exponent = parse_unary_expr(tokens)?;
+        return Ok(Stmt {
+            kind: StmtType::BinaryExpr,
+            line: base.line,
+            slot: None,
+            left: Some(Box::new(base)),
+            operator: Some(operator.value),
+            right: Some(Box::new(exponent)),
+            value: None,
+            body: None,
+            consequent: None,
+        });
+    }
+    Ok(base)
+}
+
+/// `x++` and `x--` update the variable but evaluate to its previous value,
+/// which an assignment cannot express, so they get a node of their own.
+fn parse_postfix_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let operand = parse_member_expr(tokens)?;
+
+    // On the next line `++` and `--` start a prefix update instead.
+    if first(tokens).is_ok_and(|token| token.kind == TokenType::Update && token.line == operand.line) {
+        let update = consume(tokens)?;
+        let line = operand.line;
+        let operator = Some(update.value[..1].to_string());
+        if is_member_target(&operand) {
+            return Ok(Stmt {
+                kind: StmtType::PostfixUpdate,
+                line,
+                value: None,
+                operator,
+                left: Some(Box::new(operand)),
+                right: None,
+                body: None,
+                consequent: None,
+                slot: None,
+            });
+        }
+        if operand.kind != StmtType::Identifier {
+            let expected = format!("identifier, field or item before '{}'", update.value);
+            return Err(ParserError::UnexpectedToken { expected, found: update });
+        }
+        return Ok(Stmt {
+            kind: StmtType::PostfixUpdate,
+            line,
+            value: operand.value,
+            operator,
+            left: None,
+            right: None,
+            body: None,
+            consequent: None,
+            slot: None,
+        });
+    }
+    Ok(operand)
+}
+
+/// Calls, indexing and field access, with `.` or with `?.` to get `null`
+/// instead of an error when the object itself is `null`, skipping the
+/// rest of the chain after it. A call's `(` and
+/// an index's `[` have to be on the same line as the object, so an
+/// expression starting the next line is not mistaken for either.
+fn parse_member_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let mut object = parse_primary_expr(tokens)?;
+
+    while let Ok(token) = first(tokens) {
+        if token.kind == TokenType::OpenParen && token.line == object.line {
+            object = parse_call_expr(tokens, object)?;
+            continue;
+        }
+        if token.kind == TokenType::OpenSquareBracket && token.line == object.line {
             consume(tokens)?;
-            let right = parse_primary_expr(tokens)?;
-            left = Stmt {
-                kind: StmtType::BinaryExpr,
-                left: Some(Box::new(left)),
-                operator: Some(operator.value),
-                right: Some(Box::new(right)),
+            let index = parse_expr(tokens)?;
+            expect(tokens, TokenType::CloseSquareBracket)?;
+            object = Stmt {
+                kind: StmtType::IndexExpr,
+                line: object.line,
+                slot: None,
+                left: Some(Box::new(object)),
+                right: Some(Box::new(index)),
+                operator: None,
                 value: None,
                 body: None,
                 consequent: None,
             };
+            continue;
+        }
+        if token.kind != TokenType::Dot && token.kind != TokenType::OptionalChain {
+            break;
+        }
+        let operator = consume(tokens)?;
+        let field = expect(tokens, TokenType::Identifier)?;
+        object = Stmt {
+            kind: StmtType::MemberExpr,
+            line: object.line,
+            slot: None,
+            left: Some(Box::new(object)),
+            operator: Some(operator.value),
+            value: Some(field.value),
+            right: None,
+            body: None,
+            consequent: None,
+        };
+    }
+    Ok(object)
+}
+
+/// Arguments are expressions, `name: value` for a named argument or
+/// `...list` to pass the items of a list as positional arguments.
+fn parse_call_expr(tokens: &mut Vec<Token>, callee: Stmt) -> Result<Stmt, ParserError> {
+    expect(tokens, TokenType::OpenParen)?;
+    let mut arguments = Vec::new();
+    while first(tokens)?.kind != TokenType::CloseParen {
+        let is_named = first(tokens)?.kind == TokenType::Identifier
+            && tokens.get(1).is_some_and(|token| token.kind == TokenType::Colon);
+        let argument = if is_named {
+            let name = consume(tokens)?;
+            consume(tokens)?;
+            Stmt {
+                kind: StmtType::NamedArgument,
+                line: name.line,
+                value: Some(name.value),
+                right: Some(Box::new(parse_expr(tokens)?)),
+                left: None,
+                body: None,
+                operator: None,
+                consequent: None,
+                slot: None,
+            }
         } else {
+            parse_element(tokens)?
+        };
+        arguments.push(argument);
+        if first(tokens)?.kind != TokenType::Comma {
             break;
         }
+        consume(tokens)?;
     }
-    Ok(left)
+    expect(tokens, TokenType::CloseParen)?;
+
+    Ok(Stmt {
+        kind: StmtType::CallExpression,
+        line: callee.line,
+        left: Some(Box::new(callee)),
+        body: Some(arguments),
+        right: None,
+        value: None,
+        operator: None,
+        consequent: None,
+        slot: None,
+    })
+}
+
+/// An item of a list literal or a positional argument: an expression, or
+/// `...list` to insert all the items of a list.
+fn parse_element(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    if first(tokens)?.kind != TokenType::Ellipsis {
+        return parse_expr(tokens);
+    }
+    let ellipsis = consume(tokens)?;
+    Ok(Stmt {
+        kind: StmtType::Spread,
+        line: ellipsis.line,
+        right: Some(Box::new(parse_expr(tokens)?)),
+        left: None,
+        body: None,
+        value: None,
+        operator: None,
+        consequent: None,
+        slot: None,
+    })
+}
+
+fn parse_list_literal(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let open = expect(tokens, TokenType::OpenSquareBracket)?;
+    let mut items = Vec::new();
+    while first(tokens)?.kind != TokenType::CloseSquareBracket {
+        items.push(parse_element(tokens)?);
+        if first(tokens)?.kind != TokenType::Comma {
+            break;
+        }
+        consume(tokens)?;
+    }
+    expect(tokens, TokenType::CloseSquareBracket)?;
+
+    Ok(Stmt {
+        kind: StmtType::ListLiteral,
+        line: open.line,
+        body: Some(items),
+        left: None,
+        right: None,
+        value: None,
+        operator: None,
+        consequent: None,
+        slot: None,
+    })
+}
+
+/// `{ x: 1, y }`: fields are `Property` nodes named by `value` with their
+/// value in `right`; `y` alone is short for `y: y`.
+fn parse_object_literal(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
+    let open = expect(tokens, TokenType::OpenBracket)?;
+    let mut fields = Vec::new();
+    while first(tokens)?.kind != TokenType::CloseBracket {
+        let name = expect(tokens, TokenType::Identifier)?;
+        let value = if first(tokens)?.kind == TokenType::Colon {
+            consume(tokens)?;
+            parse_expr(tokens)?
+        } else {
+            Stmt {
+                kind: StmtType::Identifier,
+                line: name.line,
+                value: Some(name.value.clone()),
+                left: None,
+                right: None,
+                operator: None,
+                consequent: None,
+                body: None,
+                slot: None,
+            }
+        };
+        fields.push(Stmt {
+            kind: StmtType::Property,
+            line: name.line,
+            value: Some(name.value),
+            right: Some(Box::new(value)),
+            left: None,
+            body: None,
+            operator: None,
+            consequent: None,
+            slot: None,
+        });
+        if first(tokens)?.kind != TokenType::Comma {
+            break;
+        }
+        consume(tokens)?;
+    }
+    expect(tokens, TokenType::CloseBracket)?;
+
+    Ok(Stmt {
+        kind: StmtType::ObjectLiteral,
+        line: open.line,
+        body: Some(fields),
+        left: None,
+        right: None,
+        value: None,
+        operator: None,
+        consequent: None,
+        slot: None,
+    })
 }
 
 fn parse_primary_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
@@ -245,6 +1295,30 @@ fn parse_primary_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
             operator: None,
             consequent: None,
             body: None,
+            line: token.line,
+            slot: None,
+        }),
+        TokenType::Integer => Ok(Stmt {
+            kind: StmtType::IntegerLiteral,
+            left: None,
+            right: None,
+            value: Some(token.value),
+            operator: None,
+            consequent: None,
+            body: None,
+            line: token.line,
+            slot: None,
+        }),
+        TokenType::Rational => Ok(Stmt {
+            kind: StmtType::RationalLiteral,
+            left: None,
+            right: None,
+            value: Some(token.value),
+            operator: None,
+            consequent: None,
+            body: None,
+            line: token.line,
+            slot: None,
         }),
         TokenType::Identifier => Ok(Stmt {
             kind: StmtType::Identifier,
@@ -254,13 +1328,45 @@ fn parse_primary_expr(tokens: &mut Vec<Token>) -> Result<Stmt, ParserError> {
             operator: None,
             consequent: None,
             body: None,
+            line: token.line,
+            slot: None,
         }),
         TokenType::OpenParen => {
-            let expr = parse_addition_expr(tokens)?;
+            let expr = parse_expr(tokens)?;
             expect(tokens, TokenType::CloseParen)?;
             Ok(expr)
         }
-        TokenType::Null => Err(ParserError::MissingExpression),
+        TokenType::Null => Ok(Stmt {
+            kind: StmtType::NullLiteral,
+            left: None,
+            right: None,
+            value: Some(token.value),
+            operator: None,
+            consequent: None,
+            body: None,
+            line: token.line,
+            slot: None,
+        }),
+        TokenType::If => {
+            tokens.insert(0, token);
+            parse_if_stmt(tokens)
+        },
+        TokenType::OpenSquareBracket => {
+            tokens.insert(0, token);
+            parse_list_literal(tokens)
+        },
+        TokenType::OpenBracket => {
+            tokens.insert(0, token);
+            parse_object_literal(tokens)
+        },
+        TokenType::Work => parse_work_expr(tokens, token),
+        TokenType::Function | TokenType::Async => {
+            tokens.insert(0, token);
+            let mut function = parse_function(tokens)?;
+            // A name on a function expression is only for display.
+            function.kind = StmtType::FunctionExpression;
+            Ok(function)
+        },
         _ => Err(ParserError::UnexpectedToken {
             expected: "number, identifier, or open parenthesis".to_string(),
             found: token,
@@ -0,0 +1,412 @@
+use std::collections::HashSet;
+use std::fmt;
+use crate::frontend::ast::{Program, Slot, Stmt, StmtType};
+
+#[derive(Debug, Clone)]
+pub enum ResolveError {
+    UseBeforeDeclaration { name: String, line: usize },
+    DuplicateDeclaration { name: String, line: usize },
+    UndeclaredAssignment { name: String, line: usize },
+    ReturnOutsideFunction { line: usize },
+    YieldOutsideGenerator { line: usize },
+    AwaitOutsideAsync { line: usize },
+    AssignmentToConstant { name: String, line: usize },
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResolveError::UseBeforeDeclaration { name, line } => {
+                write!(f, "Resolve error (line {}): '{}' is used before its declaration", line, name)
+            },
+            ResolveError::DuplicateDeclaration { name, line } => {
+                write!(f, "Resolve error (line {}): '{}' is already declared in this scope", line, name)
+            },
+            ResolveError::UndeclaredAssignment { name, line } => {
+                write!(f, "Resolve error (line {}): cannot assign to undeclared variable '{}'", line, name)
+            },
+            ResolveError::ReturnOutsideFunction { line } => {
+                write!(f, "Resolve error (line {}): 'return' outside of a function", line)
+            },
+            ResolveError::YieldOutsideGenerator { line } => {
+                write!(f, "Resolve error (line {}): 'yield' outside of a generator", line)
+            },
+            ResolveError::AwaitOutsideAsync { line } => {
+                write!(f, "Resolve error (line {}): 'await' outside of an async function", line)
+            },
+            ResolveError::AssignmentToConstant { name, line } => {
+                write!(f, "Resolve error (line {}): cannot assign to constant '{}'", line, name)
+            },
+        }
+    }
+}
+
+/// The kind of code a statement runs in, which decides where `yield` and
+/// `await` are allowed. The program itself may `await`.
+#[derive(Clone, Copy, PartialEq, Default)]
+enum Context {
+    #[default]
+    Program,
+    Function,
+    Generator,
+    Async,
+    /// A `work` block, which runs its own event loop like a program.
+    Work,
+}
+
+enum Resolution {
+    Local(Slot),
+    Global,
+    Unknown,
+}
+
+#[derive(Default)]
+struct Scope {
+    /// Names declared so far; the position is the slot index.
+    declared: Vec<String>,
+    /// Names declared somewhere in this scope, in order, used to tell a
+    /// use-before-declaration apart from a reference to an outer variable.
+    upcoming: Vec<String>,
+    /// Declared names that are `const`.
+    constants: HashSet<String>,
+    /// Whether this is the parameter scope of a function.
+    function: bool,
+    /// Upcoming names used from functions nested in this scope, with the
+    /// slot index they were given and the line using them.
+    forward: Vec<(String, usize, usize)>,
+}
+
+/// Binds every identifier to a block-local slot or to the global table.
+/// Globals declared by previous programs are remembered, so a `Resolver`
+/// can be kept alive across REPL lines.
+#[derive(Default)]
+pub struct Resolver {
+    globals: HashSet<String>,
+    /// Globals from previous programs declared with `const`.
+    global_constants: HashSet<String>,
+    program: Scope,
+    scopes: Vec<Scope>,
+    /// How many function bodies enclose the statement being resolved.
+    function_depth: usize,
+    context: Context,
+    errors: Vec<ResolveError>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn resolve(&mut self, program: &mut Program) -> Result<(), Vec<ResolveError>> {
+        self.program = Scope { upcoming: declared_names(&program.body), ..Scope::default() };
+        self.scopes.clear();
+        self.function_depth = 0;
+        self.context = Context::Program;
+
+        for stmt in program.body.iter_mut() {
+            self.resolve_stmt(stmt);
+        }
+
+        let program_scope = std::mem::take(&mut self.program);
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors));
+        }
+        for name in &program_scope.declared {
+            if program_scope.constants.contains(name) {
+                self.global_constants.insert(name.clone());
+            } else {
+                self.global_constants.remove(name);
+            }
+        }
+        self.globals.extend(program_scope.declared);
+        Ok(())
+    }
+
+    /// Declares a global defined from outside any program, such as one the
+    /// host sets, so later programs may use and assign it.
+    pub fn declare_global(&mut self, name: &str) {
+        self.globals.insert(name.to_string());
+        self.global_constants.remove(name);
+    }
+
+    fn resolve_stmt(&mut self, stmt: &mut Stmt) {
+        match stmt.kind {
+            StmtType::Identifier => {
+                let name = stmt.value.clone().unwrap_or_default();
+                if let Resolution::Local(slot) = self.lookup(&name, stmt.line) {
+                    stmt.slot = Some(slot);
+                }
+            },
+            StmtType::VariableDeclaration | StmtType::Assignment if stmt.left.is_some() => {
+                if let Some(value) = stmt.right.as_deref_mut() {
+                    self.resolve_stmt(value);
+                }
+                let declaration = match stmt.kind {
+                    StmtType::VariableDeclaration => Some(stmt.operator.is_some()),
+                    _ => None,
+                };
+                if let Some(pattern) = stmt.left.as_deref_mut() {
+                    self.resolve_pattern(pattern, declaration);
+                }
+            },
+            StmtType::PostfixUpdate if stmt.left.is_some() => self.resolve_children(stmt),
+            StmtType::Assignment | StmtType::PostfixUpdate => {
+                self.resolve_children(stmt);
+                let name = stmt.value.clone().unwrap_or_default();
+                stmt.slot = self.resolve_assignment(name, stmt.line);
+            },
+            StmtType::VariableDeclaration => {
+                self.resolve_children(stmt);
+                let name = stmt.value.clone().unwrap_or_default();
+                stmt.slot = self.declare(name, stmt.line, stmt.operator.is_some());
+            },
+            StmtType::FunctionDeclaration => {
+                // Declared before the body so the function can call itself.
+                let name = stmt.value.clone().unwrap_or_default();
+                stmt.slot = self.declare(name, stmt.line, false);
+                self.resolve_function(stmt);
+            },
+            StmtType::FunctionExpression => self.resolve_function(stmt),
+            StmtType::ReturnStmt => {
+                if self.function_depth == 0 {
+                    self.errors.push(ResolveError::ReturnOutsideFunction { line: stmt.line });
+                }
+                self.resolve_children(stmt);
+            },
+            StmtType::YieldExpr => {
+                if self.context != Context::Generator {
+                    self.errors.push(ResolveError::YieldOutsideGenerator { line: stmt.line });
+                }
+                self.resolve_children(stmt);
+            },
+            StmtType::AwaitExpr => {
+                if !matches!(self.context, Context::Program | Context::Async | Context::Work) {
+                    self.errors.push(ResolveError::AwaitOutsideAsync { line: stmt.line });
+                }
+                self.resolve_children(stmt);
+            },
+            StmtType::ForInStmt => {
+                if let Some(iterable) = stmt.right.as_deref_mut() {
+                    self.resolve_stmt(iterable);
+                }
+                // The loop variable lives in a scope around the body block,
+                // created anew for each iteration.
+                self.scopes.push(Scope::default());
+                match stmt.left.as_deref_mut() {
+                    Some(pattern) => self.resolve_pattern(pattern, Some(false)),
+                    None => {
+                        let name = stmt.value.clone().unwrap_or_default();
+                        stmt.slot = self.declare(name, stmt.line, false);
+                    },
+                }
+                if let Some(body) = stmt.consequent.as_deref_mut() {
+                    self.resolve_stmt(body);
+                }
+                self.scopes.pop();
+            },
+            StmtType::BlockStmt => {
+                let body = stmt.body.get_or_insert_with(Vec::new);
+                self.scopes.push(Scope { upcoming: declared_names(body), ..Scope::default() });
+                for child in body.iter_mut() {
+                    self.resolve_stmt(child);
+                }
+                self.pop_block_scope();
+            },
+            _ => self.resolve_children(stmt),
+        }
+    }
+
+    /// The names of a destructuring pattern, left to right, and those of
+    /// the patterns nested in it, each after the default of its pattern.
+    fn resolve_pattern(&mut self, pattern: &mut Stmt, declaration: Option<bool>) {
+        for element in pattern.body.iter_mut().flatten() {
+            if !matches!(element.kind, StmtType::ListPattern | StmtType::ObjectPattern) {
+                self.resolve_pattern_name(element, declaration);
+                continue;
+            }
+            if let Some(default) = element.right.as_deref_mut() {
+                self.resolve_stmt(default);
+            }
+            self.resolve_pattern(element, declaration);
+        }
+    }
+
+    /// A name of a destructuring pattern, declared when `declaration` says
+    /// whether it is `const` and assigned otherwise. Its default is resolved
+    /// first, so it sees the names before it but not the name itself.
+    fn resolve_pattern_name(&mut self, name: &mut Stmt, declaration: Option<bool>) {
+        if let Some(default) = name.right.as_deref_mut() {
+            self.resolve_stmt(default);
+        }
+        let text = name.value.clone().unwrap_or_default();
+        name.slot = match declaration {
+            Some(constant) => self.declare(text, name.line, constant),
+            None => self.resolve_assignment(text, name.line),
+        };
+    }
+
+    /// The slot an assignment to `name` writes, reporting assignments to
+    /// undeclared variables and to constants.
+    fn resolve_assignment(&mut self, name: String, line: usize) -> Option<Slot> {
+        if self.is_constant(&name) {
+            self.errors.push(ResolveError::AssignmentToConstant { name: name.clone(), line });
+        }
+        match self.lookup(&name, line) {
+            Resolution::Local(slot) => Some(slot),
+            Resolution::Global => None,
+            Resolution::Unknown => {
+                self.errors.push(ResolveError::UndeclaredAssignment { name, line });
+                None
+            },
+        }
+    }
+
+    /// Whether `name`, as visible here, was declared with `const`. A global
+    /// constant declared after a function that assigns it is only caught at
+    /// run time.
+    fn is_constant(&self, name: &str) -> bool {
+        for scope in self.scopes.iter().rev() {
+            if scope.declared.iter().any(|declared| declared == name) {
+                return scope.constants.contains(name);
+            }
+            if scope.upcoming.iter().any(|upcoming| upcoming == name) {
+                return false;
+            }
+        }
+        if self.program.declared.iter().any(|declared| declared == name) {
+            self.program.constants.contains(name)
+        } else {
+            self.global_constants.contains(name)
+        }
+    }
+
+    /// Parameters live in a scope of their own, between the scope the
+    /// function is created in and its body block, matching the scope a call
+    /// creates at run time. Default values are evaluated in that scope at
+    /// call time, so each one sees the parameters before it.
+    fn resolve_function(&mut self, stmt: &mut Stmt) {
+        self.scopes.push(Scope { function: true, ..Scope::default() });
+        self.function_depth += 1;
+        let context = match stmt.operator.as_deref() {
+            Some("*") => Context::Generator,
+            Some("async") => Context::Async,
+            Some("work") => Context::Work,
+            _ => Context::Function,
+        };
+        let enclosing_context = std::mem::replace(&mut self.context, context);
+        for (index, param) in stmt.body.iter_mut().flatten().enumerate() {
+            if let Some(default) = param.right.as_deref_mut() {
+                self.resolve_stmt(default);
+            }
+            let name = param.value.clone().unwrap_or_default();
+            if self.declare(name, param.line, false).is_some() {
+                param.slot = Some(Slot { depth: 0, index });
+            }
+        }
+
+        if let Some(body) = stmt.consequent.as_deref_mut() {
+            self.resolve_stmt(body);
+        }
+        self.context = enclosing_context;
+        self.function_depth -= 1;
+        self.scopes.pop();
+    }
+
+    fn resolve_children(&mut self, stmt: &mut Stmt) {
+        for child in [&mut stmt.left, &mut stmt.right, &mut stmt.consequent].into_iter().flatten() {
+            self.resolve_stmt(child);
+        }
+        if stmt.kind != StmtType::BlockStmt {
+            for child in stmt.body.iter_mut().flatten() {
+                self.resolve_stmt(child);
+            }
+        }
+    }
+
+    fn lookup(&mut self, name: &str, line: usize) -> Resolution {
+        let mut in_function = false;
+        for (depth, scope) in self.scopes.iter_mut().rev().enumerate() {
+            if let Some(index) = scope.declared.iter().rposition(|declared| declared == name) {
+                return Resolution::Local(Slot { depth, index });
+            }
+            if let Some(index) = scope.upcoming.iter().position(|upcoming| upcoming == name) {
+                // A function body runs once it is called, by which time the
+                // rest of the scope around it may have been declared.
+                if in_function {
+                    scope.forward.push((name.to_string(), index, line));
+                    return Resolution::Local(Slot { depth, index });
+                }
+                self.errors.push(ResolveError::UseBeforeDeclaration { name: name.to_string(), line });
+                return Resolution::Global;
+            }
+            in_function |= scope.function;
+        }
+
+        if self.program.declared.iter().any(|declared| declared == name) || self.globals.contains(name) {
+            Resolution::Global
+        } else if self.program.upcoming.iter().any(|upcoming| upcoming == name) {
+            // A function body runs later, once the whole program has been
+            // declared, so it may refer to globals declared after it.
+            if self.function_depth > 0 {
+                return Resolution::Global;
+            }
+            self.errors.push(ResolveError::UseBeforeDeclaration { name: name.to_string(), line });
+            Resolution::Global
+        } else {
+            Resolution::Unknown
+        }
+    }
+
+    /// Leaves a block, checking that the names used from functions before
+    /// their declaration got the slots those functions were given.
+    fn pop_block_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        for (name, index, line) in scope.forward {
+            if scope.declared.get(index) != Some(&name) {
+                self.errors.push(ResolveError::UseBeforeDeclaration { name, line });
+            }
+        }
+    }
+
+    fn declare(&mut self, name: String, line: usize, constant: bool) -> Option<Slot> {
+        let is_global = self.scopes.is_empty();
+        let scope = self.scopes.last_mut().unwrap_or(&mut self.program);
+        if scope.declared.contains(&name) {
+            self.errors.push(ResolveError::DuplicateDeclaration { name: name.clone(), line });
+        }
+        if constant {
+            scope.constants.insert(name.clone());
+        }
+        scope.declared.push(name);
+
+        if is_global {
+            None
+        } else {
+            Some(Slot { depth: 0, index: scope.declared.len() - 1 })
+        }
+    }
+}
+
+fn declared_names(body: &[Stmt]) -> Vec<String> {
+    body.iter()
+        .filter(|stmt| matches!(stmt.kind, StmtType::VariableDeclaration | StmtType::FunctionDeclaration))
+        .flat_map(|stmt| {
+            let mut names: Vec<String> = stmt.value.iter().cloned().collect();
+            if let Some(pattern) = &stmt.left {
+                pattern_names(pattern, &mut names);
+            }
+            names
+        })
+        .collect()
+}
+
+fn pattern_names(pattern: &Stmt, names: &mut Vec<String>) {
+    for element in pattern.body.iter().flatten() {
+        match element.kind {
+            StmtType::ListPattern | StmtType::ObjectPattern => pattern_names(element, names),
+            _ => names.extend(element.value.clone()),
+        }
+    }
+}
@@ -0,0 +1,15 @@
+//! An interpreter for a small scripting language, with a tree-walking and a
+//! bytecode backend. `Engine` embeds it in a Rust program; the `compiler`
+//! binary is the command line on top of the same modules.
+
+pub mod frontend;
+pub mod runtime;
+pub mod ffi;
+
+pub use runtime::convert::{FromValue, IntoArguments, IntoValue};
+pub use runtime::engine::{Engine, ScriptFunction};
+pub use runtime::error::{RuntimeError, TraceFrame};
+pub use runtime::limits::{Interrupt, Limits};
+pub use runtime::native::Natives;
+pub use runtime::runner::Options;
+pub use runtime::value::Value;
@@ -1,15 +1,82 @@
-mod frontend;
-mod runtime;
-use std::env::args;
+use std::{env::args, process::exit, str::FromStr, thread, time::Duration};
 
-use runtime::{repl::Repl, runner::Runner};
+use compiler::runtime::{native::standard_natives, repl::Repl, runner::{Options, Runner}};
+
+/// Stack of the thread the command line runs programs on, deep enough for
+/// the calls the limits allow.
+const PROGRAM_STACK: usize = 256 * 1024 * 1024;
 
 fn main() {
-    let args: Vec<String> = args().collect();
+    let program = thread::Builder::new()
+        .name("program".to_string())
+        .stack_size(PROGRAM_STACK)
+        .spawn(run_command_line)
+        .unwrap_or_else(|error| {
+            eprintln!("Could not start the program thread: {}", error);
+            exit(1)
+        });
+    if program.join().is_err() {
+        exit(101)
+    }
+}
+
+fn run_command_line() {
+    let mut options = Options { natives: standard_natives(), ..Options::default() };
+    let mut file: Option<String> = None;
+    let mut disasm = false;
+
+    for arg in args().skip(1) {
+        if let Some(name) = arg.strip_prefix("--backend=") {
+            options.backend = match name.parse() {
+                Ok(backend) => backend,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    exit(1)
+                }
+            };
+        } else if arg == "--optimize" {
+            options.optimize = true;
+        } else if arg == "--dump-ast" {
+            options.dump_ast = true;
+        } else if arg == "--bigint" {
+            options.big_integers = true;
+        } else if arg == "--virtual-clock" {
+            options.virtual_clock = true;
+        } else if let Some(steps) = arg.strip_prefix("--fuel=") {
+            options.limits.fuel = Some(parse_limit(&arg, steps));
+        } else if let Some(depth) = arg.strip_prefix("--max-depth=") {
+            options.limits.call_depth = parse_limit(&arg, depth);
+        } else if let Some(bytes) = arg.strip_prefix("--max-value-size=") {
+            options.limits.value_size = Some(parse_limit(&arg, bytes));
+        } else if let Some(bytes) = arg.strip_prefix("--max-heap=") {
+            options.limits.heap = Some(parse_limit(&arg, bytes));
+        } else if let Some(milliseconds) = arg.strip_prefix("--timeout=") {
+            options.limits.timeout = Some(Duration::from_millis(parse_limit(&arg, milliseconds)));
+        } else if arg == "disasm" && file.is_none() && !disasm {
+            disasm = true;
+        } else {
+            file = Some(arg);
+        }
+    }
     
-    if args.len() > 1 {
-        Runner::run(args[1].as_str())
+    if let Some(file) = file {
+        if disasm {
+            Runner::disassemble(file.as_str(), &options)
+        } else {
+            Runner::run(file.as_str(), &options)
+        }
+    } else if disasm {
+        eprintln!("Usage: disasm <file>");
+        exit(1)
     } else {
-        Repl::run();
+        Repl::run(&options);
     }
 }
+
+/// The number a limit switch such as `--fuel=1000` sets.
+fn parse_limit<T: FromStr>(arg: &str, value: &str) -> T {
+    value.parse().unwrap_or_else(|_| {
+        eprintln!("Invalid value in '{}' (expected a non-negative integer)", arg);
+        exit(1)
+    })
+}
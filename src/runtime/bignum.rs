@@ -0,0 +1,564 @@
+//! Arbitrary-precision integers and exact rationals, used when `i64` and
+//! `f64` are not precise enough.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// Sign-magnitude integer. The magnitude is stored in base 2^32, least
+/// significant limb first, without trailing zero limbs; zero is never
+/// negative, so the derived equality is value equality.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn zero() -> Self {
+        BigInt { negative: false, magnitude: vec![] }
+    }
+
+    pub fn from_i64(value: i64) -> Self {
+        let unsigned = value.unsigned_abs();
+        BigInt::from_parts(value < 0, vec![unsigned as u32, (unsigned >> 32) as u32])
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.magnitude.len() > 2 {
+            return None;
+        }
+        let unsigned = self.magnitude.iter().rev().fold(0u64, |acc, limb| (acc << 32) | *limb as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(unsigned)
+        } else {
+            i64::try_from(unsigned).ok()
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let unsigned = self.magnitude.iter().rev().fold(0f64, |acc, limb| acc * 4294967296.0 + *limb as f64);
+        if self.negative { -unsigned } else { unsigned }
+    }
+
+    /// Bytes the magnitude takes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.magnitude.len() * std::mem::size_of::<u32>()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn neg(&self) -> Self {
+        BigInt::from_parts(!self.negative, self.magnitude.clone())
+    }
+
+    pub fn abs(&self) -> Self {
+        BigInt::from_parts(false, self.magnitude.clone())
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        if self.negative == other.negative {
+            return BigInt::from_parts(self.negative, add_magnitudes(&self.magnitude, &other.magnitude));
+        }
+        match compare_magnitudes(&self.magnitude, &other.magnitude) {
+            Ordering::Less => BigInt::from_parts(other.negative, sub_magnitudes(&other.magnitude, &self.magnitude)),
+            _ => BigInt::from_parts(self.negative, sub_magnitudes(&self.magnitude, &other.magnitude)),
+        }
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        BigInt::from_parts(self.negative != other.negative, mul_magnitudes(&self.magnitude, &other.magnitude))
+    }
+
+    /// Quotient rounded towards negative infinity and the matching remainder,
+    /// which has the sign of the divisor. `None` when dividing by zero.
+    pub fn div_rem_floor(&self, other: &Self) -> Option<(Self, Self)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (quotient, remainder) = div_rem_magnitudes(&self.magnitude, &other.magnitude);
+        let mut quotient = BigInt::from_parts(self.negative != other.negative, quotient);
+        let mut remainder = BigInt::from_parts(self.negative, remainder);
+
+        if !remainder.is_zero() && remainder.negative != other.negative {
+            quotient = quotient.sub(&BigInt::from_i64(1));
+            remainder = remainder.add(other);
+        }
+        Some((quotient, remainder))
+    }
+
+    pub fn gcd(&self, other: &Self) -> Self {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while !b.is_zero() {
+            let (_, remainder) = div_rem_magnitudes(&a.magnitude, &b.magnitude);
+            a = b;
+            b = BigInt::from_parts(false, remainder);
+        }
+        a
+    }
+
+    pub fn pow10(exponent: usize) -> Self {
+        let mut magnitude = vec![1];
+        for _ in 0..exponent {
+            magnitude = mul_small(&magnitude, 10, 0);
+        }
+        BigInt::from_parts(false, magnitude)
+    }
+
+    pub fn pow(&self, exponent: u32) -> Self {
+        let mut result = BigInt::from_i64(1);
+        let mut base = self.clone();
+        let mut exponent = exponent;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base);
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.mul(&base);
+            }
+        }
+        result
+    }
+
+    pub fn shl(&self, bits: usize) -> Self {
+        let mut magnitude = vec![0u32; bits / 32];
+        let shift = bits % 32;
+        let mut carry = 0u32;
+        for limb in &self.magnitude {
+            if shift == 0 {
+                magnitude.push(*limb);
+            } else {
+                magnitude.push((limb << shift) | carry);
+                carry = limb >> (32 - shift);
+            }
+        }
+        if carry > 0 {
+            magnitude.push(carry);
+        }
+        BigInt::from_parts(self.negative, magnitude)
+    }
+
+    /// Arithmetic shift: rounds towards negative infinity like `//` by a
+    /// power of two.
+    pub fn shr(&self, bits: usize) -> Self {
+        let divisor = BigInt::from_i64(1).shl(bits);
+        self.div_rem_floor(&divisor).map(|(quotient, _)| quotient).unwrap_or_else(BigInt::zero)
+    }
+
+    /// Bitwise complement in two's complement, i.e. `-self - 1`.
+    pub fn not(&self) -> Self {
+        self.neg().sub(&BigInt::from_i64(1))
+    }
+
+    pub fn bitand(&self, other: &Self) -> Self {
+        self.bitwise(other, |a, b| a & b)
+    }
+
+    pub fn bitor(&self, other: &Self) -> Self {
+        self.bitwise(other, |a, b| a | b)
+    }
+
+    pub fn bitxor(&self, other: &Self) -> Self {
+        self.bitwise(other, |a, b| a ^ b)
+    }
+
+    /// Applies `op` limb by limb to the two's complement representations of
+    /// both operands, wide enough to hold the sign of each.
+    fn bitwise<F>(&self, other: &Self, op: F) -> Self
+    where
+        F: Fn(u32, u32) -> u32,
+    {
+        let width = self.magnitude.len().max(other.magnitude.len()) + 1;
+        let left = self.twos_complement(width);
+        let right = other.twos_complement(width);
+        let limbs: Vec<u32> = left.iter().zip(&right).map(|(a, b)| op(*a, *b)).collect();
+
+        let negative = limbs[width - 1] >> 31 == 1;
+        if negative {
+            let inverted: Vec<u32> = limbs.iter().map(|limb| !limb).collect();
+            BigInt::from_parts(true, add_magnitudes(&inverted, &[1]))
+        } else {
+            BigInt::from_parts(false, limbs)
+        }
+    }
+
+    fn twos_complement(&self, width: usize) -> Vec<u32> {
+        let mut limbs = self.magnitude.clone();
+        limbs.resize(width, 0);
+        if self.negative {
+            let inverted: Vec<u32> = limbs.iter().map(|limb| !limb).collect();
+            limbs = add_magnitudes(&inverted, &[1]);
+            limbs.resize(width, 0);
+        }
+        limbs
+    }
+
+    fn from_parts(negative: bool, mut magnitude: Vec<u32>) -> Self {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        BigInt { negative: negative && !magnitude.is_empty(), magnitude }
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.magnitude, &other.magnitude),
+            (true, true) => compare_magnitudes(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = String;
+
+    /// Parses an optionally signed integer, in decimal or with a `0x`, `0o`
+    /// or `0b` prefix.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let (radix, digits) = match digits.get(..2) {
+            Some("0x") => (16, &digits[2..]),
+            Some("0o") => (8, &digits[2..]),
+            Some("0b") => (2, &digits[2..]),
+            _ => (10, digits),
+        };
+        if digits.is_empty() {
+            return Err(format!("'{}' is not an integer", text));
+        }
+
+        let mut magnitude = vec![];
+        for digit in digits.chars() {
+            let digit = digit.to_digit(radix).ok_or_else(|| format!("'{}' is not an integer", text))?;
+            magnitude = mul_small(&magnitude, radix, digit);
+        }
+        Ok(BigInt::from_parts(negative, magnitude))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        let mut chunks = vec![];
+        let mut magnitude = self.magnitude.clone();
+        while !magnitude.is_empty() {
+            let (quotient, remainder) = div_rem_small(&magnitude, 1_000_000_000);
+            chunks.push(remainder);
+            magnitude = quotient;
+        }
+
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap_or(0))?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Exact fraction kept in lowest terms with a positive denominator.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Rational {
+    numerator: BigInt,
+    denominator: BigInt,
+}
+
+impl Rational {
+    /// `None` when `denominator` is zero.
+    pub fn new(numerator: BigInt, denominator: BigInt) -> Option<Self> {
+        if denominator.is_zero() {
+            return None;
+        }
+        let divisor = numerator.gcd(&denominator);
+        let (mut numerator, _) = numerator.div_rem_floor(&divisor)?;
+        let (mut denominator, _) = denominator.div_rem_floor(&divisor)?;
+        if denominator.is_negative() {
+            numerator = numerator.neg();
+            denominator = denominator.neg();
+        }
+        Some(Rational { numerator, denominator })
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        self.numerator.size_in_bytes() + self.denominator.size_in_bytes()
+    }
+
+    pub fn from_integer(value: BigInt) -> Self {
+        Rational { numerator: value, denominator: BigInt::from_i64(1) }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.numerator.is_zero()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.numerator.to_f64() / self.denominator.to_f64()
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        Rational::new(
+            self.numerator.mul(&other.denominator).add(&other.numerator.mul(&self.denominator)),
+            self.denominator.mul(&other.denominator),
+        ).expect("product of non-zero denominators is non-zero")
+    }
+
+    pub fn sub(&self, other: &Self) -> Self {
+        self.add(&Rational { numerator: other.numerator.neg(), denominator: other.denominator.clone() })
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        Rational::new(self.numerator.mul(&other.numerator), self.denominator.mul(&other.denominator))
+            .expect("product of non-zero denominators is non-zero")
+    }
+
+    /// `None` when `other` is zero.
+    pub fn div(&self, other: &Self) -> Option<Self> {
+        Rational::new(self.numerator.mul(&other.denominator), self.denominator.mul(&other.numerator))
+    }
+
+    pub fn neg(&self) -> Self {
+        Rational { numerator: self.numerator.neg(), denominator: self.denominator.clone() }
+    }
+
+    /// `None` when raising zero to a negative power.
+    pub fn pow(&self, exponent: i32) -> Option<Self> {
+        let magnitude = exponent.unsigned_abs();
+        let numerator = self.numerator.pow(magnitude);
+        let denominator = self.denominator.pow(magnitude);
+        if exponent < 0 {
+            Rational::new(denominator, numerator)
+        } else {
+            Rational::new(numerator, denominator)
+        }
+    }
+
+    pub fn floor(&self) -> BigInt {
+        self.numerator.div_rem_floor(&self.denominator).map(|(quotient, _)| quotient).unwrap_or_else(BigInt::zero)
+    }
+
+    /// Renders the value in literal syntax: `1.25d` when the decimal
+    /// expansion terminates, `1d/3d` otherwise.
+    pub fn to_literal(&self) -> String {
+        let mut denominator = self.denominator.clone();
+        let mut twos = 0;
+        let mut fives = 0;
+        for (factor, count) in [(2, &mut twos), (5, &mut fives)] {
+            let factor = BigInt::from_i64(factor);
+            while let Some((quotient, remainder)) = denominator.div_rem_floor(&factor) {
+                if !remainder.is_zero() {
+                    break;
+                }
+                denominator = quotient;
+                *count += 1;
+            }
+        }
+        if denominator != BigInt::from_i64(1) {
+            return format!("{}d/{}d", self.numerator, self.denominator);
+        }
+
+        let places = twos.max(fives);
+        let scaled = self.numerator.mul(&BigInt::pow10(places)).div_rem_floor(&self.denominator)
+            .map(|(quotient, _)| quotient)
+            .unwrap_or_else(BigInt::zero);
+        let digits = scaled.abs().to_string();
+        let sign = if scaled.is_negative() { "-" } else { "" };
+
+        if places == 0 {
+            return format!("{}{}d", sign, digits);
+        }
+        let padded = format!("{:0>width$}", digits, width = places + 1);
+        let (whole, fraction) = padded.split_at(padded.len() - places);
+        format!("{}{}.{}d", sign, whole, fraction)
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.numerator.mul(&other.denominator).cmp(&other.numerator.mul(&self.denominator))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for Rational {
+    type Err = String;
+
+    /// Parses `n`, `n.m` or `n/m`.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if let Some((numerator, denominator)) = text.split_once('/') {
+            return Rational::new(numerator.parse()?, denominator.parse()?)
+                .ok_or_else(|| format!("'{}' has a zero denominator", text));
+        }
+
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        let numerator = format!("{}{}", whole, fraction).parse::<BigInt>()?;
+        Rational::new(numerator, BigInt::pow10(fraction.len()))
+            .ok_or_else(|| format!("'{}' is not a number", text))
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+impl fmt::Debug for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+/// Requires `a >= b`.
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, limb) in a.iter().enumerate() {
+        let mut difference = *limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = if difference < 0 { 1 } else { 0 };
+        if difference < 0 {
+            difference += 1 << 32;
+        }
+        result.push(difference as u32);
+    }
+    while result.last() == Some(&0) {
+        result.pop();
+    }
+    result
+}
+
+fn mul_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u32; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, y) in b.iter().enumerate() {
+            let product = *x as u64 * *y as u64 + result[i + j] as u64 + carry;
+            result[i + j] = product as u32;
+            carry = product >> 32;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+    while result.last() == Some(&0) {
+        result.pop();
+    }
+    result
+}
+
+fn mul_small(a: &[u32], factor: u32, addend: u32) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len() + 1);
+    let mut carry = addend as u64;
+    for limb in a {
+        let product = *limb as u64 * factor as u64 + carry;
+        result.push(product as u32);
+        carry = product >> 32;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+fn div_rem_small(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder = 0u64;
+    for i in (0..a.len()).rev() {
+        let current = (remainder << 32) | a[i] as u64;
+        quotient[i] = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    while quotient.last() == Some(&0) {
+        quotient.pop();
+    }
+    (quotient, remainder as u32)
+}
+
+/// Truncating division of magnitudes; `b` must be non-zero.
+fn div_rem_magnitudes(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if b.len() == 1 {
+        let (quotient, remainder) = div_rem_small(a, b[0]);
+        let remainder = if remainder == 0 { vec![] } else { vec![remainder] };
+        return (quotient, remainder);
+    }
+    if compare_magnitudes(a, b) == Ordering::Less {
+        return (vec![], a.to_vec());
+    }
+
+    // Binary long division: slow for huge operands but simple and exact.
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder: Vec<u32> = vec![];
+    for bit in (0..a.len() * 32).rev() {
+        let mut carry = (a[bit / 32] >> (bit % 32)) & 1;
+        for limb in remainder.iter_mut() {
+            let next_carry = *limb >> 31;
+            *limb = (*limb << 1) | carry;
+            carry = next_carry;
+        }
+        if carry > 0 {
+            remainder.push(carry);
+        }
+
+        if compare_magnitudes(&remainder, b) != Ordering::Less {
+            remainder = sub_magnitudes(&remainder, b);
+            quotient[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    while quotient.last() == Some(&0) {
+        quotient.pop();
+    }
+    (quotient, remainder)
+}
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+use std::time::Duration;
+use crate::runtime::channel::{select, Channel};
+use crate::runtime::environment::Environment;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::event_loop::EventLoop;
+use crate::runtime::function::{Arguments, Function};
+use crate::runtime::operators::spread_items;
+use crate::runtime::value::Value;
+
+/// Functions every program starts with, as globals. Scripts may redefine
+/// their names.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin {
+    /// `now()`: milliseconds since the program started, on the event
+    /// loop's clock.
+    Now,
+    /// `sleep(ms)`: a promise fulfilled with `null` after `ms`.
+    Sleep,
+    /// `setTimeout(callback, ms)`: calls `callback` once after `ms`.
+    SetTimeout,
+    /// `setInterval(callback, ms)`: calls `callback` every `ms`.
+    SetInterval,
+    /// `cancel(timer)`: stops a timer; `false` if it was not pending.
+    Cancel,
+    /// `all(awaitables)`: a promise of the list of their values.
+    All,
+    /// `race(awaitables)`: a promise settled like the first one to settle.
+    Race,
+    /// `timeout(awaitable, ms)`: rejected if `awaitable` takes longer.
+    Timeout,
+    /// `channel(capacity?)`: a channel, unbounded without a capacity.
+    Channel,
+    /// `select(channels)`: a promise of the first value one of them has.
+    Select,
+}
+
+const BUILTINS: [Builtin; 10] = [
+    Builtin::Now,
+    Builtin::Sleep,
+    Builtin::SetTimeout,
+    Builtin::SetInterval,
+    Builtin::Cancel,
+    Builtin::All,
+    Builtin::Race,
+    Builtin::Timeout,
+    Builtin::Channel,
+    Builtin::Select,
+];
+
+impl Builtin {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Now => "now",
+            Builtin::Sleep => "sleep",
+            Builtin::SetTimeout => "setTimeout",
+            Builtin::SetInterval => "setInterval",
+            Builtin::Cancel => "cancel",
+            Builtin::All => "all",
+            Builtin::Race => "race",
+            Builtin::Timeout => "timeout",
+            Builtin::Channel => "channel",
+            Builtin::Select => "select",
+        }
+    }
+
+    fn params(&self) -> &'static [&'static str] {
+        match self {
+            Builtin::Now => &[],
+            Builtin::Sleep => &["ms"],
+            Builtin::SetTimeout | Builtin::SetInterval => &["callback", "ms"],
+            Builtin::Cancel => &["timer"],
+            Builtin::All | Builtin::Race => &["awaitables"],
+            Builtin::Timeout => &["awaitable", "ms"],
+            Builtin::Channel => &["capacity?"],
+            Builtin::Select => &["channels"],
+        }
+    }
+}
+
+pub fn define_builtins(environment: &mut Environment) {
+    for builtin in BUILTINS {
+        environment.define_global(builtin.name(), Value::Function(Arc::new(Function::Builtin(builtin))));
+    }
+}
+
+/// Calls a builtin; the scheduling ones work on `event_loop`.
+pub fn call_builtin(builtin: Builtin, arguments: Arguments, event_loop: &EventLoop) -> Result<Value, RuntimeError> {
+    let params = builtin.params();
+    let required = params.iter().filter(|param| !param.ends_with('?')).count();
+    if !(required..=params.len()).contains(&arguments.positional.len()) || !arguments.named.is_empty() {
+        return Err(RuntimeError::ArityMismatch {
+            name: builtin.name().to_string(),
+            expected: params.join(", "),
+            found: arguments.positional.len() + arguments.named.len(),
+        });
+    }
+
+    let mut values = arguments.positional.into_iter();
+    let mut next = || values.next().unwrap_or(Value::Null);
+    Ok(match builtin {
+        Builtin::Now => Value::Integer(i64::try_from(event_loop.now().as_millis()).unwrap_or(i64::MAX)),
+        Builtin::Sleep => Value::Promise(event_loop.sleep(milliseconds(next())?)),
+        Builtin::SetTimeout | Builtin::SetInterval => {
+            let callback = next();
+            if !matches!(callback, Value::Function(_)) {
+                return Err(RuntimeError::TypeError { message: format!("{} is not callable.", callback) });
+            }
+            let delay = milliseconds(next())?;
+            let id = event_loop.set_timer(callback, delay, builtin == Builtin::SetInterval);
+            Value::Integer(id as i64)
+        },
+        Builtin::Cancel => match next() {
+            Value::Integer(id) => Value::Boolean(u64::try_from(id).is_ok_and(|id| event_loop.cancel(id))),
+            other => return Err(RuntimeError::TypeError { message: format!("{} is not a timer.", other) }),
+        },
+        Builtin::All => Value::Promise(event_loop.all(spread_items(next())?)),
+        Builtin::Race => Value::Promise(event_loop.race(spread_items(next())?)),
+        Builtin::Timeout => {
+            let awaitable = next();
+            Value::Promise(event_loop.timeout(awaitable, milliseconds(next())?))
+        },
+        Builtin::Channel => match next() {
+            Value::Null => Value::Channel(Channel::new(None)),
+            Value::Integer(capacity) if capacity >= 0 => Value::Channel(Channel::new(Some(capacity as usize))),
+            other => return Err(RuntimeError::TypeError { message: format!("{} is not a valid channel capacity.", other) }),
+        },
+        Builtin::Select => Value::Promise(select(spread_items(next())?, event_loop)?),
+    })
+}
+
+fn milliseconds(value: Value) -> Result<Duration, RuntimeError> {
+    match value {
+        Value::Integer(ms) if ms >= 0 => Ok(Duration::from_millis(ms as u64)),
+        Value::Number(ms) if ms >= 0.0 && ms.is_finite() => Ok(Duration::from_secs_f64(ms / 1000.0)),
+        other => Err(RuntimeError::TypeError { message: format!("{} is not a valid number of milliseconds.", other) }),
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt;
+use std::sync::Arc;
+use crate::frontend::ast::Slot;
+use crate::runtime::function::Prototype;
+use crate::runtime::operators::{BinaryOp, UnaryOp};
+use crate::runtime::pattern::Pattern;
+use crate::runtime::value::Value;
+
+/// Which statement a conditional jump belongs to, so the VM can report the
+/// same truthiness error as the tree-walking interpreter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Branch {
+    If,
+    While,
+}
+
+impl fmt::Display for Branch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Branch::If => write!(f, "If"),
+            Branch::While => write!(f, "While"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    /// Pushes `constants[index]`.
+    Constant(usize),
+    Null,
+    Pop,
+    /// Pushes copies of the top that many values, in order.
+    Dup(usize),
+    /// Moves the value on top of the stack below that many values under it.
+    Bury(usize),
+    Binary(BinaryOp),
+    Unary(UnaryOp),
+    /// Absolute jump to an instruction index.
+    Jump(usize),
+    /// Pops the condition and jumps when it is falsy.
+    JumpIfFalse(usize, Branch),
+    /// Jumps when the top of the stack is not `null`, leaving it in place;
+    /// pops it otherwise. Implements `??` and `?.`.
+    JumpIfNotNull(usize),
+    /// Pops the object and pushes the field named by `constants[index]`;
+    /// `true` for `?.`.
+    GetField(usize, bool),
+    /// Global variables, named by the identifier stored in `constants[index]`.
+    DefineGlobal(usize),
+    /// Defines a `const` global, which `SET_GLOBAL` then refuses to change.
+    DefineConstant(usize),
+    GetGlobal(usize),
+    SetGlobal(usize),
+    /// Block-local variables, addressed by resolver slots.
+    DefineLocal(usize),
+    GetLocal(Slot),
+    SetLocal(Slot),
+    PushScope,
+    PopScope,
+    /// Pushes a function built from `functions[index]` that captures the
+    /// current scope.
+    Closure(usize),
+    /// Pops that many arguments and then the callee, and pushes the result.
+    Call(usize),
+    /// Call with named arguments or spreads: pops that many (name, value)
+    /// pairs, the list of positional arguments and then the callee.
+    CallWith(usize),
+    /// Pops that many values and pushes a list of them, in order.
+    BuildList(usize),
+    /// Pops a value and appends it to the list below it.
+    AppendList,
+    /// Pops a list and appends its items to the list below it.
+    ExtendList,
+    /// Pops that many (name, value) pairs and pushes an object with them as
+    /// fields, in order.
+    BuildObject(usize),
+    /// Pops the index and the object and pushes `object[index]`.
+    Index,
+    /// Pops the value and the object, sets the field named by
+    /// `constants[index]` and pushes the value back.
+    SetField(usize),
+    /// Pops the value, the index and the list, sets the item and pushes the
+    /// value back.
+    SetIndex,
+    /// Takes apart the value on top of the stack, leaving it in place, with
+    /// `patterns[index]`. Pushes the parts last to first so the first one
+    /// ends up on top; a part for a name with a default is followed by a
+    /// boolean telling whether it was found.
+    Destructure(usize),
+    /// Pops the value and suspends the generator running this chunk;
+    /// resuming it pushes the value passed to `.next()`.
+    Yield,
+    /// Replaces the value on top of the stack with a generator over it, for
+    /// a `for` loop.
+    Iterate,
+    /// Resumes the generator on top of the stack and pushes what it yielded;
+    /// once it is done, pops it and jumps to the target.
+    ForIter(usize),
+    /// Pops the awaited value. In an async function it suspends the task
+    /// until the value settles and resuming pushes the result; in the
+    /// program it runs the event loop until then.
+    Await,
+    /// Pops the closure of a `work` block, starts it on a worker thread and
+    /// pushes the promise of its result.
+    Work,
+    /// Pushes the natives registered under the namespace named by
+    /// `constants[index]`, for `interop`.
+    Interop(usize),
+    /// First instructions of a function: jumps past the code computing the
+    /// default of parameter `index` when an argument was passed for it.
+    SkipDefault(usize, usize),
+    /// Ends the chunk, or the function call, with the value on top of the stack.
+    Return,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Constant(index) => write!(f, "CONSTANT {}", index),
+            Instruction::Null => write!(f, "NULL"),
+            Instruction::Pop => write!(f, "POP"),
+            Instruction::Dup(count) => write!(f, "DUP {}", count),
+            Instruction::Bury(depth) => write!(f, "BURY {}", depth),
+            Instruction::Binary(operator) => write!(f, "BINARY {}", operator.symbol()),
+            Instruction::Unary(operator) => write!(f, "UNARY {}", operator.symbol()),
+            Instruction::Jump(target) => write!(f, "JUMP {:04}", target),
+            Instruction::JumpIfFalse(target, branch) => write!(f, "JUMP_IF_FALSE {:04} ({})", target, branch),
+            Instruction::JumpIfNotNull(target) => write!(f, "JUMP_IF_NOT_NULL {:04}", target),
+            Instruction::GetField(index, optional) => {
+                write!(f, "GET_FIELD {}{}", index, if *optional { " (optional)" } else { "" })
+            },
+            Instruction::DefineGlobal(index) => write!(f, "DEFINE_GLOBAL {}", index),
+            Instruction::DefineConstant(index) => write!(f, "DEFINE_CONSTANT {}", index),
+            Instruction::GetGlobal(index) => write!(f, "GET_GLOBAL {}", index),
+            Instruction::SetGlobal(index) => write!(f, "SET_GLOBAL {}", index),
+            Instruction::DefineLocal(index) => write!(f, "DEFINE_LOCAL {}", index),
+            Instruction::GetLocal(slot) => write!(f, "GET_LOCAL {}:{}", slot.depth, slot.index),
+            Instruction::SetLocal(slot) => write!(f, "SET_LOCAL {}:{}", slot.depth, slot.index),
+            Instruction::PushScope => write!(f, "PUSH_SCOPE"),
+            Instruction::PopScope => write!(f, "POP_SCOPE"),
+            Instruction::Closure(index) => write!(f, "CLOSURE {}", index),
+            Instruction::Call(argc) => write!(f, "CALL {}", argc),
+            Instruction::CallWith(named) => write!(f, "CALL_WITH {}", named),
+            Instruction::BuildList(count) => write!(f, "BUILD_LIST {}", count),
+            Instruction::AppendList => write!(f, "APPEND_LIST"),
+            Instruction::ExtendList => write!(f, "EXTEND_LIST"),
+            Instruction::BuildObject(count) => write!(f, "BUILD_OBJECT {}", count),
+            Instruction::Index => write!(f, "INDEX"),
+            Instruction::SetField(index) => write!(f, "SET_FIELD {}", index),
+            Instruction::SetIndex => write!(f, "SET_INDEX"),
+            Instruction::Destructure(index) => write!(f, "DESTRUCTURE {}", index),
+            Instruction::Yield => write!(f, "YIELD"),
+            Instruction::Iterate => write!(f, "ITERATE"),
+            Instruction::ForIter(target) => write!(f, "FOR_ITER {:04}", target),
+            Instruction::Await => write!(f, "AWAIT"),
+            Instruction::Work => write!(f, "WORK"),
+            Instruction::Interop(index) => write!(f, "INTEROP {}", index),
+            Instruction::SkipDefault(index, target) => write!(f, "SKIP_DEFAULT {} {:04}", index, target),
+            Instruction::Return => write!(f, "RETURN"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Chunk {
+    pub code: Vec<Instruction>,
+    /// Source line of each instruction, parallel to `code`.
+    pub lines: Vec<usize>,
+    pub constants: Vec<Value>,
+    /// Functions defined in this chunk, each compiled to its own chunk.
+    pub functions: Vec<Arc<Prototype>>,
+    /// Destructuring patterns used by this chunk.
+    pub patterns: Vec<Pattern>,
+}
+
+impl Chunk {
+    pub fn emit(&mut self, instruction: Instruction, line: usize) -> usize {
+        self.code.push(instruction);
+        self.lines.push(line);
+        self.code.len() - 1
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        if let Some(index) = self.constants.iter().position(|constant| constant == &value) {
+            return index;
+        }
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    /// Rewrites the target of the jump emitted at `at` to the next instruction.
+    pub fn patch_jump(&mut self, at: usize) {
+        let target = self.code.len();
+        match &mut self.code[at] {
+            Instruction::Jump(offset)
+            | Instruction::JumpIfFalse(offset, _)
+            | Instruction::JumpIfNotNull(offset)
+            | Instruction::ForIter(offset)
+            | Instruction::SkipDefault(_, offset) => *offset = target,
+            _ => unreachable!("patch_jump called on a non-jump instruction"),
+        }
+    }
+}
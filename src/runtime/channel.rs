@@ -0,0 +1,290 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use crate::runtime::error::RuntimeError;
+use crate::runtime::event_loop::{EventLoop, Promise};
+use crate::runtime::function::Arguments;
+use crate::runtime::value::{DeepCopy, Object, Value};
+
+/// The methods of a channel value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelMethod {
+    Send,
+    Recv,
+    TryRecv,
+    Close,
+}
+
+impl ChannelMethod {
+    pub fn of(name: &str) -> Option<Self> {
+        match name {
+            "send" => Some(ChannelMethod::Send),
+            "recv" => Some(ChannelMethod::Recv),
+            "tryRecv" => Some(ChannelMethod::TryRecv),
+            "close" => Some(ChannelMethod::Close),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelMethod::Send => "send",
+            ChannelMethod::Recv => "recv",
+            ChannelMethod::TryRecv => "tryRecv",
+            ChannelMethod::Close => "close",
+        }
+    }
+
+    fn params(&self) -> &'static [&'static str] {
+        match self {
+            ChannelMethod::Send => &["value"],
+            _ => &[],
+        }
+    }
+}
+
+/// A promise some operation waits on, and the loop it belongs to.
+struct Waiting {
+    event_loop: EventLoop,
+    promise: Promise,
+}
+
+impl Waiting {
+    fn complete(self, result: Result<Value, RuntimeError>) {
+        self.event_loop.complete_wait(&self.promise, result);
+    }
+}
+
+/// A `recv` waiting for a value. The receivers of one `select` wait on all
+/// its channels at once and share `claimed`, so only the first value to
+/// arrive is taken.
+struct Receiver {
+    waiting: Waiting,
+    select: Option<(Arc<AtomicBool>, usize)>,
+}
+
+impl Receiver {
+    /// Whether this receiver still wants a value; claims it if so.
+    fn claim(&self) -> bool {
+        self.select.as_ref().is_none_or(|(claimed, _)| !claimed.swap(true, Ordering::SeqCst))
+    }
+
+    fn deliver(self, value: Value, done: bool) {
+        let index = self.select.as_ref().map(|(_, index)| *index);
+        self.waiting.complete(Ok(received(index, value, done)));
+    }
+}
+
+struct ChannelInner {
+    buffer: VecDeque<Value>,
+    /// How many values the buffer holds before `send` waits; `None` for an
+    /// unbounded channel. With 0 every `send` waits for a `recv`.
+    capacity: Option<usize>,
+    closed: bool,
+    receivers: VecDeque<Receiver>,
+    /// Senders waiting for room, with the value each one sends.
+    senders: VecDeque<(Waiting, Value)>,
+}
+
+impl ChannelInner {
+    fn has_room(&self) -> bool {
+        self.capacity.is_none_or(|capacity| self.buffer.len() < capacity)
+    }
+
+    /// The next value, if one is ready: from the buffer, which then takes
+    /// the value of the first waiting sender, or straight from that sender.
+    /// `done` once the channel is closed and drained.
+    fn take(&mut self) -> Option<(Value, bool)> {
+        if let Some(value) = self.buffer.pop_front() {
+            if let Some((sender, next)) = self.senders.pop_front() {
+                self.buffer.push_back(next);
+                sender.complete(Ok(Value::Null));
+            }
+            return Some((value, false));
+        }
+        if let Some((sender, value)) = self.senders.pop_front() {
+            sender.complete(Ok(Value::Null));
+            return Some((value, false));
+        }
+        self.closed.then_some((Value::Null, true))
+    }
+}
+
+/// A queue of values between tasks and workers. Values are copied on
+/// `send`, like everything that crosses into a worker, so the receiver
+/// shares nothing with the sender; the channel itself is shared by every
+/// copy of it. `send` and `recv` return promises, which `await` waits on,
+/// also inside `work` blocks.
+#[derive(Clone)]
+pub struct Channel(Arc<Mutex<ChannelInner>>);
+
+impl Channel {
+    pub fn new(capacity: Option<usize>) -> Self {
+        Channel(Arc::new(Mutex::new(ChannelInner {
+            buffer: VecDeque::new(),
+            capacity,
+            closed: false,
+            receivers: VecDeque::new(),
+            senders: VecDeque::new(),
+        })))
+    }
+
+    /// A promise fulfilled with `null` once the value is in the channel,
+    /// or rejected if the channel is closed, then or before.
+    pub fn send(&self, value: &Value, event_loop: &EventLoop) -> Promise {
+        let value = DeepCopy::default().value(value);
+        let mut inner = self.lock();
+        if inner.closed {
+            return Promise::settled(Err(RuntimeError::ChannelClosed));
+        }
+        while let Some(receiver) = inner.receivers.pop_front() {
+            if receiver.claim() {
+                receiver.deliver(value, false);
+                return Promise::settled(Ok(Value::Null));
+            }
+        }
+        if inner.has_room() {
+            inner.buffer.push_back(value);
+            return Promise::settled(Ok(Value::Null));
+        }
+        let promise = event_loop.start_wait();
+        inner.senders.push_back((Waiting { event_loop: event_loop.clone(), promise: promise.clone() }, value));
+        promise
+    }
+
+    /// A promise of the next value, as `{ value, done }`; `done` is true
+    /// once the channel is closed and every value sent was received.
+    pub fn recv(&self, event_loop: &EventLoop) -> Promise {
+        let mut inner = self.lock();
+        if let Some((value, done)) = inner.take() {
+            return Promise::settled(Ok(received(None, value, done)));
+        }
+        let promise = event_loop.start_wait();
+        let waiting = Waiting { event_loop: event_loop.clone(), promise: promise.clone() };
+        inner.receivers.push_back(Receiver { waiting, select: None });
+        promise
+    }
+
+    /// `{ value, done }` like `recv`, if a value is ready; `null` otherwise.
+    pub fn try_recv(&self) -> Value {
+        match self.lock().take() {
+            Some((value, done)) => received(None, value, done),
+            None => Value::Null,
+        }
+    }
+
+    /// Ends the channel: `send` fails from now on, waiting senders fail,
+    /// and once the values already sent are received `recv` gives
+    /// `done: true`. Returns `false` if it was closed already.
+    pub fn close(&self) -> bool {
+        let mut inner = self.lock();
+        if inner.closed {
+            return false;
+        }
+        inner.closed = true;
+        for receiver in std::mem::take(&mut inner.receivers) {
+            if receiver.claim() {
+                receiver.deliver(Value::Null, true);
+            }
+        }
+        for (sender, _) in std::mem::take(&mut inner.senders) {
+            sender.complete(Err(RuntimeError::ChannelClosed));
+        }
+        true
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ChannelInner> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Channels are only equal to themselves.
+impl PartialEq for Channel {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = self.lock();
+        match (inner.closed, inner.capacity) {
+            (true, _) => write!(f, "<channel closed>"),
+            (false, Some(capacity)) => write!(f, "<channel {}/{}>", inner.buffer.len(), capacity),
+            (false, None) => write!(f, "<channel {}>", inner.buffer.len()),
+        }
+    }
+}
+
+impl fmt::Debug for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Calls `channel.method(...)`.
+pub fn call_channel_method(
+    channel: &Channel,
+    method: ChannelMethod,
+    arguments: Arguments,
+    event_loop: &EventLoop,
+) -> Result<Value, RuntimeError> {
+    let params = method.params();
+    if arguments.positional.len() != params.len() || !arguments.named.is_empty() {
+        return Err(RuntimeError::ArityMismatch {
+            name: method.name().to_string(),
+            expected: params.join(", "),
+            found: arguments.positional.len() + arguments.named.len(),
+        });
+    }
+    Ok(match method {
+        ChannelMethod::Send => Value::Promise(channel.send(&arguments.positional[0], event_loop)),
+        ChannelMethod::Recv => Value::Promise(channel.recv(event_loop)),
+        ChannelMethod::TryRecv => channel.try_recv(),
+        ChannelMethod::Close => Value::Boolean(channel.close()),
+    })
+}
+
+/// `select(channels)`: a promise of the first value any of the channels
+/// has, as `{ index, value, done }` with the position of its channel.
+/// Channels that already have a value are tried in order.
+pub fn select(channels: Vec<Value>, event_loop: &EventLoop) -> Result<Promise, RuntimeError> {
+    let channels = channels.into_iter().map(|value| match value {
+        Value::Channel(channel) => Ok(channel),
+        other => Err(RuntimeError::TypeError { message: format!("{} is not a channel.", other) }),
+    }).collect::<Result<Vec<_>, _>>()?;
+
+    let promise = event_loop.start_wait();
+    let claimed = Arc::new(AtomicBool::new(false));
+    for (index, channel) in channels.iter().enumerate() {
+        let mut inner = channel.lock();
+        // Claimed by a value sent to one of the channels already waited on.
+        if claimed.load(Ordering::SeqCst) {
+            break;
+        }
+        let waiting = Waiting { event_loop: event_loop.clone(), promise: promise.clone() };
+        let receiver = Receiver { waiting, select: Some((claimed.clone(), index)) };
+        if inner.buffer.is_empty() && inner.senders.is_empty() && !inner.closed {
+            inner.receivers.push_back(receiver);
+            continue;
+        }
+        if receiver.claim() {
+            let (value, done) = inner.take().unwrap_or((Value::Null, true));
+            receiver.deliver(value, done);
+        }
+        break;
+    }
+    Ok(promise)
+}
+
+/// What `recv` and `select` settle with.
+fn received(index: Option<usize>, value: Value, done: bool) -> Value {
+    let mut fields = vec![];
+    if let Some(index) = index {
+        fields.push(("index".to_string(), Value::Integer(index as i64)));
+    }
+    fields.push(("value".to_string(), value));
+    fields.push(("done".to_string(), Value::Boolean(done)));
+    Value::Object(Object::new(fields))
+}
@@ -0,0 +1,63 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Where the event loop reads the time from, measured from when the clock
+/// was created.
+pub trait Clock: Send {
+    fn now(&self) -> Duration;
+
+    /// Waits until `now` is at least `deadline`.
+    fn advance_to(&mut self, deadline: Duration);
+
+    /// How long something else may be waited for before `deadline` comes;
+    /// `None` if the clock only moves through `advance_to`.
+    fn time_until(&self, deadline: Duration) -> Option<Duration>;
+}
+
+/// Real time, from a clock that never goes backwards.
+pub struct MonotonicClock {
+    start: Instant,
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        MonotonicClock { start: Instant::now() }
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn advance_to(&mut self, deadline: Duration) {
+        thread::sleep(deadline.saturating_sub(self.now()));
+    }
+
+    fn time_until(&self, deadline: Duration) -> Option<Duration> {
+        Some(deadline.saturating_sub(self.now()))
+    }
+}
+
+/// Time that only passes when the event loop waits for a timer, and then
+/// jumps straight to it: timers fire in the same order as with the real
+/// clock, without any waiting, and every run sees the same times. Work on
+/// other threads takes no time at all.
+#[derive(Default)]
+pub struct VirtualClock {
+    now: Duration,
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        self.now
+    }
+
+    fn advance_to(&mut self, deadline: Duration) {
+        self.now = self.now.max(deadline);
+    }
+
+    fn time_until(&self, _deadline: Duration) -> Option<Duration> {
+        None
+    }
+}
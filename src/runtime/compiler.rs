@@ -0,0 +1,580 @@
+use std::sync::Arc;
+use crate::frontend::ast::{Program, Slot, Stmt, StmtType};
+use crate::runtime::bytecode::{Branch, Chunk, Instruction};
+use crate::runtime::error::RuntimeError;
+use crate::runtime::function::{FunctionBody, Prototype};
+use crate::runtime::operators::{BinaryOp, UnaryOp};
+use crate::runtime::pattern::Pattern;
+use crate::runtime::value::{literal_value, Value};
+
+pub fn compile(program: &Program) -> Result<Chunk, RuntimeError> {
+    let mut chunk = Chunk::default();
+    compile_block(&mut chunk, &program.body)?;
+    let last_line = program.body.last().map_or(1, |stmt| stmt.line);
+    chunk.emit(Instruction::Return, last_line);
+    Ok(chunk)
+}
+
+/// Compiles a sequence of statements leaving only the value of the last one
+/// on the stack (or `null` for an empty sequence), like `evaluate_block`.
+fn compile_block(chunk: &mut Chunk, body: &[Stmt]) -> Result<(), RuntimeError> {
+    if body.is_empty() {
+        let line = chunk.lines.last().copied().unwrap_or(1);
+        chunk.emit(Instruction::Null, line);
+        return Ok(());
+    }
+
+    for (index, stmt) in body.iter().enumerate() {
+        compile_stmt(chunk, stmt)?;
+        if index + 1 < body.len() {
+            chunk.emit(Instruction::Pop, stmt.line);
+        }
+    }
+    Ok(())
+}
+
+fn compile_stmt(chunk: &mut Chunk, stmt: &Stmt) -> Result<(), RuntimeError> {
+    match stmt.kind {
+        StmtType::Program => {
+            Err(RuntimeError::TypeError { message: "Cannot evaluate Program node directly as a statement.".to_string() })
+        },
+        StmtType::NumericLiteral
+        | StmtType::IntegerLiteral
+        | StmtType::RationalLiteral
+        | StmtType::BooleanLiteral
+        | StmtType::NullLiteral => {
+            let index = chunk.add_constant(literal_value(stmt)?);
+            chunk.emit(Instruction::Constant(index), stmt.line);
+            Ok(())
+        },
+        StmtType::BinaryExpr => compile_binary_expr(chunk, stmt),
+        StmtType::UnaryExpr => compile_unary_expr(chunk, stmt),
+        StmtType::CoalesceExpr => compile_coalesce_expr(chunk, stmt),
+        StmtType::MemberExpr | StmtType::IndexExpr | StmtType::CallExpression => {
+            let mut skips = Vec::new();
+            compile_chain(chunk, stmt, &mut skips)?;
+            for skip in skips {
+                chunk.patch_jump(skip);
+            }
+            Ok(())
+        },
+        StmtType::ListLiteral => compile_elements(chunk, stmt.body.as_deref().unwrap_or_default(), stmt.line),
+        StmtType::ObjectLiteral => {
+            let properties = stmt.body.as_deref().unwrap_or_default();
+            for property in properties {
+                let value_ast = property.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                    message: "Object property missing value.".to_string()
+                })?;
+                let name = name_constant(chunk, property);
+                chunk.emit(Instruction::Constant(name), property.line);
+                compile_stmt(chunk, value_ast)?;
+            }
+            chunk.emit(Instruction::BuildObject(properties.len()), stmt.line);
+            Ok(())
+        },
+        StmtType::Identifier => {
+            let instruction = match stmt.slot {
+                Some(slot) => Instruction::GetLocal(slot),
+                None => Instruction::GetGlobal(name_constant(chunk, stmt)),
+            };
+            chunk.emit(instruction, stmt.line);
+            Ok(())
+        },
+        StmtType::VariableDeclaration => {
+            let initializer = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                message: "Variable declaration missing initializer.".to_string()
+            })?;
+            compile_stmt(chunk, initializer)?;
+            let constant = stmt.operator.is_some();
+            if let Some(pattern) = &stmt.left {
+                return compile_pattern(chunk, pattern, Some(constant));
+            }
+            let instruction = define_instruction(chunk, stmt, constant);
+            chunk.emit(instruction, stmt.line);
+            Ok(())
+        },
+        StmtType::Assignment => {
+            let value_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                message: "Assignment missing value.".to_string()
+            })?;
+            compile_stmt(chunk, value_ast)?;
+            if let Some(pattern) = &stmt.left {
+                return compile_pattern(chunk, pattern, None);
+            }
+            let instruction = match stmt.slot {
+                Some(slot) => Instruction::SetLocal(slot),
+                None => Instruction::SetGlobal(name_constant(chunk, stmt)),
+            };
+            chunk.emit(instruction, stmt.line);
+            Ok(())
+        },
+        StmtType::MemberAssignment => compile_member_assignment(chunk, stmt),
+        StmtType::PostfixUpdate => compile_postfix_update(chunk, stmt),
+        StmtType::FunctionDeclaration => {
+            compile_function(chunk, stmt)?;
+            let instruction = match stmt.slot {
+                Some(slot) => Instruction::DefineLocal(slot.index),
+                None => Instruction::DefineGlobal(name_constant(chunk, stmt)),
+            };
+            chunk.emit(instruction, stmt.line);
+            Ok(())
+        },
+        StmtType::FunctionExpression => compile_function(chunk, stmt),
+        StmtType::ReturnStmt => {
+            match &stmt.right {
+                Some(value_ast) => compile_stmt(chunk, value_ast)?,
+                None => {
+                    chunk.emit(Instruction::Null, stmt.line);
+                },
+            }
+            chunk.emit(Instruction::Return, stmt.line);
+            Ok(())
+        },
+        StmtType::YieldExpr => {
+            match &stmt.right {
+                Some(value_ast) => compile_stmt(chunk, value_ast)?,
+                None => {
+                    chunk.emit(Instruction::Null, stmt.line);
+                },
+            }
+            chunk.emit(Instruction::Yield, stmt.line);
+            Ok(())
+        },
+        StmtType::AwaitExpr => {
+            let operand_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                message: "Await expression missing operand.".to_string()
+            })?;
+            compile_stmt(chunk, operand_ast)?;
+            chunk.emit(Instruction::Await, stmt.line);
+            Ok(())
+        },
+        StmtType::WorkExpr => {
+            let function_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                message: "Work block missing its body.".to_string()
+            })?;
+            compile_stmt(chunk, function_ast)?;
+            chunk.emit(Instruction::Work, stmt.line);
+            Ok(())
+        },
+        StmtType::InteropExpr => {
+            let name = name_constant(chunk, stmt);
+            chunk.emit(Instruction::Interop(name), stmt.line);
+            Ok(())
+        },
+        StmtType::IfStmt => compile_if_stmt(chunk, stmt),
+        StmtType::WhileStmt => compile_while_stmt(chunk, stmt),
+        StmtType::ForInStmt => compile_for_in_stmt(chunk, stmt),
+        StmtType::BlockStmt => {
+            let body = stmt.body.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                message: "BlockStmt is missing its body.".to_string()
+            })?;
+            chunk.emit(Instruction::PushScope, stmt.line);
+            compile_block(chunk, body)?;
+            chunk.emit(Instruction::PopScope, stmt.line);
+            Ok(())
+        },
+        _ => Err(RuntimeError::TypeError {
+            message: format!("{:?} is not supported by the vm backend.", stmt.kind)
+        }),
+    }
+}
+
+fn name_constant(chunk: &mut Chunk, stmt: &Stmt) -> usize {
+    chunk.add_constant(Value::Identifier(stmt.value.clone().unwrap_or_default()))
+}
+
+/// Defines the variable `stmt` declares from the value on top of the stack.
+fn define_instruction(chunk: &mut Chunk, stmt: &Stmt, constant: bool) -> Instruction {
+    match stmt.slot {
+        Some(slot) => Instruction::DefineLocal(slot.index),
+        None if constant => Instruction::DefineConstant(name_constant(chunk, stmt)),
+        None => Instruction::DefineGlobal(name_constant(chunk, stmt)),
+    }
+}
+
+fn compile_member_assignment(chunk: &mut Chunk, stmt: &Stmt) -> Result<(), RuntimeError> {
+    let target = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "Member assignment missing target.".to_string()
+    })?;
+    let value_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "Member assignment missing value.".to_string()
+    })?;
+
+    let (get, set) = compile_member_target(chunk, target)?;
+    if let Some(operator) = &stmt.operator {
+        get_member(chunk, &get, stmt.line);
+        compile_stmt(chunk, value_ast)?;
+        chunk.emit(Instruction::Binary(operator.parse::<BinaryOp>()?), stmt.line);
+    } else {
+        compile_stmt(chunk, value_ast)?;
+    }
+    chunk.emit(set, stmt.line);
+    Ok(())
+}
+
+/// Pushes the object of a field or item about to be assigned to, and the
+/// index of an item, and returns the instructions reading and writing it.
+fn compile_member_target(chunk: &mut Chunk, target: &Stmt) -> Result<(Instruction, Instruction), RuntimeError> {
+    let object_ast = target.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "Member assignment missing object.".to_string()
+    })?;
+
+    compile_stmt(chunk, object_ast)?;
+    match (&target.kind, &target.right) {
+        (StmtType::IndexExpr, Some(index_ast)) => {
+            compile_stmt(chunk, index_ast)?;
+            Ok((Instruction::Index, Instruction::SetIndex))
+        },
+        _ => {
+            let field = name_constant(chunk, target);
+            Ok((Instruction::GetField(field, false), Instruction::SetField(field)))
+        },
+    }
+}
+
+/// Reads the member whose object, and index, are on top of the stack,
+/// leaving them in place below it for the write.
+fn get_member(chunk: &mut Chunk, get: &Instruction, line: usize) -> usize {
+    let operands = if *get == Instruction::Index { 2 } else { 1 };
+    chunk.emit(Instruction::Dup(operands), line);
+    chunk.emit(get.clone(), line);
+    operands
+}
+
+fn compile_binary_expr(chunk: &mut Chunk, stmt: &Stmt) -> Result<(), RuntimeError> {
+    let left_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "Binary expression missing left operand.".to_string()
+    })?;
+
+    let right_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "Binary expression missing right operand.".to_string()
+    })?;
+
+    let operator = stmt.operator.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "Binary expression missing operator.".to_string()
+    })?;
+
+    compile_stmt(chunk, left_ast)?;
+    compile_stmt(chunk, right_ast)?;
+    chunk.emit(Instruction::Binary(operator.parse::<BinaryOp>()?), stmt.line);
+    Ok(())
+}
+
+/// Pushes a list of `elements`, expanding spreads. Without spreads the
+/// items are collected with a single `BUILD_LIST`.
+fn compile_elements(chunk: &mut Chunk, elements: &[Stmt], line: usize) -> Result<(), RuntimeError> {
+    if elements.iter().all(|element| element.kind != StmtType::Spread) {
+        for element in elements {
+            compile_stmt(chunk, element)?;
+        }
+        chunk.emit(Instruction::BuildList(elements.len()), line);
+        return Ok(());
+    }
+
+    chunk.emit(Instruction::BuildList(0), line);
+    for element in elements {
+        match (&element.kind, &element.right) {
+            (StmtType::Spread, Some(list)) => {
+                compile_stmt(chunk, list)?;
+                chunk.emit(Instruction::ExtendList, element.line);
+            },
+            _ => {
+                compile_stmt(chunk, element)?;
+                chunk.emit(Instruction::AppendList, element.line);
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Takes apart the value on top of the stack, which stays there, and
+/// declares or assigns each name of the pattern in turn, or takes the part
+/// apart with a nested pattern; `declaration` is as in the interpreter's
+/// `bind_pattern`. An element with a default pops the "found" flag
+/// `DESTRUCTURE` pushed above its part and replaces the part with the
+/// default when it is `false`.
+fn compile_pattern(chunk: &mut Chunk, pattern_ast: &Stmt, declaration: Option<bool>) -> Result<(), RuntimeError> {
+    let pattern = Pattern::of(pattern_ast).ok_or_else(|| RuntimeError::TypeError {
+        message: format!("{:?} is not a pattern.", pattern_ast.kind)
+    })?;
+    chunk.patterns.push(pattern);
+    chunk.emit(Instruction::Destructure(chunk.patterns.len() - 1), pattern_ast.line);
+
+    for name in pattern_ast.body.iter().flatten() {
+        if let Some(default) = &name.right {
+            let default_jump = chunk.emit(Instruction::JumpIfFalse(0, Branch::If), name.line);
+            let store_jump = chunk.emit(Instruction::Jump(0), name.line);
+            chunk.patch_jump(default_jump);
+            chunk.emit(Instruction::Pop, name.line);
+            compile_stmt(chunk, default)?;
+            chunk.patch_jump(store_jump);
+        }
+        if matches!(name.kind, StmtType::ListPattern | StmtType::ObjectPattern) {
+            compile_pattern(chunk, name, declaration)?;
+            chunk.emit(Instruction::Pop, name.line);
+            continue;
+        }
+        let instruction = match (name.slot, declaration) {
+            (_, Some(constant)) => define_instruction(chunk, name, constant),
+            (Some(slot), None) => Instruction::SetLocal(slot),
+            (None, None) => Instruction::SetGlobal(name_constant(chunk, name)),
+        };
+        chunk.emit(instruction, name.line);
+        chunk.emit(Instruction::Pop, name.line);
+    }
+    Ok(())
+}
+
+/// Plain positional calls use `CALL`. With named arguments or spreads the
+/// positional arguments are collected in a list and followed by the named
+/// ones, which are therefore evaluated last, as in the interpreter.
+/// Compiles a member, an index or a call, whose object can be one of them
+/// in turn. A `?.` that finds `null` skips the rest of the whole chain,
+/// with `null` as its value, through a `JUMP` added to `skips`.
+fn compile_chain(chunk: &mut Chunk, stmt: &Stmt, skips: &mut Vec<usize>) -> Result<(), RuntimeError> {
+    match stmt.kind {
+        StmtType::MemberExpr => {
+            let object_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                message: "Member expression missing object.".to_string()
+            })?;
+            compile_chain(chunk, object_ast, skips)?;
+            let field = name_constant(chunk, stmt);
+            let optional = stmt.operator.as_deref() == Some("?.");
+            if optional {
+                let present = chunk.emit(Instruction::JumpIfNotNull(0), stmt.line);
+                chunk.emit(Instruction::Null, stmt.line);
+                skips.push(chunk.emit(Instruction::Jump(0), stmt.line));
+                chunk.patch_jump(present);
+            }
+            chunk.emit(Instruction::GetField(field, optional), stmt.line);
+            Ok(())
+        },
+        StmtType::IndexExpr => {
+            let object_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                message: "Index expression missing object.".to_string()
+            })?;
+            let index_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                message: "Index expression missing index.".to_string()
+            })?;
+            compile_chain(chunk, object_ast, skips)?;
+            compile_stmt(chunk, index_ast)?;
+            chunk.emit(Instruction::Index, stmt.line);
+            Ok(())
+        },
+        StmtType::CallExpression => compile_call_expr(chunk, stmt, skips),
+        _ => compile_stmt(chunk, stmt),
+    }
+}
+
+fn compile_call_expr(chunk: &mut Chunk, stmt: &Stmt, skips: &mut Vec<usize>) -> Result<(), RuntimeError> {
+    let callee_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "Call expression missing callee.".to_string()
+    })?;
+    compile_chain(chunk, callee_ast, skips)?;
+
+    let arguments = stmt.body.as_deref().unwrap_or_default();
+    if arguments.iter().all(|argument| !matches!(argument.kind, StmtType::NamedArgument | StmtType::Spread)) {
+        for argument in arguments {
+            compile_stmt(chunk, argument)?;
+        }
+        chunk.emit(Instruction::Call(arguments.len()), stmt.line);
+        return Ok(());
+    }
+
+    let (named, positional): (Vec<Stmt>, Vec<Stmt>) = arguments.iter().cloned()
+        .partition(|argument| argument.kind == StmtType::NamedArgument);
+    compile_elements(chunk, &positional, stmt.line)?;
+    for argument in &named {
+        let value_ast = argument.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Named argument missing value.".to_string()
+        })?;
+        let name = name_constant(chunk, argument);
+        chunk.emit(Instruction::Constant(name), argument.line);
+        compile_stmt(chunk, value_ast)?;
+    }
+    chunk.emit(Instruction::CallWith(named.len()), stmt.line);
+    Ok(())
+}
+
+/// Compiles the body into a chunk of its own and emits the `CLOSURE` that
+/// creates the function at run time. The chunk starts by computing the
+/// defaults of the parameters that got no argument.
+fn compile_function(chunk: &mut Chunk, stmt: &Stmt) -> Result<(), RuntimeError> {
+    let body_ast = stmt.consequent.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "Function missing its body.".to_string()
+    })?;
+
+    let mut body = Chunk::default();
+    for (index, param) in stmt.body.iter().flatten().enumerate() {
+        if let Some(default) = &param.right {
+            let skip = body.emit(Instruction::SkipDefault(index, 0), param.line);
+            compile_stmt(&mut body, default)?;
+            body.emit(Instruction::SetLocal(Slot { depth: 0, index }), param.line);
+            body.emit(Instruction::Pop, param.line);
+            body.patch_jump(skip);
+        }
+    }
+    compile_stmt(&mut body, body_ast)?;
+    body.emit(Instruction::Return, body_ast.line);
+
+    chunk.functions.push(Arc::new(Prototype {
+        name: stmt.value.clone(),
+        kind: Prototype::kind_of(stmt),
+        params: Prototype::params_of(stmt),
+        body: FunctionBody::Bytecode(body),
+    }));
+    chunk.emit(Instruction::Closure(chunk.functions.len() - 1), stmt.line);
+    Ok(())
+}
+
+fn compile_unary_expr(chunk: &mut Chunk, stmt: &Stmt) -> Result<(), RuntimeError> {
+    let operand_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "Unary expression missing operand.".to_string()
+    })?;
+
+    let operator = stmt.operator.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "Unary expression missing operator.".to_string()
+    })?;
+
+    compile_stmt(chunk, operand_ast)?;
+    chunk.emit(Instruction::Unary(operator.parse::<UnaryOp>()?), stmt.line);
+    Ok(())
+}
+
+fn compile_coalesce_expr(chunk: &mut Chunk, stmt: &Stmt) -> Result<(), RuntimeError> {
+    let left_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "'??' expression missing left operand.".to_string()
+    })?;
+
+    let right_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "'??' expression missing right operand.".to_string()
+    })?;
+
+    compile_stmt(chunk, left_ast)?;
+    let end_jump = chunk.emit(Instruction::JumpIfNotNull(0), stmt.line);
+    compile_stmt(chunk, right_ast)?;
+    chunk.patch_jump(end_jump);
+    Ok(())
+}
+
+/// Leaves the previous value on the stack: it is read twice, once to be
+/// kept and once to be updated and stored.
+fn compile_postfix_update(chunk: &mut Chunk, stmt: &Stmt) -> Result<(), RuntimeError> {
+    let operator = stmt.operator.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "Postfix update missing operator.".to_string()
+    })?;
+
+    if let Some(target) = &stmt.left {
+        let (get, set) = compile_member_target(chunk, target)?;
+        let operands = get_member(chunk, &get, stmt.line);
+        let one = chunk.add_constant(Value::Integer(1));
+        // Keeps the previous value under the object for the result.
+        chunk.emit(Instruction::Dup(1), stmt.line);
+        chunk.emit(Instruction::Bury(operands + 1), stmt.line);
+        chunk.emit(Instruction::Constant(one), stmt.line);
+        chunk.emit(Instruction::Binary(operator.parse::<BinaryOp>()?), stmt.line);
+        chunk.emit(set, stmt.line);
+        chunk.emit(Instruction::Pop, stmt.line);
+        return Ok(());
+    }
+    let (get, set) = match stmt.slot {
+        Some(slot) => (Instruction::GetLocal(slot), Instruction::SetLocal(slot)),
+        None => {
+            let name = name_constant(chunk, stmt);
+            (Instruction::GetGlobal(name), Instruction::SetGlobal(name))
+        },
+    };
+    let one = chunk.add_constant(Value::Integer(1));
+
+    chunk.emit(get.clone(), stmt.line);
+    chunk.emit(get, stmt.line);
+    chunk.emit(Instruction::Constant(one), stmt.line);
+    chunk.emit(Instruction::Binary(operator.parse::<BinaryOp>()?), stmt.line);
+    chunk.emit(set, stmt.line);
+    chunk.emit(Instruction::Pop, stmt.line);
+    Ok(())
+}
+
+fn compile_if_stmt(chunk: &mut Chunk, stmt: &Stmt) -> Result<(), RuntimeError> {
+    let condition_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "If statement missing condition.".to_string()
+    })?;
+
+    let consequent_ast = stmt.consequent.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "If statement missing consequent block.".to_string()
+    })?;
+
+    compile_stmt(chunk, condition_ast)?;
+    let else_jump = chunk.emit(Instruction::JumpIfFalse(0, Branch::If), stmt.line);
+
+    compile_stmt(chunk, consequent_ast)?;
+    let end_jump = chunk.emit(Instruction::Jump(0), stmt.line);
+
+    chunk.patch_jump(else_jump);
+    match &stmt.right {
+        Some(alternate) => compile_stmt(chunk, alternate)?,
+        None => {
+            chunk.emit(Instruction::Null, stmt.line);
+        },
+    }
+    chunk.patch_jump(end_jump);
+    Ok(())
+}
+
+fn compile_while_stmt(chunk: &mut Chunk, stmt: &Stmt) -> Result<(), RuntimeError> {
+    let condition_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "While statement missing condition.".to_string()
+    })?;
+
+    let consequent_ast = stmt.consequent.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "While statement missing consequent block.".to_string()
+    })?;
+
+    let loop_start = chunk.code.len();
+    compile_stmt(chunk, condition_ast)?;
+    let exit_jump = chunk.emit(Instruction::JumpIfFalse(0, Branch::While), stmt.line);
+
+    compile_stmt(chunk, consequent_ast)?;
+    chunk.emit(Instruction::Pop, stmt.line);
+    chunk.emit(Instruction::Jump(loop_start), stmt.line);
+
+    chunk.patch_jump(exit_jump);
+    chunk.emit(Instruction::Null, stmt.line);
+    Ok(())
+}
+
+/// The iterator stays on the stack for the whole loop. Each iteration gets
+/// a scope of its own for the loop variable, around the body's block.
+fn compile_for_in_stmt(chunk: &mut Chunk, stmt: &Stmt) -> Result<(), RuntimeError> {
+    let iterable_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "For statement missing iterable.".to_string()
+    })?;
+
+    let consequent_ast = stmt.consequent.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: "For statement missing consequent block.".to_string()
+    })?;
+
+    compile_stmt(chunk, iterable_ast)?;
+    chunk.emit(Instruction::Iterate, stmt.line);
+
+    let loop_start = chunk.code.len();
+    let exit_jump = chunk.emit(Instruction::ForIter(0), stmt.line);
+    chunk.emit(Instruction::PushScope, stmt.line);
+    match &stmt.left {
+        Some(pattern) => compile_pattern(chunk, pattern, Some(false))?,
+        None => {
+            let instruction = define_instruction(chunk, stmt, false);
+            chunk.emit(instruction, stmt.line);
+        },
+    }
+    chunk.emit(Instruction::Pop, stmt.line);
+
+    compile_stmt(chunk, consequent_ast)?;
+    chunk.emit(Instruction::Pop, stmt.line);
+    chunk.emit(Instruction::PopScope, stmt.line);
+    chunk.emit(Instruction::Jump(loop_start), stmt.line);
+
+    chunk.patch_jump(exit_jump);
+    chunk.emit(Instruction::Null, stmt.line);
+    Ok(())
+}
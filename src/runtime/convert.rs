@@ -0,0 +1,232 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::runtime::bignum::BigInt;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::operators::big_value;
+use crate::runtime::value::{List, Object, Value};
+
+/// A Rust value a script can be given: as a global, an argument or the
+/// result of a native function.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+/// A Rust value a script value can be read back as. Fails with
+/// `UnrecognizedValueType` when the value has another type, or does not
+/// fit, e.g. a negative integer read as a `u32`.
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, RuntimeError>;
+}
+
+fn unexpected(expected: &str, found: Value) -> RuntimeError {
+    RuntimeError::UnrecognizedValueType { expected: expected.to_string(), found }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        Ok(value)
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Null
+    }
+}
+
+impl FromValue for () {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Null => Ok(()),
+            other => Err(unexpected("null", other)),
+        }
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            other => Err(unexpected("a boolean", other)),
+        }
+    }
+}
+
+/// Integers up to 64 bits. Those an `i64` cannot hold become big integers
+/// in the script, and reading one back fails unless it fits the type.
+macro_rules! integer_conversions {
+    ($($integer:ty),*) => {$(
+        impl IntoValue for $integer {
+            fn into_value(self) -> Value {
+                match i64::try_from(self) {
+                    Ok(i) => Value::Integer(i),
+                    Err(_) => big_value(self.to_string().parse::<BigInt>().expect("a Rust integer is a valid literal")),
+                }
+            }
+        }
+
+        impl FromValue for $integer {
+            fn from_value(value: Value) -> Result<Self, RuntimeError> {
+                let fits = match &value {
+                    Value::Integer(i) => <$integer>::try_from(*i).ok(),
+                    Value::BigInt(b) => b.to_string().parse().ok(),
+                    _ => None,
+                };
+                fits.ok_or_else(|| unexpected(concat!("an integer that fits ", stringify!($integer)), value))
+            }
+        }
+    )*};
+}
+
+integer_conversions!(i8, i16, i32, i64, u8, u16, u32, u64, usize);
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Number(self)
+    }
+}
+
+/// Integers are read as floats too.
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Number(n) => Ok(n),
+            Value::Integer(i) => Ok(i as f64),
+            Value::BigInt(b) => Ok(b.to_f64()),
+            other => Err(unexpected("a number", other)),
+        }
+    }
+}
+
+impl IntoValue for f32 {
+    fn into_value(self) -> Value {
+        Value::Number(self.into())
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        f64::from_value(value).map(|n| n as f32)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::String(s) => Ok(s),
+            other => Err(unexpected("a string", other)),
+        }
+    }
+}
+
+/// `None` is `null`.
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        self.map_or(Value::Null, IntoValue::into_value)
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Null => Ok(None),
+            other => T::from_value(other).map(Some),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::List(List::new(self.into_iter().map(IntoValue::into_value).collect()))
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::List(list) => list.to_vec().into_iter().map(T::from_value).collect(),
+            other => Err(unexpected("a list", other)),
+        }
+    }
+}
+
+/// Maps become objects, with their fields in the map's order.
+impl<T: IntoValue> IntoValue for BTreeMap<String, T> {
+    fn into_value(self) -> Value {
+        Value::Object(Object::new(self.into_iter().map(|(name, value)| (name, value.into_value())).collect()))
+    }
+}
+
+impl<T: FromValue> FromValue for BTreeMap<String, T> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        fields(value)?.into_iter().map(|(name, value)| Ok((name, T::from_value(value)?))).collect()
+    }
+}
+
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(self) -> Value {
+        Value::Object(Object::new(self.into_iter().map(|(name, value)| (name, value.into_value())).collect()))
+    }
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        fields(value)?.into_iter().map(|(name, value)| Ok((name, T::from_value(value)?))).collect()
+    }
+}
+
+fn fields(value: Value) -> Result<Vec<(String, Value)>, RuntimeError> {
+    match value {
+        Value::Object(object) => Ok(object.to_vec()),
+        other => Err(unexpected("an object", other)),
+    }
+}
+
+/// The arguments of a script function called from Rust: a tuple of values
+/// that convert with `IntoValue`, or a `Vec<Value>`, passed positionally.
+pub trait IntoArguments {
+    fn into_arguments(self) -> Vec<Value>;
+}
+
+impl IntoArguments for Vec<Value> {
+    fn into_arguments(self) -> Vec<Value> {
+        self
+    }
+}
+
+macro_rules! tuple_arguments {
+    ($(($($argument:ident),*)),*) => {$(
+        impl<$($argument: IntoValue),*> IntoArguments for ($($argument,)*) {
+            #[allow(non_snake_case)]
+            fn into_arguments(self) -> Vec<Value> {
+                let ($($argument,)*) = self;
+                vec![$($argument.into_value()),*]
+            }
+        }
+    )*};
+}
+
+tuple_arguments!((), (A), (A, B), (A, B, C), (A, B, C, D), (A, B, C, D, E), (A, B, C, D, E, F));
@@ -0,0 +1,60 @@
+use std::fmt::Write;
+use crate::runtime::bytecode::{Chunk, Instruction};
+use crate::runtime::function::FunctionBody;
+
+/// Renders `chunk` as one instruction per line. Whenever the source line
+/// changes the corresponding line of `source` is printed above the
+/// instructions generated from it. The chunks of functions defined in
+/// `chunk` follow it, each under its own headings.
+pub fn disassemble(chunk: &Chunk, source: &str) -> String {
+    let source_lines: Vec<&str> = source.lines().collect();
+    let mut output = String::new();
+    write_chunk(&mut output, chunk, &source_lines, "");
+    output
+}
+
+fn write_chunk(output: &mut String, chunk: &Chunk, source_lines: &[&str], label: &str) {
+    let _ = writeln!(output, "== {}constants ==", label);
+    for (index, constant) in chunk.constants.iter().enumerate() {
+        let _ = writeln!(output, "{:04}  {:?}", index, constant);
+    }
+
+    let _ = writeln!(output, "== {}code ==", label);
+    let mut current_line = 0;
+    for (offset, (instruction, line)) in chunk.code.iter().zip(&chunk.lines).enumerate() {
+        if *line != current_line {
+            current_line = *line;
+            let text = source_lines.get(current_line.wrapping_sub(1)).map_or("", |text| text.trim());
+            let _ = writeln!(output, "{:>4} | {}", current_line, text);
+        }
+
+        let _ = match instruction {
+            Instruction::Constant(index)
+            | Instruction::DefineGlobal(index)
+            | Instruction::DefineConstant(index)
+            | Instruction::SetField(index)
+            | Instruction::GetGlobal(index)
+            | Instruction::SetGlobal(index)
+            | Instruction::GetField(index, _) => {
+                let constant = chunk.constants.get(*index).map_or("?".to_string(), |value| value.to_string());
+                writeln!(output, "{:04}     {:<24} ; {}", offset, instruction.to_string(), constant)
+            },
+            Instruction::Closure(index) => {
+                let name = chunk.functions.get(*index).map_or("?", |prototype| prototype.display_name());
+                writeln!(output, "{:04}     {:<24} ; fn {}", offset, instruction.to_string(), name)
+            },
+            Instruction::Destructure(index) => {
+                let pattern = chunk.patterns.get(*index).map_or("?".to_string(), |pattern| pattern.to_string());
+                writeln!(output, "{:04}     {:<24} ; {}", offset, instruction.to_string(), pattern)
+            },
+            _ => writeln!(output, "{:04}     {}", offset, instruction),
+        };
+    }
+
+    for (index, prototype) in chunk.functions.iter().enumerate() {
+        if let FunctionBody::Bytecode(body) = &prototype.body {
+            let label = format!("{}fn {} #{} ", label, prototype.display_name(), index);
+            write_chunk(output, body, source_lines, &label);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::marker::PhantomData;
+use crate::runtime::convert::{FromValue, IntoArguments, IntoValue};
+use crate::runtime::error::{RuntimeError, TraceFrame};
+use crate::runtime::function::{callee_name, Arguments};
+use crate::runtime::interpreter::Interpreter;
+use crate::runtime::limits::{Interrupt, Limits};
+use crate::runtime::native::Natives;
+use crate::runtime::runner::Options;
+use crate::runtime::value::Value;
+
+/// The interpreter as a Rust program embeds it. Like the REPL, it keeps
+/// its globals between calls to `eval`, so a script can define functions
+/// that the host calls later:
+///
+/// ```
+/// use compiler::{Engine, IntoValue};
+///
+/// let mut engine = Engine::new();
+/// engine.set_global("limit", 10);
+/// engine.eval("fn clamp(x) { if (x > limit) { limit } else { x } }").unwrap();
+/// let clamped: i64 = engine.call_as("clamp", vec![42.into_value()]).unwrap();
+/// assert_eq!(clamped, 10);
+/// ```
+///
+/// It always runs on the tree-walking backend, whose state outlives a
+/// single program. Programs run on the thread that calls the engine, so
+/// how deeply their calls can nest also depends on the stack of that
+/// thread: once it runs low, calls fail with `CallDepthExceeded`.
+pub struct Engine {
+    interpreter: Interpreter,
+}
+
+impl Engine {
+    /// An engine with the builtins, timers on the real clock, integers that
+    /// fail on overflow, no natives and no limits.
+    pub fn new() -> Self {
+        Engine { interpreter: Interpreter::new() }
+    }
+
+    /// An engine set up like the command line with `options`; the backend
+    /// is ignored.
+    pub fn with_options(options: &Options) -> Self {
+        let mut engine = Engine::new();
+        engine.interpreter.set_overflow(options.overflow());
+        engine.interpreter.set_clock(options.clock());
+        engine.interpreter.set_limits(options.limits);
+        engine.interpreter.register_natives(&options.natives);
+        engine
+    }
+
+    /// Caps what each `eval`, and each call of a script function, may use;
+    /// every one of them starts with the full limits.
+    pub fn set_limits(&mut self, limits: Limits) -> &mut Self {
+        self.interpreter.set_limits(limits);
+        self
+    }
+
+    /// A handle another thread can use to stop the `eval` or call in
+    /// progress, which then fails with `RuntimeError::Interrupted`, or the
+    /// next one if none is.
+    pub fn interrupt_handle(&self) -> Interrupt {
+        self.interpreter.interrupt_handle()
+    }
+
+    /// Makes the functions of `natives` available to `interop`.
+    pub fn register_natives(&mut self, natives: &Natives) -> &mut Self {
+        self.interpreter.register_natives(natives);
+        self
+    }
+
+    /// Registers a single native function, for `interop namespace`.
+    pub fn register_native(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        function: impl Fn(&[Value]) -> Result<Value, RuntimeError> + Send + Sync + 'static,
+    ) -> &mut Self {
+        let mut natives = Natives::new();
+        natives.register(namespace, name, function);
+        self.register_natives(&natives)
+    }
+
+    /// Defines or replaces the global `name`, which scripts see like any
+    /// variable they declared with `def`.
+    pub fn set_global(&mut self, name: &str, value: impl IntoValue) -> &mut Self {
+        self.interpreter.set_global(name, value.into_value());
+        self
+    }
+
+    pub fn global<T: FromValue>(&self, name: &str) -> Result<T, RuntimeError> {
+        T::from_value(self.interpreter.global(name)?)
+    }
+
+    /// Runs `source` as a program and returns the value of its last
+    /// statement, once the tasks it started have run.
+    pub fn eval(&mut self, source: &str) -> Result<Value, RuntimeError> {
+        self.interpreter.interpret(source)
+    }
+
+    pub fn eval_as<T: FromValue>(&mut self, source: &str) -> Result<T, RuntimeError> {
+        T::from_value(self.eval(source)?)
+    }
+
+    /// Calls the global function `name` with `arguments`, as positional
+    /// arguments. A failure inside the call comes back as
+    /// `RuntimeError::Traced`.
+    pub fn call(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let callee = self.interpreter.global(name)?;
+        self.call_traced(callee, arguments)
+    }
+
+    pub fn call_as<T: FromValue>(&mut self, name: &str, arguments: Vec<Value>) -> Result<T, RuntimeError> {
+        T::from_value(self.call(name, arguments)?)
+    }
+
+    /// The global function `name`, to call later with arguments `A` for a
+    /// result of type `R`. Fails if the global is not a function.
+    pub fn function<A: IntoArguments, R: FromValue>(&self, name: &str) -> Result<ScriptFunction<A, R>, RuntimeError> {
+        self.global(name)
+    }
+
+    /// Calls `function`; errors come back as `RuntimeError::Traced`, with
+    /// the calls they unwound through.
+    fn call_traced(&mut self, function: Value, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let name = callee_name(&function);
+        self.interpreter.call_function(function, Arguments::positional(arguments)).map_err(|error| {
+            let mut trace = self.interpreter.take_trace();
+            trace.push(TraceFrame { function: name, line: None });
+            RuntimeError::Traced { error: Box::new(error), trace }
+        })
+    }
+}
+
+/// A script function the host keeps to call back later, taking arguments
+/// `A` and returning `R`. It holds the function value itself, so it keeps
+/// working after the script reassigns the global it came from:
+///
+/// ```
+/// use compiler::{Engine, ScriptFunction};
+///
+/// let mut engine = Engine::new();
+/// let area: ScriptFunction<(f64, f64), f64> = engine.eval_as("fn (w, h) { w * h }").unwrap();
+/// assert_eq!(area.call(&mut engine, (2.0, 1.5)).unwrap(), 3.0);
+/// ```
+pub struct ScriptFunction<A, R> {
+    function: Value,
+    signature: PhantomData<fn(A) -> R>,
+}
+
+impl<A: IntoArguments, R: FromValue> ScriptFunction<A, R> {
+    pub fn name(&self) -> String {
+        callee_name(&self.function)
+    }
+
+    /// Calls the function on `engine`, which should be the one it came
+    /// from: it runs in the globals the function captured either way. A
+    /// failure inside the call comes back as `RuntimeError::Traced`.
+    pub fn call(&self, engine: &mut Engine, arguments: A) -> Result<R, RuntimeError> {
+        R::from_value(engine.call_traced(self.function.clone(), arguments.into_arguments())?)
+    }
+}
+
+impl<A, R> Clone for ScriptFunction<A, R> {
+    fn clone(&self) -> Self {
+        ScriptFunction { function: self.function.clone(), signature: PhantomData }
+    }
+}
+
+impl<A, R> FromValue for ScriptFunction<A, R> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Function(_) => Ok(ScriptFunction { function: value, signature: PhantomData }),
+            other => Err(RuntimeError::UnrecognizedValueType { expected: "a function".to_string(), found: other }),
+        }
+    }
+}
+
+/// Hands the function back to scripts, e.g. as an argument.
+impl<A, R> IntoValue for ScriptFunction<A, R> {
+    fn into_value(self) -> Value {
+        self.function
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
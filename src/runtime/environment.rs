@@ -0,0 +1,194 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use crate::frontend::ast::Slot;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::native::Natives;
+use crate::runtime::value::{DeepCopy, Value};
+
+pub type ScopeRef = Arc<Mutex<Scope>>;
+
+/// Block-local variables, addressed by the slots the resolver assigned.
+#[derive(Debug, Default)]
+pub struct Scope {
+    slots: Vec<Value>,
+    parent: Option<ScopeRef>,
+}
+
+#[derive(Debug, Default)]
+struct Globals {
+    values: HashMap<String, Value>,
+    /// Globals declared with `const`.
+    constants: HashSet<String>,
+    /// Functions the host registered, for `interop` to import.
+    natives: Natives,
+}
+
+/// Variables visible to a running program: globals by name and a chain of
+/// block scopes indexed by `Slot`. The globals are shared with the
+/// environments made by `fork`.
+#[derive(Debug, Default)]
+pub struct Environment {
+    globals: Arc<Mutex<Globals>>,
+    scope: Option<ScopeRef>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An environment with the same globals, starting in `scope`, for code
+    /// that runs apart from the current evaluation, such as a generator body.
+    pub fn fork(&self, scope: Option<ScopeRef>) -> Environment {
+        Environment { globals: self.globals.clone(), scope }
+    }
+
+    pub fn push_scope(&mut self) {
+        let parent = self.scope.take();
+        self.scope = Some(Arc::new(Mutex::new(Scope { slots: vec![], parent })));
+    }
+
+    pub fn pop_scope(&mut self) {
+        if let Some(scope) = self.scope.take() {
+            self.scope = lock(&scope).parent.clone();
+        }
+    }
+
+    /// The innermost scope, captured by functions created in it.
+    pub fn current_scope(&self) -> Option<ScopeRef> {
+        self.scope.clone()
+    }
+
+    /// Enters a function call: a new scope holding `arguments`, whose parent
+    /// is the scope the function captured. Returns the caller's scope, to be
+    /// put back with `restore_scope` once the call is over.
+    pub fn enter_call(&mut self, captured: Option<ScopeRef>, arguments: Vec<Value>) -> Option<ScopeRef> {
+        self.scope.replace(Self::call_scope(captured, arguments))
+    }
+
+    /// An environment with its own copy of the globals, made by `copy`, for
+    /// code that runs on another thread without sharing anything with this
+    /// one.
+    pub fn isolate(&self, copy: &mut DeepCopy) -> Environment {
+        let (values, constants, natives) = {
+            let globals = lock(&self.globals);
+            (globals.values.clone(), globals.constants.clone(), globals.natives.clone())
+        };
+        let values = values.iter().map(|(name, value)| (name.clone(), copy.value(value))).collect();
+        Environment { globals: Arc::new(Mutex::new(Globals { values, constants, natives })), scope: None }
+    }
+
+    /// The scope `enter_call` would enter, for a call that starts later.
+    pub fn call_scope(captured: Option<ScopeRef>, arguments: Vec<Value>) -> ScopeRef {
+        Arc::new(Mutex::new(Scope { slots: arguments, parent: captured }))
+    }
+
+    pub fn restore_scope(&mut self, scope: Option<ScopeRef>) {
+        self.scope = scope;
+    }
+
+    /// Drops every block scope, e.g. after an error unwound the evaluation.
+    pub fn reset_scopes(&mut self) {
+        self.scope = None;
+    }
+
+    pub fn define(&mut self, index: usize, value: Value) -> Result<(), RuntimeError> {
+        let scope = self.scope.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Local variable declared outside of a block.".to_string()
+        })?;
+        let mut scope = lock(scope);
+        if index < scope.slots.len() {
+            scope.slots[index] = value;
+        } else {
+            scope.slots.resize(index, Value::Null);
+            scope.slots.push(value);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, slot: Slot) -> Result<Value, RuntimeError> {
+        let scope = self.ancestor(slot.depth)?;
+        let scope = lock(&scope);
+        scope.slots.get(slot.index).cloned().ok_or_else(|| invalid_slot(slot))
+    }
+
+    pub fn set(&mut self, slot: Slot, value: Value) -> Result<(), RuntimeError> {
+        let scope = self.ancestor(slot.depth)?;
+        let mut scope = lock(&scope);
+        let target = scope.slots.get_mut(slot.index).ok_or_else(|| invalid_slot(slot))?;
+        *target = value;
+        Ok(())
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        let mut globals = lock(&self.globals);
+        globals.constants.remove(name);
+        globals.values.insert(name.to_string(), value);
+    }
+
+    pub fn define_constant(&mut self, name: &str, value: Value) {
+        let mut globals = lock(&self.globals);
+        globals.values.insert(name.to_string(), value);
+        globals.constants.insert(name.to_string());
+    }
+
+    pub fn get_global(&self, name: &str) -> Result<Value, RuntimeError> {
+        lock(&self.globals).values.get(name).cloned().ok_or_else(|| RuntimeError::UndefinedVariable { name: name.to_string() })
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+        let mut globals = lock(&self.globals);
+        if globals.constants.contains(name) {
+            return Err(RuntimeError::AssignmentToConstant { name: name.to_string() });
+        }
+        let target = globals.values.get_mut(name).ok_or_else(|| RuntimeError::UndefinedVariable { name: name.to_string() })?;
+        *target = value;
+        Ok(())
+    }
+
+    pub fn define_natives(&mut self, natives: &Natives) {
+        lock(&self.globals).natives.extend(natives);
+    }
+
+    /// The natives of `namespace`, for `interop`.
+    pub fn import_natives(&self, namespace: &str) -> Result<Value, RuntimeError> {
+        lock(&self.globals).natives.import(namespace)
+    }
+
+    fn ancestor(&self, depth: usize) -> Result<ScopeRef, RuntimeError> {
+        let mut scope = self.scope.clone();
+        for _ in 0..depth {
+            scope = scope.and_then(|scope| lock(&scope).parent.clone());
+        }
+        scope.ok_or_else(|| RuntimeError::TypeError {
+            message: format!("No enclosing scope at depth {}.", depth)
+        })
+    }
+}
+
+/// An empty scope, filled in by `copy_scope_into`.
+pub fn new_scope() -> ScopeRef {
+    Arc::new(Mutex::new(Scope::default()))
+}
+
+/// Fills `target` with the copies `copy` makes of the slots and the parent
+/// of `source`.
+pub fn copy_scope_into(source: &ScopeRef, target: &ScopeRef, copy: &mut DeepCopy) {
+    let (slots, parent) = {
+        let source = lock(source);
+        (source.slots.clone(), source.parent.clone())
+    };
+    let parent = parent.map(|parent| copy.scope(&parent));
+    let slots = slots.iter().map(|slot| copy.value(slot)).collect();
+    *lock(target) = Scope { slots, parent };
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn invalid_slot(slot: Slot) -> RuntimeError {
+    RuntimeError::TypeError {
+        message: format!("Local slot {} at depth {} is not defined.", slot.index, slot.depth)
+    }
+}
@@ -1,35 +1,180 @@
 use std::fmt;
+use crate::frontend::lexer::LexerError;
 use crate::frontend::parser::ParserError;
+use crate::frontend::resolver::ResolveError;
 use crate::runtime::value::Value;
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum RuntimeError {
+    LexerError(LexerError),
     ParserError(ParserError),
+    ResolveErrors(Vec<ResolveError>),
     TypeError { message: String },
     UnknownOperator { operator: String },
     DivisionByZero,
+    IntegerOverflow { operator: String },
     UnrecognizedValueType { expected: String, found: Value },
     UndefinedVariable { name: String },
+    /// `expected` is the signature of the function, e.g. `a, b?, ...rest`.
+    ArityMismatch { name: String, expected: String, found: usize },
+    IndexOutOfRange { index: i64, length: usize },
+    /// Assignment to a `const` global that the resolver could not catch,
+    /// e.g. from a function declared before the constant.
+    AssignmentToConstant { name: String },
+    /// Modification of a list or object made immutable with `freeze`.
+    FrozenValue { value: String },
+    /// A destructured value does not have the shape of the pattern, e.g. a
+    /// list with too few items.
+    PatternMismatch { pattern: String, reason: String },
+    /// A promise passed to `timeout` took longer than it allows.
+    Timeout { milliseconds: u128 },
+    /// Sending on a channel after `close`.
+    ChannelClosed,
+    /// Every thread of the program waits for another one.
+    Deadlock,
+    /// `interop` of a namespace the host registered no natives under.
+    UnknownNamespace { namespace: String },
+    /// The program took more steps than its fuel allows.
+    OutOfFuel { fuel: u64 },
+    /// Calls nested deeper than allowed, e.g. by runaway recursion.
+    CallDepthExceeded { limit: usize },
+    /// A string, big number, list or object grew past the bytes allowed
+    /// for one value.
+    ValueTooLarge { limit: usize },
+    HeapLimitExceeded { limit: usize },
+    /// The program ran for longer than allowed, unlike `Timeout`, which
+    /// is a single promise taking too long.
+    ExecutionTimeout { milliseconds: u128 },
+    /// The host asked the program to stop, e.g. Ctrl-C in the REPL.
+    Interrupted,
+    /// An error raised in a script function the host called, with the
+    /// calls it unwound through, innermost first.
+    Traced { error: Box<RuntimeError>, trace: Vec<TraceFrame> },
+    /// Unwinds a `return` to the enclosing call, which turns it back into
+    /// the call's value. The resolver rejects `return` outside functions, so
+    /// it never escapes a program.
+    Return(Value),
 }
 
 impl fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            RuntimeError::LexerError(e) => write!(f, "Erro Léxico: {}", e),
             RuntimeError::ParserError(e) => write!(f, "Erro de Parsing: {}", e),
+            RuntimeError::ResolveErrors(errors) => {
+                let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "Erro de Resolução: {}", messages.join("\n"))
+            },
             RuntimeError::TypeError { message } => write!(f, "Erro de Tipo: {}", message),
             RuntimeError::UnknownOperator { operator } => write!(f, "Operador desconhecido: '{}'", operator),
             RuntimeError::DivisionByZero => write!(f, "Erro de Execução: Divisão por zero."),
+            RuntimeError::IntegerOverflow { operator } => write!(f, "Erro de Execução: Estouro de inteiro em '{}'.", operator),
             RuntimeError::UnrecognizedValueType { expected, found } => {
                 write!(f, "Erro de Execução: Tipo de valor inesperado. Esperava {}, mas encontrou {:?}", expected, found)
             },
             RuntimeError::UndefinedVariable { name } => write!(f, "Erro de Execução: Variável não definida '{}'", name),
+            RuntimeError::ArityMismatch { name, expected, found } => {
+                write!(f, "Erro de Execução: '{}' espera os parâmetros ({}), mas recebeu {} argumento(s).", name, expected, found)
+            },
+            RuntimeError::IndexOutOfRange { index, length } => {
+                write!(f, "Erro de Execução: Índice {} fora dos limites de uma lista de tamanho {}.", index, length)
+            },
+            RuntimeError::AssignmentToConstant { name } => {
+                write!(f, "Erro de Execução: Não é possível reatribuir a constante '{}'.", name)
+            },
+            RuntimeError::FrozenValue { value } => {
+                write!(f, "Erro de Execução: {} está congelado e não pode ser modificado.", value)
+            },
+            RuntimeError::PatternMismatch { pattern, reason } => {
+                write!(f, "Erro de Execução: O valor não corresponde ao padrão {}: {}.", pattern, reason)
+            },
+            RuntimeError::Timeout { milliseconds } => {
+                write!(f, "Erro de Execução: Tempo esgotado após {} ms.", milliseconds)
+            },
+            RuntimeError::ChannelClosed => write!(f, "Erro de Execução: O canal está fechado."),
+            RuntimeError::Deadlock => {
+                write!(f, "Erro de Execução: Impasse: todas as threads estão esperando umas pelas outras.")
+            },
+            RuntimeError::UnknownNamespace { namespace } => {
+                write!(f, "Erro de Execução: Nenhuma função nativa registrada no namespace '{}'.", namespace)
+            },
+            RuntimeError::OutOfFuel { fuel } => {
+                write!(f, "Erro de Execução: O programa excedeu o limite de {} passos.", fuel)
+            },
+            RuntimeError::CallDepthExceeded { limit } => {
+                write!(f, "Erro de Execução: Chamadas aninhadas além do limite de {}.", limit)
+            },
+            RuntimeError::ValueTooLarge { limit } => {
+                write!(f, "Erro de Execução: Um valor excedeu o limite de {} bytes por valor.", limit)
+            },
+            RuntimeError::HeapLimitExceeded { limit } => {
+                write!(f, "Erro de Execução: O programa excedeu o limite de {} bytes de memória.", limit)
+            },
+            RuntimeError::ExecutionTimeout { milliseconds } => {
+                write!(f, "Erro de Execução: O programa excedeu o tempo limite de {} ms.", milliseconds)
+            },
+            RuntimeError::Interrupted => write!(f, "Erro de Execução: Execução interrompida."),
+            RuntimeError::Traced { error, trace } => {
+                write!(f, "{}", error)?;
+                for frame in trace {
+                    write!(f, "\n  {}", frame)?;
+                }
+                Ok(())
+            },
+            RuntimeError::Return(_) => write!(f, "Erro de Execução: 'return' fora de uma função."),
         }
     }
 }
 
+impl RuntimeError {
+    /// The error itself, without the trace of the calls it went through.
+    pub fn root(&self) -> &RuntimeError {
+        match self {
+            RuntimeError::Traced { error, .. } => error.root(),
+            error => error,
+        }
+    }
+
+    pub fn trace(&self) -> &[TraceFrame] {
+        match self {
+            RuntimeError::Traced { trace, .. } => trace,
+            _ => &[],
+        }
+    }
+}
+
+/// A call an error unwound through.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFrame {
+    pub function: String,
+    /// Line of the call, or `None` for the call the host made.
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "em {}, chamada na linha {}", self.function, line),
+            None => write!(f, "em {}, chamada pelo host", self.function),
+        }
+    }
+}
+
+impl From<LexerError> for RuntimeError {
+    fn from(error: LexerError) -> Self {
+        RuntimeError::LexerError(error)
+    }
+}
+
 impl From<ParserError> for RuntimeError {
     fn from(error: ParserError) -> Self {
         RuntimeError::ParserError(error)
     }
+}
+
+impl From<Vec<ResolveError>> for RuntimeError {
+    fn from(errors: Vec<ResolveError>) -> Self {
+        RuntimeError::ResolveErrors(errors)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,703 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::time::Duration;
+use crate::runtime::clock::{Clock, MonotonicClock};
+use crate::runtime::error::RuntimeError;
+use crate::runtime::function::Arguments;
+use crate::runtime::generator::{Generator, Step};
+use crate::runtime::limits::Budget;
+use crate::runtime::value::{List, Value};
+
+enum PromiseState {
+    /// Not settled yet; what runs once it is.
+    Pending(Vec<Waiter>),
+    Fulfilled(Value),
+    Rejected(RuntimeError),
+}
+
+struct PromiseInner {
+    state: PromiseState,
+    /// Whether anything awaited it, so a rejection is not lost.
+    awaited: bool,
+}
+
+/// The value of a call to an async function, settled with the result of
+/// the call once its task has run to the end, of a `work` block, or of a
+/// scheduling builtin such as `sleep` or `all`.
+#[derive(Clone)]
+pub struct Promise(Arc<Mutex<PromiseInner>>);
+
+impl Promise {
+    fn new(state: PromiseState) -> Self {
+        Promise(Arc::new(Mutex::new(PromiseInner { state, awaited: false })))
+    }
+
+    fn pending() -> Self {
+        Promise::new(PromiseState::Pending(vec![]))
+    }
+
+    /// An already settled promise.
+    pub fn settled(result: Result<Value, RuntimeError>) -> Self {
+        Promise::new(match result {
+            Ok(value) => PromiseState::Fulfilled(value),
+            Err(error) => PromiseState::Rejected(error),
+        })
+    }
+
+    /// What awaiting `value` waits for: a promise as it is, anything else
+    /// as an already fulfilled promise.
+    fn of(value: Value) -> Self {
+        match value {
+            Value::Promise(promise) => promise,
+            other => Promise::new(PromiseState::Fulfilled(other)),
+        }
+    }
+
+    /// The outcome, once settled.
+    pub fn result(&self) -> Option<Result<Value, RuntimeError>> {
+        match &self.lock().state {
+            PromiseState::Pending(_) => None,
+            PromiseState::Fulfilled(value) => Some(Ok(value.clone())),
+            PromiseState::Rejected(error) => Some(Err(error.clone())),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PromiseInner> {
+        lock(&self.0)
+    }
+}
+
+/// Promises are only equal to themselves.
+impl PartialEq for Promise {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Display for Promise {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.result() {
+            None => write!(f, "<promise pending>"),
+            Some(Ok(value)) => write!(f, "<promise {}>", value),
+            Some(Err(_)) => write!(f, "<promise rejected>"),
+        }
+    }
+}
+
+impl fmt::Debug for Promise {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// The body of an async call, run like a generator whose `await`s yield
+/// the value awaited, and the promise its result settles.
+struct Task {
+    generator: Generator,
+    promise: Promise,
+}
+
+/// What happens when a promise settles.
+enum Waiter {
+    /// A task awaiting it resumes with its value, or fails with its error.
+    Task(Task),
+    /// Another promise settles the same way, unless it already settled:
+    /// how `race` and `timeout` pick the first outcome.
+    Forward(Promise),
+    /// One of the promises passed to `all`.
+    Gather { index: usize, gathered: Arc<Mutex<Gathered>> },
+}
+
+/// The values `all` collected so far.
+struct Gathered {
+    promise: Promise,
+    values: Vec<Value>,
+    remaining: usize,
+}
+
+enum TimerAction {
+    /// `sleep`: fulfills the promise with `null`.
+    Fulfill(Promise),
+    /// `timeout`: rejects the promise, unless it settled first.
+    Reject(Promise, RuntimeError),
+    /// `setTimeout`, and `setInterval` with the time until it calls the
+    /// callback again.
+    Call { callback: Value, interval: Option<Duration> },
+}
+
+struct Timer {
+    id: u64,
+    deadline: Duration,
+    action: TimerAction,
+}
+
+impl Timer {
+    /// Whether firing it would settle `promise`, which makes it useless
+    /// once the promise settled another way.
+    fn settles(&self, promise: &Promise) -> bool {
+        match &self.action {
+            TimerAction::Fulfill(target) | TimerAction::Reject(target, _) => target == promise,
+            TimerAction::Call { .. } => false,
+        }
+    }
+}
+
+struct Queue {
+    /// Tasks that can run, with the value their pending `await` gets.
+    ready: VecDeque<(Task, Value)>,
+    /// Promises rejected since the loop last finished.
+    rejected: Vec<Promise>,
+    timers: Vec<Timer>,
+    next_timer: u64,
+    clock: Box<dyn Clock>,
+    /// Promises of workers still running.
+    working: Vec<Promise>,
+    /// Promises of channel operations still waiting for another thread
+    /// or task to take part.
+    waiting: Vec<Promise>,
+    /// Whether the thread driving the loop is counted in
+    /// `Threads::blocked`. Another thread that settles one of its promises
+    /// takes it out of the count right away, before it even wakes up.
+    blocked: bool,
+    /// Set by other threads when they settle one of the loop's promises,
+    /// or when one of them blocks or ends, so that the thread driving the
+    /// loop looks again at whether it can go on.
+    woken: bool,
+}
+
+impl Queue {
+    /// Whether something other than the thread driving the loop can still
+    /// settle one of its promises.
+    fn expects_other_threads(&self) -> bool {
+        !self.working.is_empty() || !self.waiting.is_empty()
+    }
+}
+
+/// The threads of one program: the one that started it and its workers.
+/// Once every one of them waits for the others, with no timer left to wake
+/// any of them up, none of them can ever go on. A thread that settles a
+/// promise of another one is running, so it is never counted as blocked
+/// itself while the other one is not counted anymore. Each thread that
+/// blocks or ends wakes the others up, so the last one to block sees it.
+struct Threads {
+    live: usize,
+    blocked: usize,
+    /// The loops the threads drive.
+    loops: Vec<Waker>,
+}
+
+struct Waker {
+    queue: Weak<Mutex<Queue>>,
+    wake: Weak<Condvar>,
+}
+
+/// What the event loop needs from the backend that drives it.
+pub trait Driver {
+    /// Runs a task's generator up to its next `await` or its end.
+    fn resume(&mut self, generator: &Generator, sent: Value) -> Result<Step, RuntimeError>;
+
+    /// Calls the callback of a timer.
+    fn call(&mut self, callee: Value, arguments: Arguments) -> Result<Value, RuntimeError>;
+
+    /// What the program may still use, which stops the loop while it waits
+    /// too.
+    fn budget(&self) -> &Budget;
+}
+
+/// Runs the tasks of async calls and the timers on the thread that drives
+/// it, one at a time. Timers that are due go first, then tasks in the
+/// order they became ready; only when nothing is ready does the loop wait
+/// for the next timer. A task runs until it awaits something that has not
+/// settled; awaiting anything else still lets the other ready tasks go
+/// first. Results of work on other threads are picked up as they come,
+/// and the loop sleeps while it waits for them. With a virtual clock the
+/// interleaving of tasks and timers is the same on every run. Copies share
+/// the same queue.
+#[derive(Clone)]
+pub struct EventLoop {
+    queue: Arc<Mutex<Queue>>,
+    /// Signalled when another thread settles a promise.
+    wake: Arc<Condvar>,
+    /// Shared with the loops of the program's workers.
+    threads: Arc<Mutex<Threads>>,
+}
+
+impl Default for EventLoop {
+    fn default() -> Self {
+        EventLoop::new(Box::new(MonotonicClock::default()))
+    }
+}
+
+impl EventLoop {
+    pub fn new(clock: Box<dyn Clock>) -> Self {
+        let threads = Arc::new(Mutex::new(Threads { live: 1, blocked: 0, loops: vec![] }));
+        EventLoop::with_threads(clock, threads)
+    }
+
+    fn with_threads(clock: Box<dyn Clock>, threads: Arc<Mutex<Threads>>) -> Self {
+        let queue = Queue {
+            ready: VecDeque::new(),
+            rejected: vec![],
+            timers: vec![],
+            next_timer: 0,
+            clock,
+            working: vec![],
+            waiting: vec![],
+            blocked: false,
+            woken: false,
+        };
+        let event_loop = EventLoop { queue: Arc::new(Mutex::new(queue)), wake: Arc::new(Condvar::new()), threads };
+        lock(&event_loop.threads).loops.push(Waker {
+            queue: Arc::downgrade(&event_loop.queue),
+            wake: Arc::downgrade(&event_loop.wake),
+        });
+        event_loop
+    }
+
+    /// The loop of a new worker thread of the same program, which has to
+    /// call `end_worker` once it is done.
+    pub fn for_worker(&self) -> EventLoop {
+        lock(&self.threads).live += 1;
+        EventLoop::with_threads(Box::new(MonotonicClock::default()), self.threads.clone())
+    }
+
+    pub fn end_worker(&self) {
+        {
+            let mut threads = lock(&self.threads);
+            threads.live -= 1;
+            threads.loops.retain(|waker| !std::ptr::eq(waker.queue.as_ptr(), Arc::as_ptr(&self.queue)));
+        }
+        self.wake_others();
+    }
+
+    /// Queues the body of an async call and returns the promise of its
+    /// result.
+    pub fn spawn(&self, generator: Generator) -> Promise {
+        let promise = Promise::pending();
+        self.schedule(Task { generator, promise: promise.clone() }, Value::Null);
+        promise
+    }
+
+    /// A promise for work done on another thread, which settles it with
+    /// `complete_work`. The loop does not finish before it is settled.
+    pub fn start_work(&self) -> Promise {
+        let promise = Promise::pending();
+        self.lock().working.push(promise.clone());
+        promise
+    }
+
+    /// Settles a promise from `start_work`; called from the thread that
+    /// did the work.
+    pub fn complete_work(&self, promise: &Promise, result: Result<Value, RuntimeError>) {
+        self.complete(promise, result, |queue| &mut queue.working);
+    }
+
+    /// A promise for a channel operation that waits for another thread or
+    /// task, which settles it with `complete_wait`.
+    pub fn start_wait(&self) -> Promise {
+        let promise = Promise::pending();
+        self.lock().waiting.push(promise.clone());
+        promise
+    }
+
+    /// Settles a promise from `start_wait`, from any thread.
+    pub fn complete_wait(&self, promise: &Promise, result: Result<Value, RuntimeError>) {
+        self.complete(promise, result, |queue| &mut queue.waiting);
+    }
+
+    /// Settles `promise`, takes it out of `pending` of the queue and wakes
+    /// up the thread driving the loop.
+    fn complete(&self, promise: &Promise, result: Result<Value, RuntimeError>, pending: fn(&mut Queue) -> &mut Vec<Promise>) {
+        self.settle(promise, result);
+        let mut queue = self.lock();
+        let promises = pending(&mut queue);
+        if let Some(position) = promises.iter().position(|pending| pending == promise) {
+            promises.swap_remove(position);
+        }
+        queue.woken = true;
+        self.unblock(&mut queue);
+        self.wake.notify_all();
+    }
+
+    /// Time since the loop was created, on its clock.
+    pub fn now(&self) -> Duration {
+        self.lock().clock.now()
+    }
+
+    /// A promise fulfilled with `null` once `delay` has passed.
+    pub fn sleep(&self, delay: Duration) -> Promise {
+        let promise = Promise::pending();
+        self.add_timer(delay, TimerAction::Fulfill(promise.clone()));
+        promise
+    }
+
+    /// Calls `callback` once `delay` has passed, and every `delay` after
+    /// that if `repeat` is set. Returns the id `cancel` takes.
+    pub fn set_timer(&self, callback: Value, delay: Duration, repeat: bool) -> u64 {
+        // An interval of 0 would fire forever without letting tasks run.
+        let interval = repeat.then(|| delay.max(Duration::from_millis(1)));
+        self.add_timer(delay, TimerAction::Call { callback, interval })
+    }
+
+    /// Stops a timer from firing again. Returns `false` if it was not
+    /// pending anymore.
+    pub fn cancel(&self, id: u64) -> bool {
+        let mut queue = self.lock();
+        let before = queue.timers.len();
+        queue.timers.retain(|timer| timer.id != id);
+        queue.timers.len() < before
+    }
+
+    /// A promise fulfilled with the list of the values of `awaitables` once
+    /// all of them are fulfilled, or rejected as soon as one of them is.
+    pub fn all(&self, awaitables: Vec<Value>) -> Promise {
+        if awaitables.is_empty() {
+            return Promise::new(PromiseState::Fulfilled(Value::List(List::new(vec![]))));
+        }
+        let promise = Promise::pending();
+        let gathered = Arc::new(Mutex::new(Gathered {
+            promise: promise.clone(),
+            values: vec![Value::Null; awaitables.len()],
+            remaining: awaitables.len(),
+        }));
+        for (index, awaitable) in awaitables.into_iter().enumerate() {
+            self.subscribe(&Promise::of(awaitable), Waiter::Gather { index, gathered: gathered.clone() });
+        }
+        promise
+    }
+
+    /// A promise that settles like the first of `awaitables` to settle.
+    pub fn race(&self, awaitables: Vec<Value>) -> Promise {
+        let promise = Promise::pending();
+        for awaitable in awaitables {
+            self.subscribe(&Promise::of(awaitable), Waiter::Forward(promise.clone()));
+        }
+        promise
+    }
+
+    /// A promise that settles like `awaitable`, or is rejected if that
+    /// takes longer than `delay`.
+    pub fn timeout(&self, awaitable: Value, delay: Duration) -> Promise {
+        let promise = Promise::pending();
+        let error = RuntimeError::Timeout { milliseconds: delay.as_millis() };
+        self.add_timer(delay, TimerAction::Reject(promise.clone(), error));
+        self.subscribe(&Promise::of(awaitable), Waiter::Forward(promise.clone()));
+        promise
+    }
+
+    /// `await value` outside of an async function: runs tasks and timers
+    /// until the value settles and returns its outcome.
+    pub fn wait(&self, value: Value, driver: &mut impl Driver) -> Result<Value, RuntimeError> {
+        let promise = Promise::of(value);
+        promise.lock().awaited = true;
+        self.run(Some(&promise), driver)?;
+        promise.result().unwrap_or_else(|| Err(match self.lock().expects_other_threads() {
+            true => RuntimeError::Deadlock,
+            false => RuntimeError::TypeError {
+                message: "Awaited promise can never settle: no task or timer is left to run.".to_string()
+            },
+        }))
+    }
+
+    /// Runs every remaining task and timer, once the program itself is
+    /// done. The first rejection nothing awaited becomes the program's
+    /// error. Channel operations nothing awaited are dropped, but workers
+    /// that can never finish are a deadlock.
+    pub fn finish(&self, driver: &mut impl Driver) -> Result<(), RuntimeError> {
+        self.run(None, driver)?;
+        if !self.lock().working.is_empty() {
+            return Err(RuntimeError::Deadlock);
+        }
+        let rejected = std::mem::take(&mut self.lock().rejected);
+        match rejected.iter().find(|promise| !promise.lock().awaited).and_then(Promise::result) {
+            Some(Err(error)) => Err(error),
+            _ => Ok(()),
+        }
+    }
+
+    /// Drops the tasks and timers a program that failed with `error` left
+    /// behind, so that they do not hold up the next one. Its work and
+    /// channel operations are rejected with `error` right away: workers
+    /// still run to their end, but what they come up with is dropped.
+    pub fn abandon(&self, error: &RuntimeError) {
+        let left = {
+            let mut queue = self.lock();
+            queue.ready.clear();
+            queue.timers.clear();
+            queue.rejected.clear();
+            let mut left = std::mem::take(&mut queue.working);
+            left.append(&mut queue.waiting);
+            left
+        };
+        for promise in left {
+            let mut inner = promise.lock();
+            if let PromiseState::Pending(_) = inner.state {
+                inner.state = PromiseState::Rejected(error.clone());
+            }
+        }
+    }
+
+    /// Fires timers and resumes ready tasks until `until` settles or
+    /// nothing is left. An error in a timer's callback stops the loop, and
+    /// so does the program's budget, also while the loop waits.
+    fn run(&self, until: Option<&Promise>, driver: &mut impl Driver) -> Result<(), RuntimeError> {
+        let budget = driver.budget().clone();
+        while until.is_none_or(|promise| promise.result().is_none()) {
+            budget.check()?;
+            if let Some(timer) = self.next_timer(false) {
+                self.fire(timer, driver)?;
+                continue;
+            }
+            let Some((task, sent)) = self.lock().ready.pop_front() else {
+                if self.wait_for_others(&budget)? {
+                    continue;
+                }
+                // The other threads are all waiting too: only time passing
+                // can change anything.
+                match self.next_timer(true) {
+                    Some(timer) => self.fire(timer, driver)?,
+                    None if self.wait_for_timer(&budget) => {},
+                    None => break,
+                }
+                continue;
+            };
+            match driver.resume(&task.generator, sent) {
+                Ok(Step::Yield(awaited)) => self.subscribe(&Promise::of(awaited), Waiter::Task(task)),
+                Ok(Step::Return(value)) => self.settle(&task.promise, Ok(value)),
+                Err(error) => self.settle(&task.promise, Err(error)),
+            }
+        }
+        Ok(())
+    }
+
+    /// The earliest timer, if it is due. On a virtual clock, time also
+    /// jumps to it if nothing else can happen before it or `force` is set;
+    /// real time is waited for by `wait_for_timer`.
+    fn next_timer(&self, force: bool) -> Option<Timer> {
+        let mut queue = self.lock();
+        let (position, deadline) = queue.timers.iter().enumerate()
+            .min_by_key(|(_, timer)| (timer.deadline, timer.id))
+            .map(|(position, timer)| (position, timer.deadline))?;
+        let busy = !queue.ready.is_empty() || queue.expects_other_threads();
+        let virtual_clock = queue.clock.time_until(deadline).is_none();
+        if deadline > queue.clock.now() && !(virtual_clock && (force || !busy)) {
+            return None;
+        }
+        queue.clock.advance_to(deadline);
+        Some(queue.timers.remove(position))
+    }
+
+    /// Sleeps until the earliest timer is due on a real clock, or for as
+    /// long as `budget` lets a wait go on. Returns `false` if there is no
+    /// timer left.
+    fn wait_for_timer(&self, budget: &Budget) -> bool {
+        let queue = self.lock();
+        let Some(deadline) = queue.timers.iter().map(|timer| timer.deadline).min() else {
+            return false;
+        };
+        if let Some(wait) = queue.clock.time_until(deadline) {
+            drop(self.wake.wait_timeout(queue, budget.wait_limit(wait)));
+        }
+        true
+    }
+
+    /// Sleeps while only other threads can settle something, until one of
+    /// them does, the next timer is due or `budget` fails. Returns `false`
+    /// if there is nothing to wait for, or every thread of the program is
+    /// waiting.
+    fn wait_for_others(&self, budget: &Budget) -> Result<bool, RuntimeError> {
+        let queue = self.lock();
+        if !queue.ready.is_empty() {
+            return Ok(true);
+        }
+        if !queue.expects_other_threads() {
+            return Ok(false);
+        }
+        let next_deadline = queue.timers.iter().map(|timer| timer.deadline).min();
+        if let Some(wait) = next_deadline.and_then(|deadline| queue.clock.time_until(deadline)) {
+            drop(self.wake.wait_timeout(queue, budget.wait_limit(wait)));
+            return Ok(true);
+        }
+
+        // A timer on a virtual clock fires once every other thread waits;
+        // until then this thread is not blocked, since it has one to fire.
+        let mut queue = queue;
+        if next_deadline.is_some() {
+            loop {
+                let others_blocked = {
+                    let threads = lock(&self.threads);
+                    threads.blocked + 1 >= threads.live
+                };
+                if others_blocked || !queue.ready.is_empty() {
+                    return Ok(!others_blocked);
+                }
+                budget.check()?;
+                queue.woken = false;
+                queue = self.wake.wait_timeout_while(queue, budget.wait_limit(Duration::MAX), |queue| !queue.woken)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .0;
+            }
+        }
+
+        {
+            let mut threads = lock(&self.threads);
+            if threads.blocked + 1 >= threads.live {
+                return Ok(false);
+            }
+            threads.blocked += 1;
+        }
+        queue.blocked = true;
+        drop(queue);
+        self.wake_others();
+
+        let mut queue = self.lock();
+        loop {
+            if !queue.blocked {
+                return Ok(true);
+            }
+            let stuck = queue.ready.is_empty() && {
+                let threads = lock(&self.threads);
+                threads.blocked >= threads.live
+            };
+            if stuck || !queue.ready.is_empty() {
+                self.unblock(&mut queue);
+                return Ok(!stuck);
+            }
+            if let Err(error) = budget.check() {
+                self.unblock(&mut queue);
+                return Err(error);
+            }
+            queue.woken = false;
+            queue = self.wake.wait_timeout_while(queue, budget.wait_limit(Duration::MAX), |queue| queue.blocked && !queue.woken)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+    }
+
+    /// Has the loops of the program's other threads look again at whether
+    /// they can go on. Takes their locks one at a time, so it must not be
+    /// called with the lock of this loop's queue held.
+    fn wake_others(&self) {
+        let others: Vec<(Arc<Mutex<Queue>>, Arc<Condvar>)> = lock(&self.threads).loops.iter()
+            .filter_map(|waker| Some((waker.queue.upgrade()?, waker.wake.upgrade()?)))
+            .filter(|(queue, _)| !Arc::ptr_eq(queue, &self.queue))
+            .collect();
+        for (queue, wake) in others {
+            lock(&queue).woken = true;
+            wake.notify_all();
+        }
+    }
+
+    /// Takes the thread driving this loop out of the blocked ones.
+    fn unblock(&self, queue: &mut Queue) {
+        if queue.blocked {
+            queue.blocked = false;
+            lock(&self.threads).blocked -= 1;
+        }
+    }
+
+    fn fire(&self, timer: Timer, driver: &mut impl Driver) -> Result<(), RuntimeError> {
+        match timer.action {
+            TimerAction::Fulfill(promise) => self.settle(&promise, Ok(Value::Null)),
+            TimerAction::Reject(promise, error) => self.settle(&promise, Err(error)),
+            TimerAction::Call { callback, interval } => {
+                if let Some(interval) = interval {
+                    // Rescheduled before the call, so the callback can cancel it.
+                    let action = TimerAction::Call { callback: callback.clone(), interval: Some(interval) };
+                    self.lock().timers.push(Timer { id: timer.id, deadline: timer.deadline + interval, action });
+                }
+                driver.call(callback, Arguments::default())?;
+            },
+        }
+        Ok(())
+    }
+
+    fn add_timer(&self, delay: Duration, action: TimerAction) -> u64 {
+        let mut queue = self.lock();
+        let id = queue.next_timer;
+        queue.next_timer += 1;
+        let deadline = queue.clock.now() + delay;
+        queue.timers.push(Timer { id, deadline, action });
+        id
+    }
+
+    /// Runs `waiter` once `awaited` settles, right away if it already has.
+    fn subscribe(&self, awaited: &Promise, waiter: Waiter) {
+        let mut inner = awaited.lock();
+        inner.awaited = true;
+        let result = match &mut inner.state {
+            PromiseState::Pending(waiting) => {
+                waiting.push(waiter);
+                return;
+            },
+            PromiseState::Fulfilled(value) => Ok(value.clone()),
+            PromiseState::Rejected(error) => Err(error.clone()),
+        };
+        drop(inner);
+        self.notify(waiter, &result);
+    }
+
+    /// Settles `promise`, unless it already settled, and runs what waited
+    /// for it.
+    fn settle(&self, promise: &Promise, result: Result<Value, RuntimeError>) {
+        let waiting = {
+            let mut inner = promise.lock();
+            let PromiseState::Pending(waiting) = &mut inner.state else {
+                return;
+            };
+            let waiting = std::mem::take(waiting);
+            inner.state = match &result {
+                Ok(value) => PromiseState::Fulfilled(value.clone()),
+                Err(error) => PromiseState::Rejected(error.clone()),
+            };
+            waiting
+        };
+        {
+            let mut queue = self.lock();
+            if result.is_err() {
+                queue.rejected.push(promise.clone());
+            }
+            queue.timers.retain(|timer| !timer.settles(promise));
+        }
+        for waiter in waiting {
+            self.notify(waiter, &result);
+        }
+    }
+
+    /// Awaiting a rejected promise fails the task with the same error.
+    fn notify(&self, waiter: Waiter, result: &Result<Value, RuntimeError>) {
+        match (waiter, result) {
+            (Waiter::Task(task), Ok(value)) => self.schedule(task, value.clone()),
+            (Waiter::Task(task), Err(error)) => self.settle(&task.promise, Err(error.clone())),
+            (Waiter::Forward(promise), result) => self.settle(&promise, result.clone()),
+            (Waiter::Gather { gathered, .. }, Err(error)) => {
+                let promise = lock(&gathered).promise.clone();
+                self.settle(&promise, Err(error.clone()));
+            },
+            (Waiter::Gather { index, gathered }, Ok(value)) => {
+                let done = {
+                    let mut gathered = lock(&gathered);
+                    gathered.values[index] = value.clone();
+                    gathered.remaining -= 1;
+                    (gathered.remaining == 0).then(|| (gathered.promise.clone(), std::mem::take(&mut gathered.values)))
+                };
+                if let Some((promise, values)) = done {
+                    self.settle(&promise, Ok(Value::List(List::new(values))));
+                }
+            },
+        }
+    }
+
+    fn schedule(&self, task: Task, sent: Value) {
+        self.lock().ready.push_back((task, sent));
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        lock(&self.queue)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,244 @@
+use std::fmt;
+use std::sync::Arc;
+use crate::frontend::ast::{Stmt, StmtType};
+use crate::runtime::builtins::Builtin;
+use crate::runtime::bytecode::Chunk;
+use crate::runtime::channel::{Channel, ChannelMethod};
+use crate::runtime::environment::ScopeRef;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::generator::Generator;
+use crate::runtime::native::Native;
+use crate::runtime::value::{List, Value};
+
+/// Code of a function, in the form the backend that created it runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FunctionBody {
+    /// The whole function node, evaluated by the tree-walking interpreter:
+    /// default values are in its parameters and the block in `consequent`.
+    Tree(Stmt),
+    /// The body compiled for the vm, starting with the code that fills in
+    /// default values; it ends with `RETURN`.
+    Bytecode(Chunk),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FunctionKind {
+    Plain,
+    /// `fn*`: calling it returns a generator that runs the body lazily.
+    Generator,
+    /// `async fn`: calling it queues the body on the event loop and returns
+    /// a promise of its result.
+    Async,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    /// Has a default value, used when no argument is passed for it.
+    pub optional: bool,
+    /// `...name`: collects the remaining positional arguments in a list.
+    pub rest: bool,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.rest {
+            write!(f, "...{}", self.name)
+        } else if self.optional {
+            write!(f, "{}?", self.name)
+        } else {
+            write!(f, "{}", self.name)
+        }
+    }
+}
+
+/// Everything about a function that is known before it runs. Calling it
+/// puts the arguments, in order, in the slots of a fresh scope whose parent
+/// is the scope the function was created in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prototype {
+    pub name: Option<String>,
+    pub kind: FunctionKind,
+    pub params: Vec<Param>,
+    pub body: FunctionBody,
+}
+
+impl Prototype {
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or("anonymous")
+    }
+
+    /// Kind of a function node: the parser marks generators with `*` and
+    /// async functions with `async`.
+    pub fn kind_of(stmt: &Stmt) -> FunctionKind {
+        match stmt.operator.as_deref() {
+            Some("*") => FunctionKind::Generator,
+            Some("async") => FunctionKind::Async,
+            _ => FunctionKind::Plain,
+        }
+    }
+
+    /// Parameters of a function node, as the parser lays them out.
+    pub fn params_of(stmt: &Stmt) -> Vec<Param> {
+        stmt.body.iter().flatten()
+            .filter(|param| param.kind == StmtType::Identifier)
+            .map(|param| Param {
+                name: param.value.clone().unwrap_or_default(),
+                optional: param.right.is_some(),
+                rest: param.operator.as_deref() == Some("..."),
+            })
+            .collect()
+    }
+
+    pub fn signature(&self) -> String {
+        let params: Vec<String> = self.params.iter().map(|param| param.to_string()).collect();
+        params.join(", ")
+    }
+}
+
+/// Arguments of a call, after spreading lists.
+#[derive(Debug, Default)]
+pub struct Arguments {
+    pub positional: Vec<Value>,
+    pub named: Vec<(String, Value)>,
+}
+
+impl Arguments {
+    pub fn positional(values: Vec<Value>) -> Self {
+        Arguments { positional: values, named: vec![] }
+    }
+
+    fn count(&self) -> usize {
+        self.positional.len() + self.named.len()
+    }
+}
+
+/// Values of a call's parameter slots.
+pub struct Binding {
+    pub slots: Vec<Value>,
+    /// `true` for each optional parameter that got no argument and still
+    /// has to be set to its default value, inside the call's scope.
+    pub missing: Vec<bool>,
+}
+
+/// A callable value.
+pub enum Function {
+    /// A function created by `fn`, with the scope it captured.
+    Closure { prototype: Arc<Prototype>, scope: Option<ScopeRef> },
+    /// `first >> then`: calls `first` with the arguments and `then` with
+    /// its result.
+    Composed { first: Value, then: Value },
+    /// The `next` method of a generator.
+    Next(Generator),
+    /// A function every program starts with, such as `sleep`.
+    Builtin(Builtin),
+    /// A method of a channel, such as `send`.
+    Channel(Channel, ChannelMethod),
+    /// A function the host registered in Rust, imported with `interop`.
+    Native(Native),
+}
+
+impl Function {
+    pub fn name(&self) -> String {
+        match self {
+            Function::Closure { prototype, .. } => prototype.display_name().to_string(),
+            Function::Composed { first, then } => format!("{} >> {}", callee_name(first), callee_name(then)),
+            Function::Next(_) => "next".to_string(),
+            Function::Builtin(builtin) => builtin.name().to_string(),
+            Function::Channel(_, method) => method.name().to_string(),
+            Function::Native(native) => native.name(),
+        }
+    }
+}
+
+/// Functions are only equal to themselves.
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fn {}>", self.name())
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Name of the function a callee value holds, for messages.
+pub fn callee_name(value: &Value) -> String {
+    match value {
+        Value::Function(function) => function.name(),
+        other => other.to_string(),
+    }
+}
+
+/// Looks up the function behind a callee value, failing for values that
+/// cannot be called.
+pub fn as_function(callee: &Value) -> Result<Arc<Function>, RuntimeError> {
+    match callee {
+        Value::Function(function) => Ok(function.clone()),
+        other => Err(RuntimeError::TypeError { message: format!("{} is not callable.", other) }),
+    }
+}
+
+/// Matches the arguments of a call with the parameters of `prototype`.
+/// Positional arguments fill parameters in order, named ones fill the
+/// parameter with their name, and a rest parameter takes the positional
+/// arguments left over.
+pub fn bind_arguments(prototype: &Prototype, arguments: Arguments) -> Result<Binding, RuntimeError> {
+    let arity_mismatch = || RuntimeError::ArityMismatch {
+        name: prototype.display_name().to_string(),
+        expected: prototype.signature(),
+        found: arguments.count(),
+    };
+
+    let has_rest = prototype.params.last().is_some_and(|param| param.rest);
+    let fixed = prototype.params.len() - has_rest as usize;
+    if arguments.positional.len() > fixed && !has_rest {
+        return Err(arity_mismatch());
+    }
+
+    let mut slots: Vec<Option<Value>> = vec![None; fixed];
+    let mut positional = arguments.positional.iter().cloned();
+    for slot in slots.iter_mut() {
+        *slot = positional.next();
+    }
+    let rest: Vec<Value> = positional.collect();
+
+    for (name, value) in &arguments.named {
+        let index = prototype.params[..fixed].iter().position(|param| &param.name == name).ok_or_else(|| {
+            RuntimeError::TypeError {
+                message: format!("'{}' has no parameter named '{}'.", prototype.display_name(), name)
+            }
+        })?;
+        if slots[index].is_some() {
+            return Err(RuntimeError::TypeError {
+                message: format!("'{}' got more than one value for parameter '{}'.", prototype.display_name(), name)
+            });
+        }
+        slots[index] = Some(value.clone());
+    }
+
+    if slots.iter().zip(&prototype.params).any(|(slot, param)| slot.is_none() && !param.optional) {
+        return Err(arity_mismatch());
+    }
+
+    let missing = slots.iter().map(Option::is_none).collect();
+    let mut slots: Vec<Value> = slots.into_iter().map(|slot| slot.unwrap_or(Value::Null)).collect();
+    if has_rest {
+        slots.push(Value::List(List::new(rest)));
+    }
+    Ok(Binding { slots, missing })
+}
+
+/// `left >> right` and `left << right` on functions. `<<` composes right to
+/// left: `(f << g)(x)` is `f(g(x))`.
+pub fn compose(first: Value, then: Value) -> Value {
+    Value::Function(Arc::new(Function::Composed { first, then }))
+}
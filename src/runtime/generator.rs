@@ -0,0 +1,283 @@
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use std::vec;
+use crate::runtime::environment::ScopeRef;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::function::{Arguments, Prototype};
+use crate::runtime::limits::guard_stack;
+use crate::runtime::value::{Object, Value};
+
+/// How far resuming a generator got.
+#[derive(Debug)]
+pub enum Step {
+    /// The generator yielded this value, or the task of an async call
+    /// awaits it, and can be resumed again.
+    Yield(Value),
+    /// The body finished with this value.
+    Return(Value),
+}
+
+impl Step {
+    pub fn value(self) -> Value {
+        match self {
+            Step::Yield(value) | Step::Return(value) => value,
+        }
+    }
+
+    /// The `{ value, done }` object returned by `.next()`.
+    pub fn into_object(self) -> Value {
+        let done = matches!(self, Step::Return(_));
+        Value::Object(Object::new(vec![
+            ("value".to_string(), self.value()),
+            ("done".to_string(), Value::Boolean(done)),
+        ]))
+    }
+}
+
+/// What runs when a generator is resumed.
+pub enum Body {
+    /// The items of a list iterated by `for`.
+    Items(vec::IntoIter<Value>),
+    /// Tree backend: the body runs on a thread of its own, which only
+    /// proceeds while the generator is being resumed. See `Coroutine`.
+    Thread(Coroutine),
+    /// Vm backend: the call's frame, saved at the last `yield`.
+    Frame(Frame),
+}
+
+/// A generator or async call suspended by the vm.
+pub struct Frame {
+    pub prototype: Arc<Prototype>,
+    /// Where the body continues; 0 before the first resume.
+    pub ip: usize,
+    /// The values the call had on the value stack.
+    pub stack: Vec<Value>,
+    pub scope: Option<ScopeRef>,
+    /// Parameters that still need their default value.
+    pub missing: Vec<bool>,
+    /// Calls the call that made it was nested in; its body runs one deeper.
+    pub depth: usize,
+}
+
+enum State {
+    Suspended(Body),
+    Running,
+    Done,
+}
+
+/// The object a call to a `fn*` function returns. Nothing runs until it is
+/// resumed, by `.next()` or a `for` loop; copies share the same progress.
+#[derive(Clone)]
+pub struct Generator {
+    name: Arc<str>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Generator {
+    pub fn new(name: &str, body: Body) -> Self {
+        Generator { name: name.into(), state: Arc::new(Mutex::new(State::Suspended(body))) }
+    }
+
+    /// Runs the generator up to its next `yield` or the end of its body.
+    /// `sent` becomes the value of the `yield` it was suspended at. Vm
+    /// frames are resumed by `run_frame`; the other bodies run by
+    /// themselves. Once done, a generator keeps returning `null`.
+    pub fn resume(
+        &self,
+        sent: Value,
+        run_frame: impl FnOnce(&mut Frame, Value) -> Result<Step, RuntimeError>,
+    ) -> Result<Step, RuntimeError> {
+        let state = std::mem::replace(&mut *self.lock(), State::Running);
+        let mut body = match state {
+            State::Suspended(body) => body,
+            State::Running => {
+                return Err(RuntimeError::TypeError { message: format!("{} is already running.", self) });
+            },
+            State::Done => {
+                *self.lock() = State::Done;
+                return Ok(Step::Return(Value::Null));
+            },
+        };
+
+        let step = match &mut body {
+            Body::Items(items) => Ok(items.next().map_or(Step::Return(Value::Null), Step::Yield)),
+            Body::Thread(coroutine) => coroutine.resume(sent),
+            Body::Frame(frame) => run_frame(frame, sent),
+        };
+        *self.lock() = match step {
+            Ok(Step::Yield(_)) => State::Suspended(body),
+            _ => State::Done,
+        };
+        step
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Generators are only equal to themselves.
+impl PartialEq for Generator {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl fmt::Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<generator {}>", self.name)
+    }
+}
+
+impl fmt::Debug for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// What a `for` loop iterates over: generators as they are, lists item by
+/// item.
+pub fn iterate(value: Value) -> Result<Generator, RuntimeError> {
+    match value {
+        Value::Generator(generator) => Ok(generator),
+        Value::List(list) => Ok(Generator::new("list", Body::Items(list.to_vec().into_iter()))),
+        other => Err(RuntimeError::TypeError { message: format!("{} is not iterable.", other) }),
+    }
+}
+
+/// The value passed to `.next()`, which takes at most one.
+pub fn sent_value(arguments: Arguments) -> Result<Value, RuntimeError> {
+    if arguments.positional.len() > 1 || !arguments.named.is_empty() {
+        return Err(RuntimeError::ArityMismatch {
+            name: "next".to_string(),
+            expected: "value?".to_string(),
+            found: arguments.positional.len() + arguments.named.len(),
+        });
+    }
+    Ok(arguments.positional.into_iter().next().unwrap_or(Value::Null))
+}
+
+type Start = Box<dyn FnOnce(Yielder) -> Result<Value, RuntimeError> + Send>;
+
+/// Stack of a coroutine thread, for the calls its body makes.
+const COROUTINE_STACK: usize = 16 * 1024 * 1024;
+/// Threads kept waiting for the next coroutine once theirs ended, and how
+/// long each waits before it ends too.
+const IDLE_THREADS: usize = 16;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A body running on its own thread, handing control back and forth with
+/// whoever resumes it so that only one of them runs at a time.
+///
+/// The tree walker keeps where it is in a call on the Rust stack, so unlike
+/// the vm it cannot put a call aside at a `yield` or `await` and pick it up
+/// later; it needs a stack per suspended call instead. Each generator and
+/// async call of the tree backend therefore takes an OS thread from its
+/// first resume until its body ends: running one costs two thread switches
+/// per resume, and every suspended one keeps a thread, where the vm only
+/// keeps a saved frame. Threads are taken from a pool of those whose
+/// coroutine ended, so short-lived generators do not pay for starting one.
+pub struct Coroutine {
+    start: Option<Start>,
+    resume: Option<Sender<Value>>,
+    steps: Option<Receiver<Result<Step, RuntimeError>>>,
+}
+
+impl Coroutine {
+    /// `start` runs on the first resume; it gets the `Yielder` its `yield`s
+    /// go through and returns the value of the body.
+    pub fn new(start: impl FnOnce(Yielder) -> Result<Value, RuntimeError> + Send + 'static) -> Self {
+        Coroutine { start: Some(Box::new(start)), resume: None, steps: None }
+    }
+
+    fn resume(&mut self, sent: Value) -> Result<Step, RuntimeError> {
+        match (self.start.take(), &self.resume) {
+            (Some(start), _) => {
+                let (resume, resumed) = channel();
+                let (step, steps) = channel();
+                run_on_pool(start, Yielder { steps: step, resumed })?;
+                self.resume = Some(resume);
+                self.steps = Some(steps);
+            },
+            (None, Some(resume)) => {
+                let _ = resume.send(sent);
+            },
+            (None, None) => {},
+        }
+
+        self.steps.as_ref()
+            .and_then(|steps| steps.recv().ok())
+            .unwrap_or_else(|| Err(RuntimeError::TypeError { message: "Generator thread stopped unexpectedly.".to_string() }))
+    }
+}
+
+/// Threads waiting for a coroutine to run, each through its own channel.
+static IDLE: Mutex<Vec<Sender<(Start, Yielder)>>> = Mutex::new(Vec::new());
+
+/// Hands a coroutine to an idle thread, or to a new one if none is left.
+/// An idle thread may have just given up waiting, in which case the
+/// coroutine comes back and goes to the next one.
+fn run_on_pool(start: Start, yielder: Yielder) -> Result<(), RuntimeError> {
+    let mut coroutine = (start, yielder);
+    loop {
+        let idle = IDLE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).pop();
+        match idle {
+            Some(thread) => match thread.send(coroutine) {
+                Ok(()) => return Ok(()),
+                Err(returned) => coroutine = returned.0,
+            },
+            None => break,
+        }
+    }
+    thread::Builder::new()
+        .name("coroutine".to_string())
+        .stack_size(COROUTINE_STACK)
+        .spawn(move || run_coroutines(coroutine))
+        .map(drop)
+        .map_err(|error| RuntimeError::TypeError { message: format!("Could not start a generator thread: {}.", error) })
+}
+
+/// Runs `coroutine` and then the ones handed to this thread while idle.
+fn run_coroutines(mut coroutine: (Start, Yielder)) {
+    guard_stack(COROUTINE_STACK);
+    loop {
+        let (start, yielder) = coroutine;
+        let steps = yielder.steps.clone();
+        let result = start(yielder);
+        let _ = steps.send(result.map(Step::Return));
+        drop(steps);
+
+        let (thread, next) = channel();
+        {
+            let mut idle = IDLE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if idle.len() >= IDLE_THREADS {
+                return;
+            }
+            idle.push(thread);
+        }
+        match next.recv_timeout(IDLE_TIMEOUT) {
+            Ok(next) => coroutine = next,
+            Err(_) => return,
+        }
+    }
+}
+
+/// The coroutine's end of the handoff.
+pub struct Yielder {
+    steps: Sender<Result<Step, RuntimeError>>,
+    resumed: Receiver<Value>,
+}
+
+impl Yielder {
+    /// Hands `value` to whoever resumed the coroutine and waits to be
+    /// resumed again, returning the value sent then. If the generator was
+    /// dropped in the meantime this fails like a `return`, which unwinds the
+    /// body and ends the thread.
+    pub fn yield_value(&self, value: Value) -> Result<Value, RuntimeError> {
+        self.steps.send(Ok(Step::Yield(value))).map_err(|_| RuntimeError::Return(Value::Null))?;
+        self.resumed.recv().map_err(|_| RuntimeError::Return(Value::Null))
+    }
+}
@@ -1,178 +1,716 @@
-use crate::frontend::{ast::{Program, Stmt, StmtType}, lexer::tokenize, parser::{produce_ast}};
-use crate::runtime::error::RuntimeError;
-use crate::runtime::value::Value;
+use crate::frontend::{ast::{Program, Slot, Stmt, StmtType}, lexer::tokenize, parser::{produce_ast}, resolver::Resolver};
+use std::sync::Arc;
+use crate::runtime::builtins::{call_builtin, define_builtins};
+use crate::runtime::channel::call_channel_method;
+use crate::runtime::clock::Clock;
+use crate::runtime::environment::{Environment, ScopeRef};
+use crate::runtime::error::{RuntimeError, TraceFrame};
+use crate::runtime::event_loop::{Driver, EventLoop};
+use crate::runtime::function::{as_function, bind_arguments, callee_name, Arguments, Binding, Function, FunctionBody, FunctionKind, Prototype};
+use crate::runtime::limits::{Budget, Interrupt, Limits};
+use crate::runtime::generator::{iterate, sent_value, Body, Coroutine, Generator, Step, Yielder};
+use crate::runtime::native::Natives;
+use crate::runtime::operators::{apply_binary, apply_unary, get_field, get_index, is_truthy, set_field, set_index, spread_items, BinaryOp, Overflow, UnaryOp};
+use crate::runtime::pattern::{destructure, Pattern};
+use crate::runtime::value::{literal_value, List, Object, Value};
+use crate::runtime::worker::spawn_worker;
 
-pub fn interpret(source_code: &str) -> Result<Value, RuntimeError> {
-    let mut tokens = tokenize(source_code);
+/// Tree-walking interpreter. Globals and the resolver state survive between
+/// calls to `interpret`, which is what the REPL relies on.
+#[derive(Default)]
+pub struct Interpreter {
+    environment: Environment,
+    resolver: Resolver,
+    overflow: Overflow,
+    /// Shared with the interpreters running generator and async bodies.
+    event_loop: EventLoop,
+    /// Set when this interpreter runs the body of a generator or the task
+    /// of an async call.
+    yielder: Option<Yielder>,
+    /// Calls the error being raised unwound through so far, innermost
+    /// first. Only `call_function` reads it; async tasks and generators
+    /// keep their own.
+    trace: Vec<TraceFrame>,
+    limits: Limits,
+    interrupt: Interrupt,
+    /// What the current program used of `limits`, shared with the
+    /// interpreters of its generators, async calls and workers.
+    budget: Budget,
+    /// Calls of script functions in progress on this interpreter.
+    depth: usize,
+}
 
-    let ast = produce_ast(&mut tokens)?;
+impl Interpreter {
+    pub fn new() -> Self {
+        let mut interpreter = Self::default();
+        define_builtins(&mut interpreter.environment);
+        interpreter
+    }
 
-    evaluate(ast)
-}
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+    }
 
-fn evaluate(ast: Program) -> Result<Value, RuntimeError> {
-    evaluate_block(ast.body)
-}
+    /// Limits each program, and each call from the host, starts with.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
 
-fn evaluate_block(body: Vec<Stmt>) -> Result<Value, RuntimeError> {
-    let mut last_result = Value::Null;
-    for stmt in body {
-        last_result = evaluate_stmt(stmt)?;
+    /// The handle that stops the program running, or the call from the
+    /// host, at its next step.
+    pub fn interrupt_handle(&self) -> Interrupt {
+        self.interrupt.clone()
     }
-    Ok(last_result)
-}
 
+    /// Makes the functions of `natives` available to `interop`.
+    pub fn register_natives(&mut self, natives: &Natives) {
+        self.environment.define_natives(natives);
+    }
 
-fn evaluate_stmt(stmt: Stmt) -> Result<Value, RuntimeError> {
-    match stmt.kind {
-        StmtType::Program => {
-            Err(RuntimeError::TypeError { message: "Cannot evaluate Program node directly as a statement.".to_string() })
-        },
-        StmtType::NumericLiteral => {
-            let num_str = stmt.value.ok_or_else(|| RuntimeError::TypeError {
-                message: "NumericLiteral is missing a value.".to_string()
-            })?;
-            let num = num_str.parse::<f64>().map_err(|e| RuntimeError::TypeError {
-                message: format!("Failed to parse number '{}': {}", num_str, e),
-            })?;
-            Ok(Value::Number(num))
-        },
-        StmtType::BinaryExpr => eval_binary_expr(stmt),
-        StmtType::Identifier => {
-            Err(RuntimeError::UndefinedVariable { name: stmt.value.unwrap_or_default() })
-        },
-        StmtType::IfStmt => eval_if_stmt(stmt),
-        StmtType::WhileStmt => eval_while_stmt(stmt),
-        StmtType::BlockStmt => {
-            let body = stmt.body.ok_or_else(|| RuntimeError::TypeError {
-                message: "BlockStmt is missing its body.".to_string()
-            })?;
-            evaluate_block(body)
-        },
-        _ => unimplemented!(),
+    /// The clock timers run on; real time unless set.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.event_loop = EventLoop::new(clock);
     }
-}
 
-fn eval_binary_expr(stmt: Stmt) -> Result<Value, RuntimeError> {
-    let left_ast = stmt.left.ok_or_else(|| RuntimeError::TypeError {
-        message: "Binary expression missing left operand.".to_string()
-    })?.as_ref().clone();
+    pub fn interpret(&mut self, source_code: &str) -> Result<Value, RuntimeError> {
+        let mut tokens = tokenize(source_code)?;
 
-    let right_ast = stmt.right.ok_or_else(|| RuntimeError::TypeError {
-        message: "Binary expression missing right operand.".to_string()
-    })?.as_ref().clone();
+        let mut ast = produce_ast(&mut tokens)?;
+        self.resolver.resolve(&mut ast)?;
 
-    let operator = stmt.operator.ok_or_else(|| RuntimeError::TypeError {
-        message: "Binary expression missing operator.".to_string()
-    })?;
+        self.evaluate(&ast)
+    }
 
-    let left_val = evaluate_stmt(left_ast)?;
-    let right_val = evaluate_stmt(right_ast)?;
+    /// Evaluates an already resolved program, then the tasks its async
+    /// calls left on the event loop.
+    pub fn evaluate(&mut self, ast: &Program) -> Result<Value, RuntimeError> {
+        self.trace.clear();
+        self.budget = Budget::new(self.limits, &self.interrupt);
+        let result = self.budget.clone().end(self.evaluate_block(&ast.body).and_then(|value| {
+            let event_loop = self.event_loop.clone();
+            event_loop.finish(self)?;
+            Ok(value)
+        }));
+        if let Err(error) = &result {
+            self.environment.reset_scopes();
+            self.event_loop.abandon(error);
+        }
+        result
+    }
 
-    match operator.as_str() {
-        "*" | "/" | "-" | "+" | "%" => {
-            let left_num = match left_val {
-                Value::Number(n) => n,
-                _ => return Err(RuntimeError::TypeError { message: format!("Left operand of '{}' must be a number, got {}.", operator, left_val) }),
-            };
-            let right_num = match right_val {
-                Value::Number(n) => n,
-                _ => return Err(RuntimeError::TypeError { message: format!("Right operand of '{}' must be a number, got {}.", operator, right_val) }),
-            };
+    /// Defines or replaces the global `name`, as a `def` in a program would.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.resolver.declare_global(name);
+        self.environment.define_global(name, value);
+    }
+
+    pub fn global(&self, name: &str) -> Result<Value, RuntimeError> {
+        self.environment.get_global(name)
+    }
+
+    /// Calls `callee` from outside any program, then runs the tasks the call
+    /// left on the event loop, like `evaluate`. If it fails, `take_trace`
+    /// returns the calls the error unwound through.
+    pub fn call_function(&mut self, callee: Value, arguments: Arguments) -> Result<Value, RuntimeError> {
+        self.trace.clear();
+        self.budget = Budget::new(self.limits, &self.interrupt);
+        let result = self.budget.clone().end(self.call(callee, arguments).and_then(|value| {
+            let event_loop = self.event_loop.clone();
+            event_loop.finish(self)?;
+            Ok(value)
+        }));
+        if let Err(error) = &result {
+            self.environment.reset_scopes();
+            self.event_loop.abandon(error);
+        }
+        result
+    }
+
+    /// The calls the last error of `call_function` unwound through,
+    /// innermost first, without the one the host made.
+    pub fn take_trace(&mut self) -> Vec<TraceFrame> {
+        std::mem::take(&mut self.trace)
+    }
+
+    fn evaluate_block(&mut self, body: &[Stmt]) -> Result<Value, RuntimeError> {
+        let mut last_result = Value::Null;
+        for stmt in body {
+            last_result = self.evaluate_stmt(stmt)?;
+        }
+        Ok(last_result)
+    }
+
+
+    fn evaluate_stmt(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        self.budget.step()?;
+        match stmt.kind {
+            StmtType::Program => {
+                Err(RuntimeError::TypeError { message: "Cannot evaluate Program node directly as a statement.".to_string() })
+            },
+            StmtType::NumericLiteral
+            | StmtType::IntegerLiteral
+            | StmtType::RationalLiteral
+            | StmtType::BooleanLiteral
+            | StmtType::NullLiteral => literal_value(stmt),
+            StmtType::BinaryExpr => self.eval_binary_expr(stmt),
+            StmtType::UnaryExpr => self.eval_unary_expr(stmt),
+            StmtType::CoalesceExpr => self.eval_coalesce_expr(stmt),
+            StmtType::MemberExpr => Ok(self.eval_member_expr(stmt)?.unwrap_or(Value::Null)),
+            StmtType::IndexExpr => Ok(self.eval_index_expr(stmt)?.unwrap_or(Value::Null)),
+            StmtType::ListLiteral => {
+                let items = self.eval_elements(stmt.body.iter().flatten())?;
+                self.budget.check_size(Value::List(List::new(items)))
+            },
+            StmtType::ObjectLiteral => self.eval_object_literal(stmt),
+            StmtType::Identifier => {
+                match stmt.slot {
+                    Some(slot) => self.environment.get(slot),
+                    None => self.environment.get_global(stmt.value.as_deref().unwrap_or_default()),
+                }
+            },
+            StmtType::VariableDeclaration => self.eval_variable_declaration(stmt),
+            StmtType::Assignment => self.eval_assignment(stmt),
+            StmtType::MemberAssignment => self.eval_member_assignment(stmt),
+            StmtType::PostfixUpdate => self.eval_postfix_update(stmt),
+            StmtType::FunctionDeclaration => {
+                let function = self.eval_function(stmt)?;
+                match stmt.slot {
+                    Some(slot) => self.environment.define(slot.index, function.clone())?,
+                    None => self.environment.define_global(stmt.value.as_deref().unwrap_or_default(), function.clone()),
+                }
+                Ok(function)
+            },
+            StmtType::FunctionExpression => self.eval_function(stmt),
+            StmtType::CallExpression => Ok(self.eval_call_expr(stmt)?.unwrap_or(Value::Null)),
+            StmtType::ReturnStmt => {
+                let value = match &stmt.right {
+                    Some(value_ast) => self.evaluate_stmt(value_ast)?,
+                    None => Value::Null,
+                };
+                Err(RuntimeError::Return(value))
+            },
+            StmtType::YieldExpr => {
+                let value = match &stmt.right {
+                    Some(value_ast) => self.evaluate_stmt(value_ast)?,
+                    None => Value::Null,
+                };
+                let yielder = self.yielder.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                    message: "'yield' outside of a generator.".to_string()
+                })?;
+                yielder.yield_value(value)
+            },
+            StmtType::AwaitExpr => {
+                let operand_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                    message: "Await expression missing operand.".to_string()
+                })?;
+                let awaited = self.evaluate_stmt(operand_ast)?;
+                match &self.yielder {
+                    Some(yielder) => yielder.yield_value(awaited),
+                    None => {
+                        let event_loop = self.event_loop.clone();
+                        event_loop.wait(awaited, self)
+                    },
+                }
+            },
+            StmtType::WorkExpr => {
+                let function_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                    message: "Work block missing its body.".to_string()
+                })?;
+                let callee = self.evaluate_stmt(function_ast)?;
+                let (overflow, limits, budget, depth) = (self.overflow, self.limits, self.budget.clone(), self.depth);
+                Ok(spawn_worker(&callee, &self.environment, &self.event_loop, move |environment, event_loop, callee| {
+                    let mut interpreter = Interpreter { environment, overflow, event_loop, limits, budget, depth, ..Interpreter::default() };
+                    let value = interpreter.call(callee, Arguments::default())?;
+                    let event_loop = interpreter.event_loop.clone();
+                    event_loop.finish(&mut interpreter)?;
+                    Ok(value)
+                }))
+            },
+            StmtType::InteropExpr => self.environment.import_natives(stmt.value.as_deref().unwrap_or_default()),
+            StmtType::IfStmt => self.eval_if_stmt(stmt),
+            StmtType::WhileStmt => self.eval_while_stmt(stmt),
+            StmtType::ForInStmt => self.eval_for_in_stmt(stmt),
+            StmtType::BlockStmt => {
+                let body = stmt.body.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                    message: "BlockStmt is missing its body.".to_string()
+                })?;
+                self.environment.push_scope();
+                let result = self.evaluate_block(body);
+                self.environment.pop_scope();
+                result
+            },
+            _ => unimplemented!(),
+        }
+    }
+
+    fn eval_binary_expr(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        let left_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Binary expression missing left operand.".to_string()
+        })?;
+
+        let right_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Binary expression missing right operand.".to_string()
+        })?;
+
+        let operator = stmt.operator.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Binary expression missing operator.".to_string()
+        })?;
 
-            let result = match operator.as_str() {
-                "*" => left_num * right_num,
-                "/" => {
-                    if right_num == 0.0 {
-                        return Err(RuntimeError::DivisionByZero);
-                    }
-                    left_num / right_num
+        let left_val = self.evaluate_stmt(left_ast)?;
+        let right_val = self.evaluate_stmt(right_ast)?;
+
+        self.budget.check_size(apply_binary(operator.parse::<BinaryOp>()?, left_val, right_val, self.overflow)?)
+    }
+
+    fn eval_unary_expr(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        let operand_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Unary expression missing operand.".to_string()
+        })?;
+
+        let operator = stmt.operator.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Unary expression missing operator.".to_string()
+        })?;
+
+        let operand = self.evaluate_stmt(operand_ast)?;
+        self.budget.check_size(apply_unary(operator.parse::<UnaryOp>()?, operand, self.overflow)?)
+    }
+
+    fn eval_coalesce_expr(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        let left_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "'??' expression missing left operand.".to_string()
+        })?;
+
+        let right_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "'??' expression missing right operand.".to_string()
+        })?;
+
+        match self.evaluate_stmt(left_ast)? {
+            Value::Null => self.evaluate_stmt(right_ast),
+            value => Ok(value),
+        }
+    }
+
+    /// The object of a member, an index or a call, which can be one of
+    /// them in turn: `None` once a `?.` further down the chain found
+    /// `null`, which skips the rest of the chain.
+    fn eval_chain(&mut self, stmt: &Stmt) -> Result<Option<Value>, RuntimeError> {
+        let link = match stmt.kind {
+            StmtType::MemberExpr => Self::eval_member_expr,
+            StmtType::IndexExpr => Self::eval_index_expr,
+            StmtType::CallExpression => Self::eval_call_expr,
+            _ => return self.evaluate_stmt(stmt).map(Some),
+        };
+        self.budget.step()?;
+        link(self, stmt)
+    }
+
+    fn eval_member_expr(&mut self, stmt: &Stmt) -> Result<Option<Value>, RuntimeError> {
+        let object_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Member expression missing object.".to_string()
+        })?;
+
+        let Some(object) = self.eval_chain(object_ast)? else {
+            return Ok(None);
+        };
+        let optional = stmt.operator.as_deref() == Some("?.");
+        if optional && matches!(object, Value::Null) {
+            return Ok(None);
+        }
+        get_field(object, stmt.value.as_deref().unwrap_or_default(), optional).map(Some)
+    }
+
+    fn eval_index_expr(&mut self, stmt: &Stmt) -> Result<Option<Value>, RuntimeError> {
+        let object_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Index expression missing object.".to_string()
+        })?;
+
+        let index_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Index expression missing index.".to_string()
+        })?;
+
+        let Some(object) = self.eval_chain(object_ast)? else {
+            return Ok(None);
+        };
+        let index = self.evaluate_stmt(index_ast)?;
+        get_index(object, index).map(Some)
+    }
+
+    /// Values of list items or positional arguments, with spreads expanded.
+    fn eval_elements<'s>(&mut self, elements: impl Iterator<Item = &'s Stmt>) -> Result<Vec<Value>, RuntimeError> {
+        let mut values = Vec::new();
+        for element in elements {
+            match (&element.kind, &element.right) {
+                (StmtType::Spread, Some(list)) => {
+                    let list = self.evaluate_stmt(list)?;
+                    values.extend(spread_items(list)?);
                 },
-                "-" => left_num - right_num,
-                "+" => left_num + right_num,
-                "%" => left_num % right_num,
-                _ => unreachable!(),
+                _ => values.push(self.evaluate_stmt(element)?),
+            }
+        }
+        Ok(values)
+    }
+
+    fn eval_object_literal(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        let mut fields = Vec::new();
+        for property in stmt.body.iter().flatten() {
+            let value_ast = property.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                message: "Object property missing value.".to_string()
+            })?;
+            fields.push((property.value.clone().unwrap_or_default(), self.evaluate_stmt(value_ast)?));
+        }
+        self.budget.check_size(Value::Object(Object::new(fields)))
+    }
+
+    /// Declares or assigns the names of a destructuring pattern from the
+    /// parts of `value`, left to right, taking apart those matched by a
+    /// nested pattern in turn. `declaration` is `None` for an assignment and
+    /// otherwise tells whether the names are `const`.
+    fn bind_pattern(&mut self, pattern_ast: &Stmt, value: &Value, declaration: Option<bool>) -> Result<(), RuntimeError> {
+        let pattern = Pattern::of(pattern_ast).ok_or_else(|| RuntimeError::TypeError {
+            message: format!("{:?} is not a pattern.", pattern_ast.kind)
+        })?;
+        let binding = destructure(&pattern, value)?;
+
+        for (index, (name, part)) in pattern_ast.body.iter().flatten().zip(binding.slots).enumerate() {
+            let part = match (&name.right, binding.missing.get(index)) {
+                (Some(default), Some(true)) => self.evaluate_stmt(default)?,
+                _ => part,
             };
-            Ok(Value::Number(result))
-        },
-        "==" | "!=" | "<" | "<=" | ">" | ">=" => {
-            let left_num = match left_val {
-                Value::Number(n) => n,
-                _ => return Err(RuntimeError::TypeError { message: format!("Left operand of comparison '{}' must be a number, got {}.", operator, left_val) }),
+            if matches!(name.kind, StmtType::ListPattern | StmtType::ObjectPattern) {
+                self.bind_pattern(name, &part, declaration)?;
+                continue;
+            }
+            let text = name.value.as_deref().unwrap_or_default();
+            match (name.slot, declaration) {
+                (slot, Some(constant)) => self.define_variable(slot, text, part, constant)?,
+                (Some(slot), None) => self.environment.set(slot, part)?,
+                (None, None) => self.environment.set_global(text, part)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn define_variable(&mut self, slot: Option<Slot>, name: &str, value: Value, constant: bool) -> Result<(), RuntimeError> {
+        match slot {
+            Some(slot) => self.environment.define(slot.index, value)?,
+            None if constant => self.environment.define_constant(name, value),
+            None => self.environment.define_global(name, value),
+        }
+        Ok(())
+    }
+
+    fn eval_variable_declaration(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        let initializer = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Variable declaration missing initializer.".to_string()
+        })?;
+
+        let value = self.evaluate_stmt(initializer)?;
+        let constant = stmt.operator.is_some();
+        match &stmt.left {
+            Some(pattern) => self.bind_pattern(pattern, &value, Some(constant))?,
+            None => self.define_variable(stmt.slot, stmt.value.as_deref().unwrap_or_default(), value.clone(), constant)?,
+        }
+        Ok(value)
+    }
+
+    /// `object.field = value` and `list[index] = value`.
+    fn eval_member_assignment(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        let target = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Member assignment missing target.".to_string()
+        })?;
+        let value_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Member assignment missing value.".to_string()
+        })?;
+
+        let (object, index) = self.eval_member_target(target)?;
+        let value = match &stmt.operator {
+            Some(operator) => {
+                let current = read_member(target, &object, &index)?;
+                let value = self.evaluate_stmt(value_ast)?;
+                self.budget.check_size(apply_binary(operator.parse::<BinaryOp>()?, current, value, self.overflow)?)?
+            },
+            None => self.evaluate_stmt(value_ast)?,
+        };
+        self.write_member(target, object, index, value.clone())?;
+        Ok(value)
+    }
+
+    /// The object of a field or item about to be assigned to, and the index
+    /// of an item, each evaluated once.
+    fn eval_member_target(&mut self, target: &Stmt) -> Result<(Value, Option<Value>), RuntimeError> {
+        let object_ast = target.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Member assignment missing object.".to_string()
+        })?;
+
+        let object = self.evaluate_stmt(object_ast)?;
+        let index = match (&target.kind, &target.right) {
+            (StmtType::IndexExpr, Some(index_ast)) => Some(self.evaluate_stmt(index_ast)?),
+            _ => None,
+        };
+        Ok((object, index))
+    }
+
+    fn write_member(&mut self, target: &Stmt, object: Value, index: Option<Value>, value: Value) -> Result<(), RuntimeError> {
+        match index {
+            Some(index) => set_index(object.clone(), index, value)?,
+            None => set_field(object.clone(), target.value.as_deref().unwrap_or_default(), value)?,
+        }
+        self.budget.check_size(object).map(drop)
+    }
+
+    fn eval_assignment(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        let value_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Assignment missing value.".to_string()
+        })?;
+
+        let value = self.evaluate_stmt(value_ast)?;
+        if let Some(pattern) = &stmt.left {
+            self.bind_pattern(pattern, &value, None)?;
+            return Ok(value);
+        }
+        match stmt.slot {
+            Some(slot) => self.environment.set(slot, value.clone())?,
+            None => self.environment.set_global(stmt.value.as_deref().unwrap_or_default(), value.clone())?,
+        }
+        Ok(value)
+    }
+
+    fn eval_postfix_update(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        let operator = stmt.operator.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Postfix update missing operator.".to_string()
+        })?;
+        if let Some(target) = &stmt.left {
+            let (object, index) = self.eval_member_target(target)?;
+            let previous = read_member(target, &object, &index)?;
+            let updated = self.budget.check_size(apply_binary(operator.parse::<BinaryOp>()?, previous.clone(), Value::Integer(1), self.overflow)?)?;
+            self.write_member(target, object, index, updated)?;
+            return Ok(previous);
+        }
+        let name = stmt.value.as_deref().unwrap_or_default();
+
+        let previous = match stmt.slot {
+            Some(slot) => self.environment.get(slot)?,
+            None => self.environment.get_global(name)?,
+        };
+        let updated = self.budget.check_size(apply_binary(operator.parse::<BinaryOp>()?, previous.clone(), Value::Integer(1), self.overflow)?)?;
+        match stmt.slot {
+            Some(slot) => self.environment.set(slot, updated)?,
+            None => self.environment.set_global(name, updated)?,
+        }
+        Ok(previous)
+    }
+
+    /// Creates a closure over the current scope. The function node is
+    /// copied out of the program so the function can outlive it, e.g.
+    /// across REPL lines.
+    fn eval_function(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        let prototype = Prototype {
+            name: stmt.value.clone(),
+            kind: Prototype::kind_of(stmt),
+            params: Prototype::params_of(stmt),
+            body: FunctionBody::Tree(stmt.clone()),
+        };
+        let scope = self.environment.current_scope();
+        Ok(Value::Function(Arc::new(Function::Closure { prototype: Arc::new(prototype), scope })))
+    }
+
+    fn eval_call_expr(&mut self, stmt: &Stmt) -> Result<Option<Value>, RuntimeError> {
+        let callee_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "Call expression missing callee.".to_string()
+        })?;
+
+        let Some(callee) = self.eval_chain(callee_ast)? else {
+            return Ok(None);
+        };
+        let arguments = stmt.body.iter().flatten();
+        let positional = self.eval_elements(arguments.clone().filter(|argument| argument.kind != StmtType::NamedArgument))?;
+        let mut named = Vec::new();
+        for argument in arguments.filter(|argument| argument.kind == StmtType::NamedArgument) {
+            let value_ast = argument.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                message: "Named argument missing value.".to_string()
+            })?;
+            named.push((argument.value.clone().unwrap_or_default(), self.evaluate_stmt(value_ast)?));
+        }
+        self.call(callee.clone(), Arguments { positional, named }).map(Some).inspect_err(|_| {
+            self.trace.push(TraceFrame { function: callee_name(&callee), line: Some(stmt.line) });
+        })
+    }
+
+    fn call(&mut self, callee: Value, arguments: Arguments) -> Result<Value, RuntimeError> {
+        match &*as_function(&callee)? {
+            Function::Closure { prototype, scope } => {
+                let FunctionBody::Tree(function) = &prototype.body else {
+                    return Err(RuntimeError::TypeError {
+                        message: format!("{} was compiled for the vm backend.", callee)
+                    });
+                };
+                let body = function.consequent.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                    message: "Function missing its body.".to_string()
+                })?;
+                let binding = bind_arguments(prototype, arguments)?;
+                self.budget.check_depth(self.depth + 1)?;
+                match prototype.kind {
+                    FunctionKind::Generator => {
+                        return Ok(Value::Generator(self.start_generator(prototype.clone(), scope.clone(), binding)));
+                    },
+                    FunctionKind::Async => {
+                        let task = self.start_generator(prototype.clone(), scope.clone(), binding);
+                        return Ok(Value::Promise(self.event_loop.spawn(task)));
+                    },
+                    FunctionKind::Plain => {},
+                }
+
+                self.depth += 1;
+                let caller = self.environment.enter_call(scope.clone(), binding.slots);
+                let result = self.fill_defaults(function, &binding.missing).and_then(|_| self.evaluate_stmt(body));
+                self.environment.restore_scope(caller);
+                self.depth -= 1;
+                match result {
+                    Err(RuntimeError::Return(value)) => Ok(value),
+                    result => result,
+                }
+            },
+            Function::Composed { first, then } => {
+                let intermediate = self.call(first.clone(), arguments)?;
+                self.call(then.clone(), Arguments::positional(vec![intermediate]))
+            },
+            Function::Next(generator) => Ok(self.resume(generator, sent_value(arguments)?)?.into_object()),
+            Function::Builtin(builtin) => call_builtin(*builtin, arguments, &self.event_loop),
+            Function::Channel(channel, method) => call_channel_method(channel, *method, arguments, &self.event_loop),
+            Function::Native(native) => self.budget.check_size(native.call(arguments)?),
+        }
+    }
+
+    /// The generator a call to a `fn*` function returns, or the task of an
+    /// async call. Its body runs in an interpreter of its own, on a
+    /// coroutine thread, sharing the globals and the event loop of this
+    /// one; defaults are filled in on the first resume. Its calls count as
+    /// nested in the one that started it.
+    fn start_generator(&self, prototype: Arc<Prototype>, scope: Option<ScopeRef>, binding: Binding) -> Generator {
+        let name = prototype.display_name().to_string();
+        let environment = self.environment.fork(Some(Environment::call_scope(scope, binding.slots)));
+        let (overflow, limits, budget, depth) = (self.overflow, self.limits, self.budget.clone(), self.depth + 1);
+        let event_loop = self.event_loop.clone();
+        let coroutine = Coroutine::new(move |yielder| {
+            let FunctionBody::Tree(function) = &prototype.body else {
+                return Err(RuntimeError::TypeError {
+                    message: format!("{} was compiled for the vm backend.", prototype.display_name())
+                });
             };
-            let right_num = match right_val {
-                Value::Number(n) => n,
-                _ => return Err(RuntimeError::TypeError { message: format!("Right operand of comparison '{}' must be a number, got {}.", operator, right_val) }),
+            let body = function.consequent.as_ref().ok_or_else(|| RuntimeError::TypeError {
+                message: "Function missing its body.".to_string()
+            })?;
+            let mut interpreter = Interpreter {
+                environment, overflow, event_loop, limits, budget, depth, yielder: Some(yielder), ..Interpreter::default()
             };
+            let result = interpreter.fill_defaults(function, &binding.missing).and_then(|_| interpreter.evaluate_stmt(body));
+            match result {
+                Err(RuntimeError::Return(value)) => Ok(value),
+                result => result,
+            }
+        });
+        Generator::new(&name, Body::Thread(coroutine))
+    }
 
-            let result = match operator.as_str() {
-                "==" => left_num == right_num,
-                "!=" => left_num != right_num,
-                "<" => left_num < right_num,
-                "<=" => left_num <= right_num,
-                ">" => left_num > right_num,
-                ">=" => left_num >= right_num,
-                _ => unreachable!(),
-            };
-            Ok(Value::Boolean(result))
-        },
-        _ => Err(RuntimeError::UnknownOperator { operator }),
+    fn resume(&mut self, generator: &Generator, sent: Value) -> Result<Step, RuntimeError> {
+        generator.resume(sent, |frame, _| Err(RuntimeError::TypeError {
+            message: format!("{} was compiled for the vm backend.", frame.prototype.display_name())
+        }))
     }
-}
 
+    /// Evaluates, in the call's scope, the defaults of the parameters that
+    /// got no argument.
+    fn fill_defaults(&mut self, function: &Stmt, missing: &[bool]) -> Result<(), RuntimeError> {
+        for (param, _) in function.body.iter().flatten().zip(missing).filter(|(_, missing)| **missing) {
+            if let (Some(default), Some(slot)) = (&param.right, param.slot) {
+                let value = self.evaluate_stmt(default)?;
+                self.environment.set(slot, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn eval_if_stmt(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        let condition_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "If statement missing condition.".to_string()
+        })?;
+
+        let consequent_ast = stmt.consequent.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "If statement missing consequent block.".to_string()
+        })?;
+
+        let condition_val = self.evaluate_stmt(condition_ast)?;
 
-fn eval_if_stmt(stmt: Stmt) -> Result<Value, RuntimeError> {
-    let condition_ast = stmt.left.ok_or_else(|| RuntimeError::TypeError {
-        message: "If statement missing condition.".to_string()
-    })?.as_ref().clone();
+        if is_truthy(&condition_val, "If")? {
+            self.evaluate_stmt(consequent_ast)
+        } else if let Some(alternate) = &stmt.right {
+            self.evaluate_stmt(alternate)
+        } else {
+            Ok(Value::Null)
+        }
+    }
+
+    fn eval_while_stmt(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        let condition_ast = stmt.left.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "While statement missing condition.".to_string()
+        })?;
 
-    let consequent_ast = stmt.consequent.ok_or_else(|| RuntimeError::TypeError {
-        message: "If statement missing consequent block.".to_string()
-    })?.as_ref().clone();
+        let consequent_ast = stmt.consequent.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "While statement missing consequent block.".to_string()
+        })?;
 
-    let condition_val = evaluate_stmt(condition_ast)?;
+        loop {
+            let condition_val = self.evaluate_stmt(condition_ast)?;
 
-    let is_truthy = match condition_val {
-        Value::Boolean(b) => b,
-        Value::Number(n) => n != 0.0,
-        Value::Null => false,
-        _ => return Err(RuntimeError::TypeError { message: format!("If condition must evaluate to a boolean or number, got {}.", condition_val) }),
-    };
+            if !is_truthy(&condition_val, "While")? {
+                break Ok(Value::Null);
+            }
 
-    if is_truthy {
-        evaluate_stmt(consequent_ast)
-    } else if let Some(alternate_box) = stmt.right {
-        evaluate_stmt(*alternate_box)
-    } else {
+            self.evaluate_stmt(consequent_ast)?;
+        }
+    }
+
+    fn eval_for_in_stmt(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
+        let iterable_ast = stmt.right.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "For statement missing iterable.".to_string()
+        })?;
+
+        let consequent_ast = stmt.consequent.as_ref().ok_or_else(|| RuntimeError::TypeError {
+            message: "For statement missing consequent block.".to_string()
+        })?;
+
+        let generator = iterate(self.evaluate_stmt(iterable_ast)?)?;
+        while let Step::Yield(item) = self.resume(&generator, Value::Null)? {
+            self.environment.push_scope();
+            let result = match &stmt.left {
+                Some(pattern) => self.bind_pattern(pattern, &item, Some(false)),
+                None => self.define_variable(stmt.slot, stmt.value.as_deref().unwrap_or_default(), item, false),
+            }.and_then(|_| self.evaluate_stmt(consequent_ast));
+            self.environment.pop_scope();
+            result?;
+        }
         Ok(Value::Null)
     }
 }
 
-fn eval_while_stmt(stmt: Stmt) -> Result<Value, RuntimeError> {
-    let condition_ast_box = stmt.left.ok_or_else(|| RuntimeError::TypeError {
-        message: "While statement missing condition.".to_string()
-    })?;
-
-    let consequent_ast_box = stmt.consequent.ok_or_else(|| RuntimeError::TypeError {
-        message: "While statement missing consequent block.".to_string()
-    })?;
-
-    loop {
-        let condition_val = evaluate_stmt(condition_ast_box.as_ref().clone())?;
-
-        let is_truthy = match condition_val {
-            Value::Boolean(b) => b,
-            Value::Number(n) => n != 0.0,
-            Value::Null => false,
-            _ => return Err(RuntimeError::TypeError {
-                message: format!("While condition must evaluate to a boolean or number, got {}.", condition_val)
-            }),
-        };
+impl Driver for Interpreter {
+    fn resume(&mut self, generator: &Generator, sent: Value) -> Result<Step, RuntimeError> {
+        Interpreter::resume(self, generator, sent)
+    }
 
-        if !is_truthy {
-            break Ok(Value::Null);
-        }
+    fn call(&mut self, callee: Value, arguments: Arguments) -> Result<Value, RuntimeError> {
+        Interpreter::call(self, callee, arguments)
+    }
 
-        evaluate_stmt(consequent_ast_box.as_ref().clone())?;
+    fn budget(&self) -> &Budget {
+        &self.budget
     }
-}
\ No newline at end of file
+}
+
+/// Current value of the field or item `target` names on `object`.
+fn read_member(target: &Stmt, object: &Value, index: &Option<Value>) -> Result<Value, RuntimeError> {
+    match index {
+        Some(index) => get_index(object.clone(), index.clone()),
+        None => get_field(object.clone(), target.value.as_deref().unwrap_or_default(), false),
+    }
+}
@@ -0,0 +1,310 @@
+use std::cell::Cell;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::runtime::error::RuntimeError;
+use crate::runtime::value::Value;
+
+/// How deeply calls may nest unless the limits say otherwise.
+pub const DEFAULT_CALL_DEPTH: usize = 1_000;
+
+/// Caps on what a program may use, each failing it with an error of its
+/// own once reached. `None` leaves that resource unlimited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// Steps the program may take: nodes the tree walker evaluates, or
+    /// instructions the vm executes. Async tasks and workers take theirs
+    /// from the same fuel.
+    pub fuel: Option<u64>,
+    /// How deeply calls of script functions may nest. There is always a
+    /// limit, since the calls would otherwise overflow the stack, and calls
+    /// also fail once they come close to the end of the stack of their
+    /// thread, should that happen first.
+    pub call_depth: usize,
+    /// Bytes a single string, big number, list or object may keep on the
+    /// heap for itself, not counting the values it holds.
+    pub value_size: Option<usize>,
+    /// Bytes the lists and objects of the program may keep on the heap at
+    /// once, with the strings and big numbers in them, on all its threads
+    /// together. Each counts from when the program builds or changes it
+    /// until its last copy is gone; a string or big number only counts
+    /// while a list or object holds it.
+    pub heap: Option<usize>,
+    /// Wall-clock time the program may run for, waiting for timers and
+    /// other threads included.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { fuel: None, call_depth: DEFAULT_CALL_DEPTH, value_size: None, heap: None, timeout: None }
+    }
+}
+
+/// Stops the program running with it from another thread, or a signal
+/// handler, at its next step, or while it waits. The program fails with
+/// `Interrupted`, which clears the request; one made while no program runs
+/// stops the next one right away. Copies share the request.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt(Arc<AtomicBool>);
+
+impl Interrupt {
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Drops a request no program stopped at yet.
+    pub fn clear(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// How many steps go by between two looks at the time.
+const TIMEOUT_CHECK: u64 = 1024;
+/// How long a wait goes on before looking for an interrupt, which cannot
+/// wake it up.
+const INTERRUPT_CHECK: Duration = Duration::from_millis(50);
+
+/// The limits of one run and what it used of them so far, and whether it
+/// was interrupted. Copies share the count, so one budget covers every
+/// thread of a program.
+#[derive(Clone, Default)]
+pub struct Budget(Option<Arc<Usage>>);
+
+struct Usage {
+    limits: Limits,
+    steps: AtomicU64,
+    heap: Heap,
+    deadline: Option<Instant>,
+    interrupt: Interrupt,
+    /// Set once a thread of the run saw the interrupt, so the others stop
+    /// too once the run cleared it.
+    interrupted: AtomicBool,
+}
+
+impl Budget {
+    /// Starts counting, and listening to `interrupt`; the timeout runs from
+    /// now.
+    pub fn new(limits: Limits, interrupt: &Interrupt) -> Self {
+        let deadline = limits.timeout.map(|timeout| Instant::now() + timeout);
+        Budget(Some(Arc::new(Usage {
+            limits,
+            steps: AtomicU64::new(0),
+            heap: Heap::default(),
+            deadline,
+            interrupt: interrupt.clone(),
+            interrupted: AtomicBool::new(false),
+        })))
+    }
+
+    /// Takes one step, failing once the program is interrupted or the fuel
+    /// or the time runs out.
+    pub fn step(&self) -> Result<(), RuntimeError> {
+        let Some(usage) = &self.0 else {
+            return Ok(());
+        };
+        usage.check_interrupt()?;
+        if usage.limits.fuel.is_none() && usage.limits.timeout.is_none() {
+            return Ok(());
+        }
+        let steps = usage.steps.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(fuel) = usage.limits.fuel.filter(|fuel| steps > *fuel) {
+            return Err(RuntimeError::OutOfFuel { fuel });
+        }
+        match steps % TIMEOUT_CHECK {
+            0 => usage.check_deadline(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails once the program is interrupted or the time runs out, like
+    /// `step` without taking one; for the event loop to call as it waits.
+    pub fn check(&self) -> Result<(), RuntimeError> {
+        match &self.0 {
+            Some(usage) => usage.check_interrupt().and_then(|_| usage.check_deadline()),
+            None => Ok(()),
+        }
+    }
+
+    /// How much of a wait for `wait` may go by before `check` has to be
+    /// called again: no longer than until the deadline, and only a short
+    /// while at a time, since nothing wakes a wait up on an interrupt.
+    pub fn wait_limit(&self, wait: Duration) -> Duration {
+        let Some(usage) = &self.0 else {
+            return wait;
+        };
+        let wait = wait.min(INTERRUPT_CHECK);
+        match usage.deadline {
+            Some(deadline) => wait.min(deadline.saturating_duration_since(Instant::now())),
+            None => wait,
+        }
+    }
+
+    /// Ends the run with `result`. An interrupt it stopped at is done with,
+    /// so the next run goes on.
+    pub fn end<T>(&self, result: Result<T, RuntimeError>) -> Result<T, RuntimeError> {
+        if let (Some(usage), Err(RuntimeError::Interrupted)) = (&self.0, &result) {
+            usage.interrupt.clear();
+        }
+        result
+    }
+
+    /// Fails if a call would nest `depth` calls deep, or if the stack of
+    /// this thread has too little left for it.
+    pub fn check_depth(&self, depth: usize) -> Result<(), RuntimeError> {
+        let limit = self.0.as_ref().map_or(DEFAULT_CALL_DEPTH, |usage| usage.limits.call_depth);
+        if depth > limit || stack_address() < stack_end() {
+            return Err(RuntimeError::CallDepthExceeded { limit });
+        }
+        Ok(())
+    }
+
+    /// Passes `value` on, just built or changed, unless it is larger than
+    /// allowed or the lists and objects of the program take up too much
+    /// of the heap with it.
+    pub fn check_size(&self, value: Value) -> Result<Value, RuntimeError> {
+        let Some(usage) = &self.0 else {
+            return Ok(value);
+        };
+        match usage.limits.value_size {
+            Some(limit) if value.size_in_bytes() > limit => return Err(RuntimeError::ValueTooLarge { limit }),
+            _ => {},
+        }
+        match usage.limits.heap {
+            Some(limit) if value.charge(&usage.heap) > limit => Err(RuntimeError::HeapLimitExceeded { limit }),
+            _ => Ok(value),
+        }
+    }
+}
+
+/// Bytes the lists and objects of a run keep on the heap together. Copies
+/// share the count.
+#[derive(Clone, Default)]
+pub(crate) struct Heap(Arc<AtomicUsize>);
+
+impl Heap {
+    /// Adds `bytes`, returning the bytes held then.
+    pub(crate) fn charge(&self, bytes: usize) -> usize {
+        self.0.fetch_add(bytes, Ordering::Relaxed) + bytes
+    }
+
+    pub(crate) fn release(&self, bytes: usize) {
+        self.0.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn bytes(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Usage {
+    fn check_interrupt(&self) -> Result<(), RuntimeError> {
+        if self.interrupted.load(Ordering::Relaxed) || self.interrupt.is_requested() {
+            self.interrupted.store(true, Ordering::Relaxed);
+            return Err(RuntimeError::Interrupted);
+        }
+        Ok(())
+    }
+
+    fn check_deadline(&self) -> Result<(), RuntimeError> {
+        match (self.limits.timeout, self.deadline) {
+            (Some(timeout), Some(deadline)) if Instant::now() >= deadline => {
+                Err(RuntimeError::ExecutionTimeout { milliseconds: timeout.as_millis() })
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Stack of the threads workers run on.
+pub(crate) const WORKER_STACK: usize = 256 * 1024 * 1024;
+/// Stack kept free of calls, for what a call does before the next one is
+/// checked: evaluating its arguments, natives, printing values. Threads
+/// with a small stack keep half of it instead.
+const STACK_RESERVE: usize = 1024 * 1024;
+/// Stack taken to be left on a thread whose bounds cannot be found: what
+/// Rust gives the threads it starts.
+const ASSUMED_STACK: usize = 2 * 1024 * 1024;
+
+thread_local! {
+    /// The address past which the stack of this thread has too little left
+    /// for another call, once known.
+    static STACK_END: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+fn stack_address() -> usize {
+    let marker = 0u8;
+    black_box(&marker) as *const u8 as usize
+}
+
+/// The address past which the stack of the current thread has too little
+/// left. Programs run on the thread of whoever runs them, the host's
+/// included, so this looks up where its stack ends the first time.
+fn stack_end() -> usize {
+    STACK_END.get().unwrap_or_else(|| {
+        let (lowest, size) = stack_bounds().unwrap_or_else(|| {
+            (stack_address().saturating_sub(ASSUMED_STACK), ASSUMED_STACK)
+        });
+        let end = lowest.saturating_add(STACK_RESERVE.min(size / 2));
+        STACK_END.set(Some(end));
+        end
+    })
+}
+
+/// Tells `Budget::check_depth` that the current thread, which started with
+/// a stack of `size` bytes, is about at its start. Stacks grow down.
+pub(crate) fn guard_stack(size: usize) {
+    let reserve = STACK_RESERVE.min(size / 2);
+    STACK_END.set(Some(stack_address().saturating_sub(size.saturating_sub(reserve))));
+}
+
+/// The lowest address and the size of the stack of the current thread.
+#[cfg(target_os = "linux")]
+fn stack_bounds() -> Option<(usize, usize)> {
+    /// Room for a `pthread_attr_t`, which is opaque and smaller than this
+    /// on every target.
+    #[repr(C, align(16))]
+    struct Attributes([u8; 128]);
+    extern "C" {
+        fn pthread_self() -> usize;
+        fn pthread_getattr_np(thread: usize, attributes: *mut Attributes) -> i32;
+        fn pthread_attr_getstack(attributes: *const Attributes, address: *mut usize, size: *mut usize) -> i32;
+        fn pthread_attr_destroy(attributes: *mut Attributes) -> i32;
+    }
+    let mut attributes = Attributes([0; 128]);
+    let (mut lowest, mut size) = (0, 0);
+    // SAFETY: `attributes` is only read after `pthread_getattr_np` filled it
+    // in, and destroyed once.
+    unsafe {
+        if pthread_getattr_np(pthread_self(), &mut attributes) != 0 {
+            return None;
+        }
+        let found = pthread_attr_getstack(&attributes, &mut lowest, &mut size) == 0;
+        pthread_attr_destroy(&mut attributes);
+        (found && size > 0).then_some((lowest, size))
+    }
+}
+
+/// The lowest address and the size of the stack of the current thread.
+#[cfg(target_os = "macos")]
+fn stack_bounds() -> Option<(usize, usize)> {
+    extern "C" {
+        fn pthread_self() -> usize;
+        fn pthread_get_stackaddr_np(thread: usize) -> usize;
+        fn pthread_get_stacksize_np(thread: usize) -> usize;
+    }
+    // SAFETY: both only read the bounds of the calling thread.
+    let (highest, size) = unsafe { (pthread_get_stackaddr_np(pthread_self()), pthread_get_stacksize_np(pthread_self())) };
+    (size > 0).then(|| (highest.saturating_sub(size), size))
+}
+
+/// The lowest address and the size of the stack of the current thread.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn stack_bounds() -> Option<(usize, usize)> {
+    None
+}
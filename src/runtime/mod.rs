@@ -3,5 +3,24 @@ pub mod interpreter;
 pub mod runner;
 pub mod environment;
 pub mod event_loop;
+pub mod clock;
+pub mod builtins;
+pub mod worker;
+pub mod channel;
+pub mod native;
+pub mod engine;
+pub mod convert;
+pub mod limits;
 pub mod value;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod operators;
+pub mod bytecode;
+pub mod compiler;
+pub mod vm;
+pub mod rbc;
+pub mod disassembler;
+pub mod optimizer;
+pub mod bignum;
+pub mod function;
+pub mod pattern;
+pub mod generator;
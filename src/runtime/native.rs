@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::function::{Arguments, Function};
+use crate::runtime::value::{freeze, Object, Value};
+
+/// The Rust side of a native function: it gets the positional arguments of
+/// the call and returns its value.
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value, RuntimeError> + Send + Sync;
+
+/// A function the host wrote in Rust, callable from scripts once imported
+/// with `interop`. It is shared, not copied, by workers, so it may be
+/// called from several threads at once.
+#[derive(Clone)]
+pub struct Native {
+    namespace: String,
+    name: String,
+    function: Arc<NativeFn>,
+}
+
+impl Native {
+    /// `namespace.name`, as errors show it.
+    pub fn name(&self) -> String {
+        format!("{}.{}", self.namespace, self.name)
+    }
+
+    /// Calls the function. Natives have no parameter names, so named
+    /// arguments are an error.
+    pub fn call(&self, arguments: Arguments) -> Result<Value, RuntimeError> {
+        if !arguments.named.is_empty() {
+            return Err(RuntimeError::TypeError {
+                message: format!("'{}' does not take named arguments.", self.name())
+            });
+        }
+        (self.function)(&arguments.positional)
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native {}>", self.name())
+    }
+}
+
+/// Native functions grouped by namespace. The host fills one in and hands
+/// it to a backend; a script then imports a namespace with `interop math`,
+/// or some of its functions with `interop math { sqrt, max }`.
+#[derive(Debug, Clone, Default)]
+pub struct Natives {
+    namespaces: HashMap<String, Vec<Native>>,
+}
+
+impl Natives {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `function` to `namespace` as `name`, replacing any function
+    /// registered there under the same name.
+    pub fn register(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        function: impl Fn(&[Value]) -> Result<Value, RuntimeError> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.add(Native { namespace: namespace.to_string(), name: name.to_string(), function: Arc::new(function) });
+        self
+    }
+
+    /// Adds every function of `other`, which wins where both have one.
+    pub fn extend(&mut self, other: &Natives) {
+        for native in other.namespaces.values().flatten() {
+            self.add(native.clone());
+        }
+    }
+
+    fn add(&mut self, native: Native) {
+        let functions = self.namespaces.entry(native.namespace.clone()).or_default();
+        match functions.iter_mut().find(|existing| existing.name == native.name) {
+            Some(existing) => *existing = native,
+            None => functions.push(native),
+        }
+    }
+
+    /// What `interop namespace` imports: a frozen object with a field per
+    /// function, in the order they were registered.
+    pub fn import(&self, namespace: &str) -> Result<Value, RuntimeError> {
+        let functions = self.namespaces.get(namespace).ok_or_else(|| RuntimeError::UnknownNamespace {
+            namespace: namespace.to_string()
+        })?;
+        let fields = functions.iter()
+            .map(|native| (native.name.clone(), Value::Function(Arc::new(Function::Native(native.clone())))))
+            .collect();
+        let namespace = Value::Object(Object::new(fields));
+        freeze(&namespace);
+        Ok(namespace)
+    }
+}
+
+/// The natives the command line registers: a few `math` functions that the
+/// operators do not cover.
+pub fn standard_natives() -> Natives {
+    let mut natives = Natives::new();
+    natives
+        .register("math", "sqrt", |arguments| unary(arguments, "math.sqrt", f64::sqrt))
+        .register("math", "floor", |arguments| unary(arguments, "math.floor", f64::floor))
+        .register("math", "abs", |arguments| match arguments {
+            [Value::Integer(n)] => n.checked_abs().map(Value::Integer).ok_or_else(|| RuntimeError::IntegerOverflow {
+                operator: "math.abs".to_string()
+            }),
+            _ => unary(arguments, "math.abs", f64::abs),
+        })
+        .register("math", "max", |arguments| {
+            let mut largest: Option<(f64, &Value)> = None;
+            for argument in arguments {
+                let n = number(argument, "math.max")?;
+                if largest.is_none_or(|(max, _)| n > max) {
+                    largest = Some((n, argument));
+                }
+            }
+            Ok(largest.map_or(Value::Null, |(_, value)| value.clone()))
+        });
+    natives
+}
+
+fn unary(arguments: &[Value], name: &str, operation: fn(f64) -> f64) -> Result<Value, RuntimeError> {
+    match arguments {
+        [argument] => Ok(Value::Number(operation(number(argument, name)?))),
+        _ => Err(RuntimeError::ArityMismatch { name: name.to_string(), expected: "x".to_string(), found: arguments.len() }),
+    }
+}
+
+fn number(value: &Value, name: &str) -> Result<f64, RuntimeError> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        Value::Integer(i) => Ok(*i as f64),
+        other => Err(RuntimeError::TypeError { message: format!("'{}' expects numbers, got {}.", name, other) }),
+    }
+}
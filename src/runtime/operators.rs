@@ -0,0 +1,560 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::runtime::bignum::{BigInt, Rational};
+use crate::runtime::channel::ChannelMethod;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::function::{compose, Function};
+use crate::runtime::value::{freeze, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Multiply,
+    Divide,
+    FloorDivide,
+    Subtract,
+    Add,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanEquals,
+    GreaterThan,
+    GreaterThanEquals,
+}
+
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::FloorDivide => "//",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Add => "+",
+            BinaryOp::Modulo => "%",
+            BinaryOp::Power => "**",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::ShiftLeft => "<<",
+            BinaryOp::ShiftRight => ">>",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::LessThan => "<",
+            BinaryOp::LessThanEquals => "<=",
+            BinaryOp::GreaterThan => ">",
+            BinaryOp::GreaterThanEquals => ">=",
+        }
+    }
+
+    fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Equal
+                | BinaryOp::NotEqual
+                | BinaryOp::LessThan
+                | BinaryOp::LessThanEquals
+                | BinaryOp::GreaterThan
+                | BinaryOp::GreaterThanEquals
+        )
+    }
+}
+
+impl FromStr for BinaryOp {
+    type Err = RuntimeError;
+
+    fn from_str(operator: &str) -> Result<Self, Self::Err> {
+        match operator {
+            "*" => Ok(BinaryOp::Multiply),
+            "/" => Ok(BinaryOp::Divide),
+            "//" => Ok(BinaryOp::FloorDivide),
+            "-" => Ok(BinaryOp::Subtract),
+            "+" => Ok(BinaryOp::Add),
+            "%" => Ok(BinaryOp::Modulo),
+            "**" => Ok(BinaryOp::Power),
+            "&" => Ok(BinaryOp::BitAnd),
+            "|" => Ok(BinaryOp::BitOr),
+            "^" => Ok(BinaryOp::BitXor),
+            "<<" => Ok(BinaryOp::ShiftLeft),
+            ">>" => Ok(BinaryOp::ShiftRight),
+            "==" => Ok(BinaryOp::Equal),
+            "!=" => Ok(BinaryOp::NotEqual),
+            "<" => Ok(BinaryOp::LessThan),
+            "<=" => Ok(BinaryOp::LessThanEquals),
+            ">" => Ok(BinaryOp::GreaterThan),
+            ">=" => Ok(BinaryOp::GreaterThanEquals),
+            _ => Err(RuntimeError::UnknownOperator { operator: operator.to_string() }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    BitNot,
+    /// `freeze value`: makes a list or object deeply immutable.
+    Freeze,
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOp::Negate => "-",
+            UnaryOp::BitNot => "~",
+            UnaryOp::Freeze => "freeze",
+        }
+    }
+}
+
+impl FromStr for UnaryOp {
+    type Err = RuntimeError;
+
+    fn from_str(operator: &str) -> Result<Self, Self::Err> {
+        match operator {
+            "-" => Ok(UnaryOp::Negate),
+            "~" => Ok(UnaryOp::BitNot),
+            "freeze" => Ok(UnaryOp::Freeze),
+            _ => Err(RuntimeError::UnknownOperator { operator: operator.to_string() }),
+        }
+    }
+}
+
+/// What happens when integer arithmetic leaves the `i64` range.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Overflow {
+    /// Report `RuntimeError::IntegerOverflow`.
+    #[default]
+    Error,
+    /// Continue with an arbitrary-precision integer.
+    Promote,
+}
+
+/// Applies `operator` to two already evaluated operands. Both backends go
+/// through here so arithmetic and comparison semantics cannot drift apart.
+///
+/// Operands are brought to the widest of their types, in the order integer,
+/// big integer, rational, float: exact values stay exact unless a float is
+/// involved. `/` on two integers (of any size) divides as floats.
+pub fn apply_binary(operator: BinaryOp, left_val: Value, right_val: Value, overflow: Overflow) -> Result<Value, RuntimeError> {
+    match operator {
+        BinaryOp::Power => return power(left_val, right_val, overflow),
+        BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor | BinaryOp::ShiftLeft | BinaryOp::ShiftRight => {
+            return bitwise(operator, left_val, right_val, overflow);
+        },
+        _ => {},
+    }
+
+    let is_comparison = operator.is_comparison();
+    let describe = if is_comparison { "comparison " } else { "" };
+
+    let left_rank = rank(&left_val).ok_or_else(|| RuntimeError::TypeError {
+        message: format!("Left operand of {}'{}' must be a number, got {}.", describe, operator.symbol(), left_val)
+    })?;
+    let right_rank = rank(&right_val).ok_or_else(|| RuntimeError::TypeError {
+        message: format!("Right operand of {}'{}' must be a number, got {}.", describe, operator.symbol(), right_val)
+    })?;
+
+    let mut rank = left_rank.max(right_rank);
+    if operator == BinaryOp::Divide && rank <= Rank::BigInt {
+        rank = Rank::Float;
+    }
+
+    if is_comparison {
+        let result = match rank {
+            Rank::Integer => compare(operator, &as_integer(&left_val), &as_integer(&right_val)),
+            Rank::BigInt => compare(operator, &as_big(&left_val), &as_big(&right_val)),
+            Rank::Rational => compare(operator, &as_rational(&left_val), &as_rational(&right_val)),
+            Rank::Float => compare(operator, &as_float(&left_val), &as_float(&right_val)),
+        };
+        return Ok(Value::Boolean(result));
+    }
+
+    match rank {
+        Rank::Integer => match integer_arithmetic(operator, as_integer(&left_val), as_integer(&right_val)) {
+            Err(RuntimeError::IntegerOverflow { .. }) if overflow == Overflow::Promote => {
+                big_arithmetic(operator, &as_big(&left_val), &as_big(&right_val))
+            },
+            result => result.map(Value::Integer),
+        },
+        Rank::BigInt => big_arithmetic(operator, &as_big(&left_val), &as_big(&right_val)),
+        Rank::Rational => rational_arithmetic(operator, &as_rational(&left_val), &as_rational(&right_val)),
+        Rank::Float => float_arithmetic(operator, as_float(&left_val), as_float(&right_val)).map(Value::Number),
+    }
+}
+
+/// Applies a prefix operator to an already evaluated operand.
+pub fn apply_unary(operator: UnaryOp, value: Value, overflow: Overflow) -> Result<Value, RuntimeError> {
+    match (operator, value) {
+        (UnaryOp::Negate, Value::Integer(i)) => match i.checked_neg() {
+            Some(negated) => Ok(Value::Integer(negated)),
+            None if overflow == Overflow::Promote => Ok(big_value(BigInt::from_i64(i).neg())),
+            None => Err(RuntimeError::IntegerOverflow { operator: operator.symbol().to_string() }),
+        },
+        (UnaryOp::Negate, Value::BigInt(b)) => Ok(big_value(b.neg())),
+        (UnaryOp::Negate, Value::Rational(r)) => Ok(Value::Rational(r.neg())),
+        (UnaryOp::Negate, Value::Number(n)) => Ok(Value::Number(-n)),
+        (UnaryOp::Negate, value) => Err(RuntimeError::TypeError {
+            message: format!("Operand of unary '-' must be a number, got {}.", value)
+        }),
+        (UnaryOp::BitNot, Value::Integer(i)) => Ok(Value::Integer(!i)),
+        (UnaryOp::BitNot, Value::BigInt(b)) => Ok(big_value(b.not())),
+        (UnaryOp::BitNot, value) => Err(RuntimeError::TypeError {
+            message: format!("Operand of '~' must be an integer, got {}.", value)
+        }),
+        // Other values are immutable already.
+        (UnaryOp::Freeze, value) => {
+            freeze(&value);
+            Ok(value)
+        },
+    }
+}
+
+/// `**` keeps exact bases exact when the exponent is an integer: integers
+/// raised to a non-negative power stay integers and rationals stay rationals.
+/// A negative power of an integer is a float, like `/` on integers.
+fn power(base: Value, exponent: Value, overflow: Overflow) -> Result<Value, RuntimeError> {
+    let overflowed = || RuntimeError::IntegerOverflow { operator: BinaryOp::Power.symbol().to_string() };
+
+    let base_rank = rank(&base).ok_or_else(|| RuntimeError::TypeError {
+        message: format!("Left operand of '**' must be a number, got {}.", base)
+    })?;
+    let exponent_rank = rank(&exponent).ok_or_else(|| RuntimeError::TypeError {
+        message: format!("Right operand of '**' must be a number, got {}.", exponent)
+    })?;
+
+    let integer_exponent = match exponent_rank {
+        Rank::Integer | Rank::BigInt => Some(as_big(&exponent)),
+        _ => None,
+    };
+    match (base_rank, integer_exponent) {
+        (Rank::Integer | Rank::BigInt, Some(exponent)) if !exponent.is_negative() => {
+            let exponent = exponent.to_i64().and_then(|e| u32::try_from(e).ok()).ok_or_else(overflowed)?;
+            if let Value::Integer(i) = base {
+                match i.checked_pow(exponent) {
+                    Some(result) => return Ok(Value::Integer(result)),
+                    None if overflow == Overflow::Error => return Err(overflowed()),
+                    None => {},
+                }
+            }
+            Ok(big_value(as_big(&base).pow(exponent)))
+        },
+        (Rank::Rational, Some(exponent)) => {
+            let exponent = exponent.to_i64().and_then(|e| i32::try_from(e).ok()).ok_or_else(overflowed)?;
+            as_rational(&base).pow(exponent).map(Value::Rational).ok_or(RuntimeError::DivisionByZero)
+        },
+        _ => {
+            let (base, exponent) = (as_float(&base), as_float(&exponent));
+            if base == 0.0 && exponent < 0.0 {
+                return Err(RuntimeError::DivisionByZero);
+            }
+            Ok(Value::Number(base.powf(exponent)))
+        },
+    }
+}
+
+/// Bitwise operators and shifts only accept integers. Negative integers
+/// behave as if they had infinitely many leading one bits, so `>>` rounds
+/// towards negative infinity.
+fn bitwise(operator: BinaryOp, left_val: Value, right_val: Value, overflow: Overflow) -> Result<Value, RuntimeError> {
+    let overflowed = || RuntimeError::IntegerOverflow { operator: operator.symbol().to_string() };
+
+    let is_shift = matches!(operator, BinaryOp::ShiftLeft | BinaryOp::ShiftRight);
+    if is_shift && (matches!(left_val, Value::Function(_)) || matches!(right_val, Value::Function(_))) {
+        return compose_functions(operator, left_val, right_val);
+    }
+
+    if !matches!(left_val, Value::Integer(_) | Value::BigInt(_)) {
+        return Err(RuntimeError::TypeError {
+            message: format!("Left operand of '{}' must be an integer, got {}.", operator.symbol(), left_val)
+        });
+    }
+    if !matches!(right_val, Value::Integer(_) | Value::BigInt(_)) {
+        return Err(RuntimeError::TypeError {
+            message: format!("Right operand of '{}' must be an integer, got {}.", operator.symbol(), right_val)
+        });
+    }
+
+    if is_shift && as_big(&right_val).is_negative() {
+        return Err(RuntimeError::TypeError {
+            message: format!("Shift count of '{}' must not be negative, got {}.", operator.symbol(), right_val)
+        });
+    }
+
+    if let (Value::Integer(left), Value::Integer(right)) = (&left_val, &right_val) {
+        let (left, right) = (*left, *right);
+        match operator {
+            BinaryOp::BitAnd => return Ok(Value::Integer(left & right)),
+            BinaryOp::BitOr => return Ok(Value::Integer(left | right)),
+            BinaryOp::BitXor => return Ok(Value::Integer(left ^ right)),
+            BinaryOp::ShiftRight => return Ok(Value::Integer(left >> right.min(63))),
+            _ => {
+                let shifted = if right < 64 { left << right } else { 0 };
+                if right < 64 && shifted >> right == left {
+                    return Ok(Value::Integer(shifted));
+                }
+                if overflow == Overflow::Error {
+                    return Err(overflowed());
+                }
+            },
+        }
+    }
+
+    let (left, right) = (as_big(&left_val), as_big(&right_val));
+    let result = match operator {
+        BinaryOp::BitAnd => left.bitand(&right),
+        BinaryOp::BitOr => left.bitor(&right),
+        BinaryOp::BitXor => left.bitxor(&right),
+        BinaryOp::ShiftLeft => {
+            let count = right.to_i64().and_then(|count| u32::try_from(count).ok()).ok_or_else(overflowed)?;
+            left.shl(count as usize)
+        },
+        _ => match right.to_i64().and_then(|count| u32::try_from(count).ok()) {
+            Some(count) => left.shr(count as usize),
+            None if left.is_negative() => BigInt::from_i64(-1),
+            None => BigInt::zero(),
+        },
+    };
+    Ok(big_value(result))
+}
+
+/// `f >> g` runs `f` then `g`; `f << g` runs `g` then `f`.
+fn compose_functions(operator: BinaryOp, left_val: Value, right_val: Value) -> Result<Value, RuntimeError> {
+    for (side, value) in [("Left", &left_val), ("Right", &right_val)] {
+        if !matches!(value, Value::Function(_)) {
+            return Err(RuntimeError::TypeError {
+                message: format!("{} operand of '{}' must be a function when composing, got {}.", side, operator.symbol(), value)
+            });
+        }
+    }
+
+    if operator == BinaryOp::ShiftRight {
+        Ok(compose(left_val, right_val))
+    } else {
+        Ok(compose(right_val, left_val))
+    }
+}
+
+/// Position of a numeric value in the promotion order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Rank {
+    Integer,
+    BigInt,
+    Rational,
+    Float,
+}
+
+fn rank(value: &Value) -> Option<Rank> {
+    match value {
+        Value::Integer(_) => Some(Rank::Integer),
+        Value::BigInt(_) => Some(Rank::BigInt),
+        Value::Rational(_) => Some(Rank::Rational),
+        Value::Number(_) => Some(Rank::Float),
+        _ => None,
+    }
+}
+
+fn as_integer(value: &Value) -> i64 {
+    match value {
+        Value::Integer(i) => *i,
+        _ => unreachable!("operand ranked as an integer"),
+    }
+}
+
+fn as_big(value: &Value) -> BigInt {
+    match value {
+        Value::Integer(i) => BigInt::from_i64(*i),
+        Value::BigInt(b) => b.clone(),
+        _ => unreachable!("operand ranked as a big integer"),
+    }
+}
+
+fn as_rational(value: &Value) -> Rational {
+    match value {
+        Value::Rational(r) => r.clone(),
+        _ => Rational::from_integer(as_big(value)),
+    }
+}
+
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Number(n) => *n,
+        Value::Integer(i) => *i as f64,
+        Value::BigInt(b) => b.to_f64(),
+        Value::Rational(r) => r.to_f64(),
+        _ => unreachable!("operand ranked as a number"),
+    }
+}
+
+/// Big integer results that fit in an `i64` go back to being plain integers.
+pub fn big_value(value: BigInt) -> Value {
+    match value.to_i64() {
+        Some(i) => Value::Integer(i),
+        None => Value::BigInt(value),
+    }
+}
+
+fn float_arithmetic(operator: BinaryOp, left_num: f64, right_num: f64) -> Result<f64, RuntimeError> {
+    let result = match operator {
+        BinaryOp::Multiply => left_num * right_num,
+        BinaryOp::Divide | BinaryOp::FloorDivide => {
+            if right_num == 0.0 {
+                return Err(RuntimeError::DivisionByZero);
+            }
+            let quotient = left_num / right_num;
+            if operator == BinaryOp::FloorDivide { quotient.floor() } else { quotient }
+        },
+        BinaryOp::Subtract => left_num - right_num,
+        BinaryOp::Add => left_num + right_num,
+        BinaryOp::Modulo => left_num % right_num,
+        _ => unreachable!(),
+    };
+    Ok(result)
+}
+
+/// `//` and `%` round towards negative infinity, so that
+/// `a == (a // b) * b + a % b` holds for every pair of integers.
+fn integer_arithmetic(operator: BinaryOp, left_int: i64, right_int: i64) -> Result<i64, RuntimeError> {
+    if matches!(operator, BinaryOp::FloorDivide | BinaryOp::Modulo) && right_int == 0 {
+        return Err(RuntimeError::DivisionByZero);
+    }
+
+    let result = match operator {
+        BinaryOp::Multiply => left_int.checked_mul(right_int),
+        BinaryOp::Subtract => left_int.checked_sub(right_int),
+        BinaryOp::Add => left_int.checked_add(right_int),
+        BinaryOp::FloorDivide => left_int.checked_div(right_int).map(|quotient| {
+            if left_int % right_int != 0 && (left_int < 0) != (right_int < 0) { quotient - 1 } else { quotient }
+        }),
+        BinaryOp::Modulo => left_int.checked_rem(right_int).map(|remainder| {
+            if remainder != 0 && (remainder < 0) != (right_int < 0) { remainder + right_int } else { remainder }
+        }),
+        _ => unreachable!(),
+    };
+    result.ok_or_else(|| RuntimeError::IntegerOverflow { operator: operator.symbol().to_string() })
+}
+
+fn big_arithmetic(operator: BinaryOp, left: &BigInt, right: &BigInt) -> Result<Value, RuntimeError> {
+    let result = match operator {
+        BinaryOp::Multiply => left.mul(right),
+        BinaryOp::Subtract => left.sub(right),
+        BinaryOp::Add => left.add(right),
+        BinaryOp::FloorDivide => left.div_rem_floor(right).ok_or(RuntimeError::DivisionByZero)?.0,
+        BinaryOp::Modulo => left.div_rem_floor(right).ok_or(RuntimeError::DivisionByZero)?.1,
+        _ => unreachable!(),
+    };
+    Ok(big_value(result))
+}
+
+fn rational_arithmetic(operator: BinaryOp, left: &Rational, right: &Rational) -> Result<Value, RuntimeError> {
+    let result = match operator {
+        BinaryOp::Multiply => left.mul(right),
+        BinaryOp::Divide => left.div(right).ok_or(RuntimeError::DivisionByZero)?,
+        BinaryOp::Subtract => left.sub(right),
+        BinaryOp::Add => left.add(right),
+        BinaryOp::FloorDivide => {
+            return Ok(big_value(left.div(right).ok_or(RuntimeError::DivisionByZero)?.floor()));
+        },
+        BinaryOp::Modulo => {
+            let quotient = left.div(right).ok_or(RuntimeError::DivisionByZero)?.floor();
+            left.sub(&right.mul(&Rational::from_integer(quotient)))
+        },
+        _ => unreachable!(),
+    };
+    Ok(Value::Rational(result))
+}
+
+fn compare<T: PartialOrd>(operator: BinaryOp, left: &T, right: &T) -> bool {
+    match operator {
+        BinaryOp::Equal => left == right,
+        BinaryOp::NotEqual => left != right,
+        BinaryOp::LessThan => left < right,
+        BinaryOp::LessThanEquals => left <= right,
+        BinaryOp::GreaterThan => left > right,
+        BinaryOp::GreaterThanEquals => left >= right,
+        _ => unreachable!(),
+    }
+}
+
+/// Reads `field` from `object`. `optional` is the `?.` form, which yields
+/// `null` for a `null` object instead of failing.
+pub fn get_field(object: Value, field: &str, optional: bool) -> Result<Value, RuntimeError> {
+    let no_field = |object: &Value| RuntimeError::TypeError {
+        message: format!("{} has no field '{}'.", object, field)
+    };
+    match object {
+        Value::Null if optional => Ok(Value::Null),
+        Value::Null => Err(RuntimeError::TypeError {
+            message: format!("Cannot read field '{}' of null; use '?.' to allow null.", field)
+        }),
+        Value::List(list) if field == "length" => Ok(Value::Integer(list.len() as i64)),
+        Value::Object(ref fields) => fields.get(field).ok_or_else(|| no_field(&object)),
+        Value::Generator(generator) if field == "next" => Ok(Value::Function(Arc::new(Function::Next(generator)))),
+        Value::Channel(ref channel) => match ChannelMethod::of(field) {
+            Some(method) => Ok(Value::Function(Arc::new(Function::Channel(channel.clone(), method)))),
+            None => Err(no_field(&object)),
+        },
+        object => Err(no_field(&object)),
+    }
+}
+
+/// `object[index]`. Lists are indexed from 0 with integers.
+pub fn get_index(object: Value, index: Value) -> Result<Value, RuntimeError> {
+    let Value::List(list) = object else {
+        return Err(RuntimeError::TypeError { message: format!("{} cannot be indexed.", object) });
+    };
+    let Value::Integer(index) = index else {
+        return Err(RuntimeError::TypeError { message: format!("List index must be an integer, got {}.", index) });
+    };
+    usize::try_from(index).ok()
+        .and_then(|position| list.get(position))
+        .ok_or(RuntimeError::IndexOutOfRange { index, length: list.len() })
+}
+
+/// `object.field = value`.
+pub fn set_field(object: Value, field: &str, value: Value) -> Result<(), RuntimeError> {
+    match object {
+        Value::Object(fields) => fields.set(field, value),
+        Value::Null => Err(RuntimeError::TypeError { message: format!("Cannot set field '{}' of null.", field) }),
+        object => Err(RuntimeError::TypeError { message: format!("Cannot set field '{}' of {}.", field, object) }),
+    }
+}
+
+/// `object[index] = value`.
+pub fn set_index(object: Value, index: Value, value: Value) -> Result<(), RuntimeError> {
+    let Value::List(list) = object else {
+        return Err(RuntimeError::TypeError { message: format!("{} cannot be indexed.", object) });
+    };
+    let Value::Integer(index) = index else {
+        return Err(RuntimeError::TypeError { message: format!("List index must be an integer, got {}.", index) });
+    };
+    list.set(index, value)
+}
+
+/// Items of the value after `...` in a list literal or a call.
+pub fn spread_items(value: Value) -> Result<Vec<Value>, RuntimeError> {
+    match value {
+        Value::List(list) => Ok(list.to_vec()),
+        other => Err(RuntimeError::TypeError { message: format!("Cannot spread {}; only lists can be spread.", other) }),
+    }
+}
+
+/// Truthiness used by `if` and `while`. `construct` names the statement in
+/// the error message ("If", "While").
+pub fn is_truthy(value: &Value, construct: &str) -> Result<bool, RuntimeError> {
+    match value {
+        Value::Boolean(b) => Ok(*b),
+        Value::Number(n) => Ok(*n != 0.0),
+        Value::Integer(i) => Ok(*i != 0),
+        Value::BigInt(b) => Ok(!b.is_zero()),
+        Value::Rational(r) => Ok(!r.is_zero()),
+        Value::Null => Ok(false),
+        _ => Err(RuntimeError::TypeError {
+            message: format!("{} condition must evaluate to a boolean or number, got {}.", construct, value)
+        }),
+    }
+}
@@ -0,0 +1,132 @@
+use crate::frontend::ast::{Program, Stmt, StmtType};
+use crate::runtime::operators::{apply_binary, apply_unary, is_truthy, BinaryOp, Overflow, UnaryOp};
+use crate::runtime::value::{literal_value, Value};
+
+/// Folds constant arithmetic and comparisons and drops branches whose
+/// condition is a literal. Anything that would fail at run time (division by
+/// zero, overflow, type errors) is left untouched so the error still happens there.
+///
+/// Runs after the resolver: blocks are never removed, only replaced by other
+/// blocks, so the resolved slot depths stay valid.
+pub fn optimize(program: &mut Program) {
+    for stmt in program.body.iter_mut() {
+        optimize_stmt(stmt);
+    }
+}
+
+fn optimize_stmt(stmt: &mut Stmt) {
+    for child in [&mut stmt.left, &mut stmt.right, &mut stmt.consequent].into_iter().flatten() {
+        optimize_stmt(child);
+    }
+    for child in stmt.body.iter_mut().flatten() {
+        optimize_stmt(child);
+    }
+
+    let replacement = match stmt.kind {
+        StmtType::BinaryExpr => fold_binary_expr(stmt),
+        StmtType::UnaryExpr => fold_unary_expr(stmt),
+        StmtType::CoalesceExpr => fold_coalesce_expr(stmt),
+        StmtType::IfStmt => eliminate_if(stmt),
+        StmtType::WhileStmt => eliminate_while(stmt),
+        _ => None,
+    };
+
+    if let Some(replacement) = replacement {
+        *stmt = replacement;
+    }
+}
+
+fn fold_binary_expr(stmt: &Stmt) -> Option<Stmt> {
+    let left_val = literal(stmt.left.as_deref()?)?;
+    let right_val = literal(stmt.right.as_deref()?)?;
+    let operator = stmt.operator.as_ref()?.parse::<BinaryOp>().ok()?;
+
+    let result = apply_binary(operator, left_val, right_val, Overflow::Error).ok()?;
+    literal_stmt(result, stmt.line)
+}
+
+fn fold_unary_expr(stmt: &Stmt) -> Option<Stmt> {
+    let operand = literal(stmt.right.as_deref()?)?;
+    let operator = stmt.operator.as_ref()?.parse::<UnaryOp>().ok()?;
+
+    let result = apply_unary(operator, operand, Overflow::Error).ok()?;
+    literal_stmt(result, stmt.line)
+}
+
+fn fold_coalesce_expr(stmt: &Stmt) -> Option<Stmt> {
+    match literal(stmt.left.as_deref()?)? {
+        Value::Null => stmt.right.as_deref().cloned(),
+        _ => stmt.left.as_deref().cloned(),
+    }
+}
+
+fn eliminate_if(stmt: &Stmt) -> Option<Stmt> {
+    let condition = literal(stmt.left.as_deref()?)?;
+
+    if is_truthy(&condition, "If").ok()? {
+        stmt.consequent.as_deref().cloned()
+    } else {
+        Some(stmt.right.as_deref().cloned().unwrap_or_else(|| empty_block(stmt.line)))
+    }
+}
+
+fn eliminate_while(stmt: &Stmt) -> Option<Stmt> {
+    let condition = literal(stmt.left.as_deref()?)?;
+
+    if is_truthy(&condition, "While").ok()? {
+        None
+    } else {
+        Some(empty_block(stmt.line))
+    }
+}
+
+fn literal(stmt: &Stmt) -> Option<Value> {
+    match stmt.kind {
+        StmtType::NumericLiteral
+        | StmtType::IntegerLiteral
+        | StmtType::RationalLiteral
+        | StmtType::BooleanLiteral
+        | StmtType::NullLiteral => literal_value(stmt).ok(),
+        _ => None,
+    }
+}
+
+fn literal_stmt(value: Value, line: usize) -> Option<Stmt> {
+    let (kind, text) = match &value {
+        Value::Number(n) => (StmtType::NumericLiteral, n.to_string()),
+        Value::Integer(i) => (StmtType::IntegerLiteral, i.to_string()),
+        Value::BigInt(b) => (StmtType::IntegerLiteral, b.to_string()),
+        Value::Rational(r) => (StmtType::RationalLiteral, r.to_string()),
+        Value::Boolean(b) => (StmtType::BooleanLiteral, b.to_string()),
+        Value::Null => (StmtType::NullLiteral, "null".to_string()),
+        _ => return None,
+    };
+
+    Some(Stmt {
+        kind,
+        value: Some(text),
+        left: None,
+        right: None,
+        operator: None,
+        consequent: None,
+        body: None,
+        line,
+        slot: None,
+    })
+}
+
+/// An empty block evaluates to `null`, which is what a skipped `if` or
+/// `while` evaluates to.
+fn empty_block(line: usize) -> Stmt {
+    Stmt {
+        kind: StmtType::BlockStmt,
+        body: Some(vec![]),
+        value: None,
+        left: None,
+        right: None,
+        operator: None,
+        consequent: None,
+        line,
+        slot: None,
+    }
+}
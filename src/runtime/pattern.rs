@@ -0,0 +1,126 @@
+use std::fmt;
+use crate::frontend::ast::{Stmt, StmtType};
+use crate::runtime::error::RuntimeError;
+use crate::runtime::function::Binding;
+use crate::runtime::value::{List, Object, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatternKind {
+    /// `[a, b, ...rest]`: items by position.
+    List,
+    /// `{ x, y }`: fields by name.
+    Object,
+}
+
+/// The left side of a destructuring `def` or assignment, or a parameter
+/// taken apart. Each element may have a default, and the last one may be a
+/// rest name collecting whatever the others did not take.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub elements: Vec<Element>,
+}
+
+/// One part of a pattern: a name, or a pattern taking the part apart in turn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    /// The field it takes from an object, or the name a list item is bound
+    /// to; empty for a nested pattern in a list.
+    pub name: String,
+    /// Has a default, used when the part is missing.
+    pub optional: bool,
+    /// `...name`: collects the items or fields the others did not take.
+    pub rest: bool,
+    pub pattern: Option<Pattern>,
+}
+
+impl Pattern {
+    /// The pattern of a `ListPattern` or `ObjectPattern` node.
+    pub fn of(stmt: &Stmt) -> Option<Pattern> {
+        let kind = match stmt.kind {
+            StmtType::ListPattern => PatternKind::List,
+            StmtType::ObjectPattern => PatternKind::Object,
+            _ => return None,
+        };
+        let elements = stmt.body.iter().flatten()
+            .map(|element| Element {
+                name: element.value.clone().unwrap_or_default(),
+                optional: element.right.is_some(),
+                rest: element.operator.as_deref() == Some("..."),
+                pattern: Pattern::of(element),
+            })
+            .collect();
+        Some(Pattern { kind, elements })
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let elements: Vec<String> = self.elements.iter().map(|element| element.to_string()).collect();
+        match self.kind {
+            PatternKind::List => write!(f, "[{}]", elements.join(", ")),
+            PatternKind::Object => write!(f, "{{{}}}", elements.join(", ")),
+        }
+    }
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.pattern, self.name.is_empty()) {
+            (Some(pattern), true) => write!(f, "{}", pattern)?,
+            (Some(pattern), false) => write!(f, "{}: {}", self.name, pattern)?,
+            (None, _) if self.rest => write!(f, "...{}", self.name)?,
+            (None, _) => write!(f, "{}", self.name)?,
+        }
+        if self.optional {
+            write!(f, "?")?;
+        }
+        Ok(())
+    }
+}
+
+/// Takes `value` apart into one value per name of `pattern`, in order. Names
+/// with a default that found nothing are flagged in `missing`, for the
+/// backend to evaluate their default.
+pub fn destructure(pattern: &Pattern, value: &Value) -> Result<Binding, RuntimeError> {
+    let mismatch = |reason: String| RuntimeError::PatternMismatch { pattern: pattern.to_string(), reason };
+
+    let has_rest = pattern.elements.last().is_some_and(|element| element.rest);
+    let fixed = &pattern.elements[..pattern.elements.len() - has_rest as usize];
+    let (mut found, rest): (Vec<Option<Value>>, Value) = match (pattern.kind, value) {
+        (PatternKind::List, Value::List(list)) => {
+            let mut items = list.to_vec();
+            if items.len() > fixed.len() && !has_rest {
+                return Err(mismatch(format!("expected at most {} item(s), got {}", fixed.len(), items.len())));
+            }
+            let rest = items.split_off(fixed.len().min(items.len()));
+            let mut found: Vec<Option<Value>> = items.into_iter().map(Some).collect();
+            found.resize(fixed.len(), None);
+            (found, Value::List(List::new(rest)))
+        },
+        (PatternKind::Object, Value::Object(object)) => {
+            let found = fixed.iter().map(|element| object.get(&element.name)).collect();
+            let rest = object.to_vec().into_iter()
+                .filter(|(field, _)| !fixed.iter().any(|element| &element.name == field))
+                .collect();
+            (found, Value::Object(Object::new(rest)))
+        },
+        (PatternKind::List, other) => return Err(mismatch(format!("expected a list, got {}", other))),
+        (PatternKind::Object, other) => return Err(mismatch(format!("expected an object, got {}", other))),
+    };
+
+    if let Some(element) = fixed.iter().zip(&found).find(|(element, value)| value.is_none() && !element.optional).map(|(element, _)| element) {
+        let reason = match pattern.kind {
+            PatternKind::List => format!("no item for '{}'", element),
+            PatternKind::Object => format!("missing field '{}'", element.name),
+        };
+        return Err(mismatch(reason));
+    }
+
+    let missing = found.iter().map(Option::is_none).collect();
+    if has_rest {
+        found.push(Some(rest));
+    }
+    let slots = found.into_iter().map(|value| value.unwrap_or(Value::Null)).collect();
+    Ok(Binding { slots, missing })
+}
@@ -0,0 +1,503 @@
+//! Binary `.rbc` cache format for compiled chunks.
+//!
+//! Layout (little endian): the `RBC\0` magic, a `u16` format version, the
+//! FNV-1a hash of the source the chunk was compiled from and the top-level
+//! chunk. A chunk is its constants pool, its function prototypes (name,
+//! kind, parameters with their flags and their own nested chunk), its
+//! destructuring patterns and finally the instructions, each followed by its
+//! source line.
+
+use std::fmt;
+use std::sync::Arc;
+use crate::frontend::ast::Slot;
+use crate::runtime::bytecode::{Branch, Chunk, Instruction};
+use crate::runtime::function::{FunctionBody, FunctionKind, Param, Prototype};
+use crate::runtime::operators::{BinaryOp, UnaryOp};
+use crate::runtime::pattern::{Element, Pattern, PatternKind};
+use crate::runtime::value::Value;
+
+const MAGIC: &[u8; 4] = b"RBC\0";
+pub const FORMAT_VERSION: u16 = 16;
+
+#[derive(Debug)]
+pub enum RbcError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    StaleSource,
+    Truncated,
+    InvalidTag { what: &'static str, tag: u8 },
+    InvalidOperator(String),
+}
+
+impl fmt::Display for RbcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RbcError::BadMagic => write!(f, "not an .rbc file"),
+            RbcError::UnsupportedVersion(version) => write!(f, "unsupported .rbc version {} (expected {})", version, FORMAT_VERSION),
+            RbcError::StaleSource => write!(f, "cached bytecode was compiled from a different source"),
+            RbcError::Truncated => write!(f, "unexpected end of .rbc file"),
+            RbcError::InvalidTag { what, tag } => write!(f, "invalid {} tag {}", what, tag),
+            RbcError::InvalidOperator(symbol) => write!(f, "invalid operator '{}'", symbol),
+        }
+    }
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` its output is stable across Rust
+/// releases, which matters for a hash that is persisted to disk.
+pub fn source_hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub fn encode(chunk: &Chunk, source_hash: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&source_hash.to_le_bytes());
+    write_chunk(&mut bytes, chunk);
+    bytes
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, chunk: &Chunk) {
+    write_u32(bytes, chunk.constants.len());
+    for constant in &chunk.constants {
+        write_value(bytes, constant);
+    }
+
+    write_u32(bytes, chunk.functions.len());
+    for prototype in &chunk.functions {
+        write_prototype(bytes, prototype);
+    }
+
+    write_u32(bytes, chunk.patterns.len());
+    for pattern in &chunk.patterns {
+        write_pattern(bytes, pattern);
+    }
+
+    write_u32(bytes, chunk.code.len());
+    for (instruction, line) in chunk.code.iter().zip(&chunk.lines) {
+        write_instruction(bytes, instruction);
+        write_u32(bytes, *line);
+    }
+}
+
+fn write_prototype(bytes: &mut Vec<u8>, prototype: &Prototype) {
+    match &prototype.name {
+        Some(name) => {
+            bytes.push(1);
+            write_str(bytes, name);
+        },
+        None => bytes.push(0),
+    }
+    bytes.push(match prototype.kind {
+        FunctionKind::Plain => 0,
+        FunctionKind::Generator => 1,
+        FunctionKind::Async => 2,
+    });
+    write_params(bytes, &prototype.params);
+    match &prototype.body {
+        FunctionBody::Bytecode(chunk) => write_chunk(bytes, chunk),
+        FunctionBody::Tree(_) => unreachable!("compiled chunks only contain bytecode functions"),
+    }
+}
+
+fn write_pattern(bytes: &mut Vec<u8>, pattern: &Pattern) {
+    bytes.push(match pattern.kind {
+        PatternKind::List => 0,
+        PatternKind::Object => 1,
+    });
+    write_u32(bytes, pattern.elements.len());
+    for element in &pattern.elements {
+        write_str(bytes, &element.name);
+        bytes.push(element.optional as u8 | (element.rest as u8) << 1 | (element.pattern.is_some() as u8) << 2);
+        if let Some(nested) = &element.pattern {
+            write_pattern(bytes, nested);
+        }
+    }
+}
+
+fn write_params(bytes: &mut Vec<u8>, params: &[Param]) {
+    write_u32(bytes, params.len());
+    for param in params {
+        write_str(bytes, &param.name);
+        bytes.push(param.optional as u8 | (param.rest as u8) << 1);
+    }
+}
+
+/// Decodes a chunk, rejecting it unless it was compiled from a source whose
+/// hash is `expected_hash`.
+pub fn decode(bytes: &[u8], expected_hash: u64) -> Result<Chunk, RbcError> {
+    let mut reader = Reader { bytes, position: 0 };
+
+    if reader.take(4)? != MAGIC {
+        return Err(RbcError::BadMagic);
+    }
+    let version = u16::from_le_bytes(reader.array()?);
+    if version != FORMAT_VERSION {
+        return Err(RbcError::UnsupportedVersion(version));
+    }
+    if u64::from_le_bytes(reader.array()?) != expected_hash {
+        return Err(RbcError::StaleSource);
+    }
+
+    reader.chunk()
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: usize) {
+    bytes.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn write_str(bytes: &mut Vec<u8>, value: &str) {
+    write_u32(bytes, value.len());
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+fn write_slot(bytes: &mut Vec<u8>, slot: &Slot) {
+    write_u32(bytes, slot.depth);
+    write_u32(bytes, slot.index);
+}
+
+fn write_value(bytes: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Number(n) => {
+            bytes.push(0);
+            bytes.extend_from_slice(&n.to_le_bytes());
+        },
+        Value::Boolean(b) => {
+            bytes.push(1);
+            bytes.push(*b as u8);
+        },
+        Value::Null => bytes.push(2),
+        Value::String(s) => {
+            bytes.push(3);
+            write_str(bytes, s);
+        },
+        Value::Identifier(id) => {
+            bytes.push(4);
+            write_str(bytes, id);
+        },
+        Value::Integer(i) => {
+            bytes.push(5);
+            bytes.extend_from_slice(&i.to_le_bytes());
+        },
+        Value::BigInt(b) => {
+            bytes.push(6);
+            write_str(bytes, &b.to_string());
+        },
+        Value::Rational(r) => {
+            bytes.push(7);
+            write_str(bytes, &r.to_string());
+        },
+        Value::Function(_) | Value::List(_) | Value::Object(_) | Value::Generator(_) | Value::Promise(_) | Value::Channel(_) => {
+            unreachable!("functions, collections, generators, promises and channels are created at run time, never stored as constants")
+        },
+    }
+}
+
+fn write_instruction(bytes: &mut Vec<u8>, instruction: &Instruction) {
+    match instruction {
+        Instruction::Constant(index) => {
+            bytes.push(0);
+            write_u32(bytes, *index);
+        },
+        Instruction::Null => bytes.push(1),
+        Instruction::Pop => bytes.push(2),
+        Instruction::Binary(operator) => {
+            bytes.push(3);
+            write_str(bytes, operator.symbol());
+        },
+        Instruction::Jump(target) => {
+            bytes.push(4);
+            write_u32(bytes, *target);
+        },
+        Instruction::JumpIfFalse(target, branch) => {
+            bytes.push(5);
+            write_u32(bytes, *target);
+            bytes.push(match branch {
+                Branch::If => 0,
+                Branch::While => 1,
+            });
+        },
+        Instruction::DefineGlobal(index) => {
+            bytes.push(6);
+            write_u32(bytes, *index);
+        },
+        Instruction::Return => bytes.push(7),
+        Instruction::GetGlobal(index) => {
+            bytes.push(8);
+            write_u32(bytes, *index);
+        },
+        Instruction::SetGlobal(index) => {
+            bytes.push(9);
+            write_u32(bytes, *index);
+        },
+        Instruction::DefineLocal(index) => {
+            bytes.push(10);
+            write_u32(bytes, *index);
+        },
+        Instruction::GetLocal(slot) => {
+            bytes.push(11);
+            write_slot(bytes, slot);
+        },
+        Instruction::SetLocal(slot) => {
+            bytes.push(12);
+            write_slot(bytes, slot);
+        },
+        Instruction::PushScope => bytes.push(13),
+        Instruction::PopScope => bytes.push(14),
+        Instruction::Unary(operator) => {
+            bytes.push(15);
+            write_str(bytes, operator.symbol());
+        },
+        Instruction::JumpIfNotNull(target) => {
+            bytes.push(16);
+            write_u32(bytes, *target);
+        },
+        Instruction::GetField(index, optional) => {
+            bytes.push(17);
+            write_u32(bytes, *index);
+            bytes.push(*optional as u8);
+        },
+        Instruction::Closure(index) => {
+            bytes.push(18);
+            write_u32(bytes, *index);
+        },
+        Instruction::Call(argc) => {
+            bytes.push(19);
+            write_u32(bytes, *argc);
+        },
+        Instruction::BuildList(count) => {
+            bytes.push(20);
+            write_u32(bytes, *count);
+        },
+        Instruction::AppendList => bytes.push(21),
+        Instruction::ExtendList => bytes.push(22),
+        Instruction::CallWith(named) => {
+            bytes.push(23);
+            write_u32(bytes, *named);
+        },
+        Instruction::Index => bytes.push(24),
+        Instruction::BuildObject(count) => {
+            bytes.push(26);
+            write_u32(bytes, *count);
+        },
+        Instruction::Destructure(index) => {
+            bytes.push(27);
+            write_u32(bytes, *index);
+        },
+        Instruction::DefineConstant(index) => {
+            bytes.push(28);
+            write_u32(bytes, *index);
+        },
+        Instruction::SetField(index) => {
+            bytes.push(29);
+            write_u32(bytes, *index);
+        },
+        Instruction::SetIndex => bytes.push(30),
+        Instruction::Yield => bytes.push(31),
+        Instruction::Iterate => bytes.push(32),
+        Instruction::ForIter(target) => {
+            bytes.push(33);
+            write_u32(bytes, *target);
+        },
+        Instruction::Await => bytes.push(34),
+        Instruction::Work => bytes.push(35),
+        Instruction::Interop(index) => {
+            bytes.push(36);
+            write_u32(bytes, *index);
+        },
+        Instruction::SkipDefault(index, target) => {
+            bytes.push(25);
+            write_u32(bytes, *index);
+            write_u32(bytes, *target);
+        },
+        Instruction::Dup(count) => {
+            bytes.push(37);
+            write_u32(bytes, *count);
+        },
+        Instruction::Bury(depth) => {
+            bytes.push(38);
+            write_u32(bytes, *depth);
+        },
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], RbcError> {
+        let end = self.position.checked_add(count).ok_or(RbcError::Truncated)?;
+        let slice = self.bytes.get(self.position..end).ok_or(RbcError::Truncated)?;
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], RbcError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, RbcError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, RbcError> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, RbcError> {
+        let len = self.u32()?;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn slot(&mut self) -> Result<Slot, RbcError> {
+        Ok(Slot { depth: self.u32()?, index: self.u32()? })
+    }
+
+    fn chunk(&mut self) -> Result<Chunk, RbcError> {
+        let mut chunk = Chunk::default();
+        for _ in 0..self.u32()? {
+            chunk.constants.push(self.value()?);
+        }
+        for _ in 0..self.u32()? {
+            chunk.functions.push(Arc::new(self.prototype()?));
+        }
+        for _ in 0..self.u32()? {
+            chunk.patterns.push(self.pattern()?);
+        }
+        for _ in 0..self.u32()? {
+            let instruction = self.instruction()?;
+            let line = self.u32()?;
+            chunk.emit(instruction, line);
+        }
+        Ok(chunk)
+    }
+
+    fn prototype(&mut self) -> Result<Prototype, RbcError> {
+        let name = match self.u8()? {
+            0 => None,
+            1 => Some(self.string()?),
+            tag => return Err(RbcError::InvalidTag { what: "function name", tag }),
+        };
+        let kind = match self.u8()? {
+            0 => FunctionKind::Plain,
+            1 => FunctionKind::Generator,
+            2 => FunctionKind::Async,
+            tag => return Err(RbcError::InvalidTag { what: "function kind", tag }),
+        };
+        let params = self.params()?;
+        Ok(Prototype { name, kind, params, body: FunctionBody::Bytecode(self.chunk()?) })
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, RbcError> {
+        let kind = match self.u8()? {
+            0 => PatternKind::List,
+            1 => PatternKind::Object,
+            tag => return Err(RbcError::InvalidTag { what: "pattern", tag }),
+        };
+        let mut elements = vec![];
+        for _ in 0..self.u32()? {
+            let name = self.string()?;
+            let flags = self.u8()?;
+            let pattern = if flags & 4 != 0 { Some(self.pattern()?) } else { None };
+            elements.push(Element { name, optional: flags & 1 != 0, rest: flags & 2 != 0, pattern });
+        }
+        Ok(Pattern { kind, elements })
+    }
+
+    fn params(&mut self) -> Result<Vec<Param>, RbcError> {
+        let mut params = vec![];
+        for _ in 0..self.u32()? {
+            let name = self.string()?;
+            let flags = self.u8()?;
+            params.push(Param { name, optional: flags & 1 != 0, rest: flags & 2 != 0 });
+        }
+        Ok(params)
+    }
+
+    fn value(&mut self) -> Result<Value, RbcError> {
+        match self.u8()? {
+            0 => Ok(Value::Number(f64::from_le_bytes(self.array()?))),
+            1 => Ok(Value::Boolean(self.u8()? != 0)),
+            2 => Ok(Value::Null),
+            3 => Ok(Value::String(self.string()?)),
+            4 => Ok(Value::Identifier(self.string()?)),
+            5 => Ok(Value::Integer(i64::from_le_bytes(self.array()?))),
+            6 => self.string()?.parse().map(Value::BigInt).map_err(|_| RbcError::InvalidTag { what: "big integer", tag: 6 }),
+            7 => self.string()?.parse().map(Value::Rational).map_err(|_| RbcError::InvalidTag { what: "rational", tag: 7 }),
+            tag => Err(RbcError::InvalidTag { what: "constant", tag }),
+        }
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, RbcError> {
+        match self.u8()? {
+            0 => Ok(Instruction::Constant(self.u32()?)),
+            1 => Ok(Instruction::Null),
+            2 => Ok(Instruction::Pop),
+            3 => {
+                let symbol = self.string()?;
+                let operator = symbol.parse::<BinaryOp>().map_err(|_| RbcError::InvalidOperator(symbol))?;
+                Ok(Instruction::Binary(operator))
+            },
+            4 => Ok(Instruction::Jump(self.u32()?)),
+            5 => {
+                let target = self.u32()?;
+                let branch = match self.u8()? {
+                    0 => Branch::If,
+                    1 => Branch::While,
+                    tag => return Err(RbcError::InvalidTag { what: "branch", tag }),
+                };
+                Ok(Instruction::JumpIfFalse(target, branch))
+            },
+            6 => Ok(Instruction::DefineGlobal(self.u32()?)),
+            7 => Ok(Instruction::Return),
+            8 => Ok(Instruction::GetGlobal(self.u32()?)),
+            9 => Ok(Instruction::SetGlobal(self.u32()?)),
+            10 => Ok(Instruction::DefineLocal(self.u32()?)),
+            11 => Ok(Instruction::GetLocal(self.slot()?)),
+            12 => Ok(Instruction::SetLocal(self.slot()?)),
+            13 => Ok(Instruction::PushScope),
+            14 => Ok(Instruction::PopScope),
+            15 => {
+                let symbol = self.string()?;
+                let operator = symbol.parse::<UnaryOp>().map_err(|_| RbcError::InvalidOperator(symbol))?;
+                Ok(Instruction::Unary(operator))
+            },
+            16 => Ok(Instruction::JumpIfNotNull(self.u32()?)),
+            17 => {
+                let index = self.u32()?;
+                Ok(Instruction::GetField(index, self.u8()? != 0))
+            },
+            18 => Ok(Instruction::Closure(self.u32()?)),
+            19 => Ok(Instruction::Call(self.u32()?)),
+            20 => Ok(Instruction::BuildList(self.u32()?)),
+            21 => Ok(Instruction::AppendList),
+            22 => Ok(Instruction::ExtendList),
+            23 => Ok(Instruction::CallWith(self.u32()?)),
+            24 => Ok(Instruction::Index),
+            26 => Ok(Instruction::BuildObject(self.u32()?)),
+            27 => Ok(Instruction::Destructure(self.u32()?)),
+            28 => Ok(Instruction::DefineConstant(self.u32()?)),
+            29 => Ok(Instruction::SetField(self.u32()?)),
+            30 => Ok(Instruction::SetIndex),
+            31 => Ok(Instruction::Yield),
+            32 => Ok(Instruction::Iterate),
+            33 => Ok(Instruction::ForIter(self.u32()?)),
+            34 => Ok(Instruction::Await),
+            35 => Ok(Instruction::Work),
+            36 => Ok(Instruction::Interop(self.u32()?)),
+            37 => Ok(Instruction::Dup(self.u32()?)),
+            38 => Ok(Instruction::Bury(self.u32()?)),
+            25 => {
+                let index = self.u32()?;
+                Ok(Instruction::SkipDefault(index, self.u32()?))
+            },
+            tag => Err(RbcError::InvalidTag { what: "instruction", tag }),
+        }
+    }
+}
@@ -1,13 +1,23 @@
-use std::{io::{stdin, stdout, Write}, process::exit};
-use crate::runtime::interpreter::{interpret};
+use std::{io::{stdin, stdout, Write}, process::exit, sync::OnceLock};
+use crate::runtime::interpreter::Interpreter;
+use crate::runtime::limits::Interrupt;
+use crate::runtime::runner::Options;
 
 pub struct Repl {}
 
 impl Repl {
-    pub fn run() {
-        println!("Repl v1.0");
-
+    pub fn run(options: &Options) {
         let mut history: Vec<String> = vec![];
+        let mut interpreter = Interpreter::new();
+        interpreter.set_overflow(options.overflow());
+        interpreter.set_clock(options.clock());
+        interpreter.set_limits(options.limits);
+        interpreter.register_natives(&options.natives);
+        // Before the banner, so that once it is out Ctrl-C is safe to press.
+        let interrupt = interpreter.interrupt_handle();
+        interrupt_on_ctrl_c(interrupt.clone());
+
+        println!("Repl v1.0");
         let stdin = stdin();
         let mut stdout = stdout();
 
@@ -16,7 +26,11 @@ impl Repl {
             let mut source = String::new();
 
             let _ = stdout.flush();
-            stdin.read_line(&mut source).expect("Invalid code");
+            // End of input, e.g. Ctrl-D, ends the session like `exit`.
+            if stdin.read_line(&mut source).expect("Invalid code") == 0 {
+                println!();
+                exit(0)
+            }
 
             if source == "exit\n" {
                 exit(0)
@@ -24,10 +38,45 @@ impl Repl {
     
             history.push(source.clone());
 
-            match interpret(source.as_str()) { 
+            // Ctrl-C only stops the line it is pressed during, not the next
+            // one after a press at the prompt.
+            interrupt.clear();
+            match interpreter.interpret(source.as_str()) { 
                 Ok(result) => println!("{}", result),
                 Err(error) => eprintln!("{}", error)
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// The handle Ctrl-C interrupts. The signal handler can only reach it
+/// through a static.
+static CTRL_C: OnceLock<Interrupt> = OnceLock::new();
+
+/// Makes Ctrl-C stop the evaluation in progress instead of the process.
+#[cfg(unix)]
+fn interrupt_on_ctrl_c(interrupt: Interrupt) {
+    use std::ffi::c_int;
+
+    const SIGINT: c_int = 2;
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+    extern "C" fn on_sigint(_: c_int) {
+        if let Some(interrupt) = CTRL_C.get() {
+            interrupt.interrupt();
+        }
+    }
+
+    if CTRL_C.set(interrupt).is_ok() {
+        // SAFETY: the handler only stores to an atomic flag.
+        unsafe {
+            signal(SIGINT, on_sigint);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn interrupt_on_ctrl_c(interrupt: Interrupt) {
+    let _ = CTRL_C.set(interrupt);
+}
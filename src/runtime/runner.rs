@@ -1,19 +1,157 @@
 use std::fs;
-use crate::runtime::interpreter::interpret;
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::frontend::{ast::Program, lexer::tokenize, parser::produce_ast, resolver::Resolver};
+use crate::runtime::bytecode::Chunk;
+use crate::runtime::clock::{Clock, MonotonicClock, VirtualClock};
+use crate::runtime::compiler::compile;
+use crate::runtime::disassembler::disassemble;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::interpreter::Interpreter;
+use crate::runtime::limits::Limits;
+use crate::runtime::native::Natives;
+use crate::runtime::operators::Overflow;
+use crate::runtime::optimizer::optimize;
+use crate::runtime::rbc::{self, source_hash};
+use crate::runtime::vm::Vm;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+    #[default]
+    Tree,
+    Vm,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "tree" => Ok(Backend::Tree),
+            "vm" => Ok(Backend::Vm),
+            _ => Err(format!("Unknown backend '{}' (expected vm or tree)", name)),
+        }
+    }
+}
+
+/// Command line switches that affect how a file is executed.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub backend: Backend,
+    /// Run the constant folding / dead branch pass before executing.
+    pub optimize: bool,
+    /// Print the (possibly optimized) AST before executing.
+    pub dump_ast: bool,
+    /// Promote integers that overflow to big integers instead of failing.
+    pub big_integers: bool,
+    /// Run timers on a virtual clock that skips ahead instead of waiting.
+    pub virtual_clock: bool,
+    /// Native functions scripts can import with `interop`.
+    pub natives: Natives,
+    /// Fuel, call depth, size of values and time a program may use.
+    pub limits: Limits,
+}
+
+impl Options {
+    pub fn overflow(&self) -> Overflow {
+        if self.big_integers { Overflow::Promote } else { Overflow::Error }
+    }
+
+    pub fn clock(&self) -> Box<dyn Clock> {
+        if self.virtual_clock { Box::new(VirtualClock::default()) } else { Box::new(MonotonicClock::default()) }
+    }
+}
 
 pub struct Runner {
     
 }
 
 impl Runner {
-    pub fn run(file: &str) {
+    pub fn run(file: &str, options: &Options) {
         let content = fs::read_to_string(file);
         match content {
             Ok(source) => {
-                let result = interpret(source.as_str());
+                let result = match options.backend {
+                    Backend::Tree => Self::parse(source.as_str(), options).and_then(|program| {
+                        let mut interpreter = Interpreter::new();
+                        interpreter.set_overflow(options.overflow());
+                        interpreter.set_clock(options.clock());
+                        interpreter.set_limits(options.limits);
+                        interpreter.register_natives(&options.natives);
+                        interpreter.evaluate(&program)
+                    }),
+                    Backend::Vm => Self::load_chunk(file, source.as_str(), options).and_then(|chunk| {
+                        let mut vm = Vm::new(&chunk);
+                        vm.set_overflow(options.overflow());
+                        vm.set_clock(options.clock());
+                        vm.set_limits(options.limits);
+                        vm.register_natives(&options.natives);
+                        vm.run()
+                    }),
+                };
                 println!("{:?}", result)
             },
             Err(err) => println!("An error ocurred on read file {}", err)
         }
     }
-}
\ No newline at end of file
+
+    pub fn disassemble(file: &str, options: &Options) {
+        let content = fs::read_to_string(file);
+        match content {
+            Ok(source) => match Self::load_chunk(file, source.as_str(), options) {
+                Ok(chunk) => print!("{}", disassemble(&chunk, source.as_str())),
+                Err(error) => eprintln!("{}", error),
+            },
+            Err(err) => println!("An error ocurred on read file {}", err)
+        }
+    }
+
+    fn parse(source: &str, options: &Options) -> Result<Program, RuntimeError> {
+        let mut tokens = tokenize(source)?;
+
+        let mut program = produce_ast(&mut tokens)?;
+        Resolver::new().resolve(&mut program)?;
+
+        if options.optimize {
+            optimize(&mut program);
+        }
+        if options.dump_ast {
+            print!("{}", program);
+        }
+        Ok(program)
+    }
+
+    /// Loads the chunk cached next to `file` when it was compiled from this
+    /// exact source, otherwise compiles `source` and refreshes the cache. A
+    /// cache that cannot be written is not an error.
+    fn load_chunk(file: &str, source: &str, options: &Options) -> Result<Chunk, RuntimeError> {
+        let cache_path = Self::cache_path(file, options.optimize);
+        let hash = Self::cache_hash(source, options.optimize);
+
+        if !options.dump_ast {
+            if let Ok(bytes) = fs::read(&cache_path) {
+                if let Ok(chunk) = rbc::decode(&bytes, hash) {
+                    return Ok(chunk);
+                }
+            }
+        }
+
+        let chunk = compile(&Self::parse(source, options)?)?;
+        let _ = fs::write(&cache_path, rbc::encode(&chunk, hash));
+        Ok(chunk)
+    }
+
+    /// Where the chunk compiled from `file` is cached: `file` with `.rbc`
+    /// added to its name, or `.opt.rbc` for the optimizer's. Adding rather
+    /// than replacing the extension keeps the cache from ever being the
+    /// source itself, and each mode keeps its own entry.
+    pub fn cache_path(file: &str, optimize: bool) -> PathBuf {
+        PathBuf::from(format!("{}{}", file, if optimize { ".opt.rbc" } else { ".rbc" }))
+    }
+
+    /// The hash the cached chunk of `source` is stored under. Chunks compiled
+    /// with and without the optimizer must not pass for one another.
+    pub fn cache_hash(source: &str, optimize: bool) -> u64 {
+        source_hash(&format!("optimize={}\0{}", optimize, source))
+    }
+}
@@ -1,23 +1,535 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::LocalKey;
+use crate::frontend::ast::{Stmt, StmtType};
+use crate::runtime::bignum::{BigInt, Rational};
+use crate::runtime::channel::Channel;
+use crate::runtime::environment::{copy_scope_into, new_scope, ScopeRef};
+use crate::runtime::error::RuntimeError;
+use crate::runtime::event_loop::Promise;
+use crate::runtime::function::Function;
+use crate::runtime::generator::Generator;
+use crate::runtime::limits::Heap;
+use crate::runtime::operators::big_value;
 
 #[allow(dead_code)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum Value {
     Number(f64),
+    Integer(i64),
+    BigInt(BigInt),
+    Rational(Rational),
     Boolean(bool),
     Null,
     String(String),
     Identifier(String),
+    Function(Arc<Function>),
+    List(List),
+    Object(Object),
+    Generator(Generator),
+    Promise(Promise),
+    Channel(Channel),
+}
+
+/// Contents of a list or object, shared by all copies of the value. Once
+/// frozen they can no longer be modified from the language.
+#[derive(Default)]
+struct Shared<T> {
+    contents: Mutex<T>,
+    frozen: AtomicBool,
+    /// Bytes of the strings and big numbers among the contents.
+    held: AtomicUsize,
+    charge: Charge,
+}
+
+impl<T> Shared<T> {
+    fn new(contents: T, held: usize) -> Arc<Self> {
+        Arc::new(Shared {
+            contents: Mutex::new(contents),
+            frozen: AtomicBool::new(false),
+            held: AtomicUsize::new(held),
+            charge: Charge::default(),
+        })
+    }
+
+    fn hold(&self, added: &Value, removed: Option<&Value>) {
+        self.held.fetch_add(held_bytes(added), Ordering::Relaxed);
+        self.held.fetch_sub(removed.map_or(0, held_bytes), Ordering::Relaxed);
+    }
+
+    /// Counts `bytes` against `heap` in place of what it counted before,
+    /// returning what `heap` holds then.
+    fn charge(&self, heap: &Heap, bytes: usize) -> usize {
+        let mut charge = self.charge.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some((previous, previous_bytes)) = charge.take() {
+            previous.release(previous_bytes);
+        }
+        let total = heap.charge(bytes);
+        *charge = Some((heap.clone(), bytes));
+        total
+    }
+
+    fn lock(&self) -> MutexGuard<'_, T> {
+        self.contents.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns `false` if it was already frozen.
+    fn freeze(&self) -> bool {
+        !self.frozen.swap(true, Ordering::SeqCst)
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::SeqCst)
+    }
+
+    fn key(self: &Arc<Self>) -> usize {
+        Arc::as_ptr(self) as *const () as usize
+    }
+
+    /// The contents, if this is the last copy of the value.
+    fn take_last(self: &mut Arc<Self>) -> Option<T> where T: Default {
+        Arc::get_mut(self).map(|shared| {
+            std::mem::take(shared.contents.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner()))
+        })
+    }
+}
+
+/// What a list or object counts against the heap of a run, given back once
+/// its last copy is gone.
+#[derive(Default)]
+struct Charge(Mutex<Option<(Heap, usize)>>);
+
+impl Drop for Charge {
+    fn drop(&mut self) {
+        if let Some((heap, bytes)) = self.0.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner()).take() {
+            heap.release(bytes);
+        }
+    }
+}
+
+/// Bytes a string or big number held by a list or object adds to it.
+fn held_bytes(value: &Value) -> usize {
+    match value {
+        Value::List(_) | Value::Object(_) => 0,
+        value => value.size_in_bytes(),
+    }
+}
+
+thread_local! {
+    /// Lists and objects being printed on this thread.
+    static PRINTING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+    /// Pairs of lists or of objects being compared on this thread.
+    static COMPARING: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Marks a list or object, or a pair of them, as entered on this thread
+/// until dropped, so that printing or comparing a cycle stops once it gets
+/// back to where it started instead of recursing forever.
+struct Visit<K: PartialEq + Copy + 'static> {
+    key: K,
+    visiting: &'static LocalKey<RefCell<Vec<K>>>,
+}
+
+impl<K: PartialEq + Copy + 'static> Visit<K> {
+    /// `None` if `key` was already entered.
+    fn enter(visiting: &'static LocalKey<RefCell<Vec<K>>>, key: K) -> Option<Self> {
+        visiting.with(|keys| {
+            let mut keys = keys.borrow_mut();
+            if keys.contains(&key) {
+                return None;
+            }
+            keys.push(key);
+            Some(Visit { key, visiting })
+        })
+    }
+}
+
+impl<K: PartialEq + Copy + 'static> Drop for Visit<K> {
+    fn drop(&mut self) {
+        self.visiting.with(|keys| {
+            let mut keys = keys.borrow_mut();
+            if let Some(position) = keys.iter().rposition(|key| *key == self.key) {
+                keys.remove(position);
+            }
+        });
+    }
+}
+
+/// Drops the lists and objects inside `items` one at a time rather than
+/// recursively, so that deeply nested ones do not overflow the stack.
+fn drop_iteratively(items: Vec<Value>) {
+    let mut pending = items;
+    while let Some(value) = pending.pop() {
+        match value {
+            Value::List(mut list) => pending.extend(list.0.take_last().unwrap_or_default()),
+            Value::Object(mut object) => {
+                pending.extend(object.0.take_last().unwrap_or_default().into_iter().map(|(_, field)| field));
+            },
+            _ => {},
+        }
+    }
+}
+
+/// A list value. Copies of a list share its elements.
+#[derive(Clone, Default)]
+pub struct List(Arc<Shared<Vec<Value>>>);
+
+impl List {
+    pub fn new(items: Vec<Value>) -> Self {
+        let held = items.iter().map(held_bytes).sum();
+        List(Shared::new(items, held))
+    }
+
+    pub fn to_vec(&self) -> Vec<Value> {
+        self.0.lock().clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().is_empty()
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.len() * std::mem::size_of::<Value>()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Value> {
+        self.0.lock().get(index).cloned()
+    }
+
+    /// Appends to a list under construction, e.g. by a list literal.
+    pub fn push(&self, value: Value) {
+        self.0.hold(&value, None);
+        self.0.lock().push(value);
+    }
+
+    /// `list[index] = value`.
+    pub fn set(&self, index: i64, value: Value) -> Result<(), RuntimeError> {
+        if self.is_frozen() {
+            return Err(RuntimeError::FrozenValue { value: Value::List(self.clone()).to_string() });
+        }
+        let mut items = self.0.lock();
+        let length = items.len();
+        let item = usize::try_from(index).ok()
+            .and_then(|position| items.get_mut(position))
+            .ok_or(RuntimeError::IndexOutOfRange { index, length })?;
+        self.0.hold(&value, Some(item));
+        *item = value;
+        Ok(())
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.0.is_frozen()
+    }
+}
+
+impl Drop for List {
+    fn drop(&mut self) {
+        if let Some(items) = self.0.take_last() {
+            drop_iteratively(items);
+        }
+    }
+}
+
+/// Lists compare by their elements. Comparing a pair again while it is
+/// being compared, through a cycle, takes them as equal so far.
+impl PartialEq for List {
+    fn eq(&self, other: &Self) -> bool {
+        if Arc::ptr_eq(&self.0, &other.0) {
+            return true;
+        }
+        match Visit::enter(&COMPARING, (self.0.key(), other.0.key())) {
+            Some(_visit) => self.to_vec() == other.to_vec(),
+            None => true,
+        }
+    }
+}
+
+impl fmt::Debug for List {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match Visit::enter(&PRINTING, self.0.key()) {
+            Some(_visit) => write!(f, "{:?}", self.to_vec()),
+            None => write!(f, "[...]"),
+        }
+    }
+}
+
+/// An object value: named fields in the order they were written. Copies of
+/// an object share its fields.
+#[derive(Clone, Default)]
+pub struct Object(Arc<Shared<Vec<(String, Value)>>>);
+
+impl Object {
+    /// An object of `fields`. A name given twice keeps its first place and
+    /// its last value, as in `{ a: 1, a: 2 }`.
+    pub fn new(fields: Vec<(String, Value)>) -> Self {
+        let mut unique: Vec<(String, Value)> = Vec::with_capacity(fields.len());
+        for (name, value) in fields {
+            match unique.iter_mut().find(|(field, _)| *field == name) {
+                Some((_, field_value)) => *field_value = value,
+                None => unique.push((name, value)),
+            }
+        }
+        let held = unique.iter().map(|(_, value)| held_bytes(value)).sum();
+        Object(Shared::new(unique, held))
+    }
+
+    pub fn to_vec(&self) -> Vec<(String, Value)> {
+        self.0.lock().clone()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.0.lock().iter().find(|(field, _)| field == name).map(|(_, value)| value.clone())
+    }
+
+    /// `object.name = value`, adding the field if the object does not have it.
+    pub fn set(&self, name: &str, value: Value) -> Result<(), RuntimeError> {
+        if self.is_frozen() {
+            return Err(RuntimeError::FrozenValue { value: Value::Object(self.clone()).to_string() });
+        }
+        let mut fields = self.0.lock();
+        match fields.iter_mut().find(|(field, _)| field == name) {
+            Some((_, field_value)) => {
+                self.0.hold(&value, Some(field_value));
+                *field_value = value;
+            },
+            None => {
+                self.0.hold(&value, None);
+                fields.push((name.to_string(), value));
+            },
+        }
+        Ok(())
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.0.lock().iter().map(|(name, _)| name.len() + std::mem::size_of::<(String, Value)>()).sum()
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.0.is_frozen()
+    }
+}
+
+impl Value {
+    /// Bytes the value keeps on the heap for itself: the text of a string,
+    /// the digits of a big number, the slots of a list or the fields of an
+    /// object, not what those hold.
+    pub fn size_in_bytes(&self) -> usize {
+        match self {
+            Value::String(s) | Value::Identifier(s) => s.len(),
+            Value::BigInt(b) => b.size_in_bytes(),
+            Value::Rational(r) => r.size_in_bytes(),
+            Value::List(list) => list.size_in_bytes(),
+            Value::Object(object) => object.size_in_bytes(),
+            _ => 0,
+        }
+    }
+
+    /// Counts a list or object against `heap` for what it keeps there now,
+    /// the strings and big numbers in it included, in place of what it
+    /// counted before; returns the bytes `heap` holds then. Other values
+    /// are not counted.
+    pub(crate) fn charge(&self, heap: &Heap) -> usize {
+        match self {
+            Value::List(list) => list.0.charge(heap, list.size_in_bytes() + list.0.held.load(Ordering::Relaxed)),
+            Value::Object(object) => object.0.charge(heap, object.size_in_bytes() + object.0.held.load(Ordering::Relaxed)),
+            _ => heap.bytes(),
+        }
+    }
+}
+
+/// Freezes `value` and every list and object reachable from it.
+pub fn freeze(value: &Value) {
+    match value {
+        Value::List(list) if list.0.freeze() => list.to_vec().iter().for_each(freeze),
+        Value::Object(object) if object.0.freeze() => object.to_vec().iter().for_each(|(_, field)| freeze(field)),
+        _ => {},
+    }
+}
+
+/// Copies values for another thread, so that the copies share nothing with
+/// the originals while keeping the sharing among themselves: two references
+/// to one list give two references to one copy, and cycles stay cycles.
+/// Generators and promises belong to the thread that made them and are
+/// copied as `null`; channels are how threads talk, so they stay shared.
+#[derive(Default)]
+pub struct DeepCopy {
+    lists: HashMap<usize, List>,
+    objects: HashMap<usize, Object>,
+    scopes: HashMap<usize, ScopeRef>,
+}
+
+impl DeepCopy {
+    pub fn value(&mut self, value: &Value) -> Value {
+        match value {
+            Value::List(list) => {
+                let key = Arc::as_ptr(&list.0) as usize;
+                if let Some(copy) = self.lists.get(&key) {
+                    return Value::List(copy.clone());
+                }
+                let copy = List::new(vec![]);
+                self.lists.insert(key, copy.clone());
+                for item in list.to_vec() {
+                    let item = self.value(&item);
+                    copy.push(item);
+                }
+                if list.is_frozen() {
+                    copy.0.freeze();
+                }
+                Value::List(copy)
+            },
+            Value::Object(object) => {
+                let key = Arc::as_ptr(&object.0) as usize;
+                if let Some(copy) = self.objects.get(&key) {
+                    return Value::Object(copy.clone());
+                }
+                let copy = Object::new(vec![]);
+                self.objects.insert(key, copy.clone());
+                for (name, field) in object.to_vec() {
+                    let field = self.value(&field);
+                    copy.0.lock().push((name, field));
+                }
+                if object.is_frozen() {
+                    copy.0.freeze();
+                }
+                Value::Object(copy)
+            },
+            Value::Function(function) => match &**function {
+                Function::Closure { prototype, scope } => {
+                    let scope = scope.as_ref().map(|scope| self.scope(scope));
+                    Value::Function(Arc::new(Function::Closure { prototype: prototype.clone(), scope }))
+                },
+                Function::Composed { first, then } => {
+                    let (first, then) = (self.value(first), self.value(then));
+                    Value::Function(Arc::new(Function::Composed { first, then }))
+                },
+                Function::Next(_) => Value::Null,
+                Function::Builtin(_) | Function::Channel(..) | Function::Native(_) => value.clone(),
+            },
+            Value::Generator(_) | Value::Promise(_) => Value::Null,
+            other => other.clone(),
+        }
+    }
+
+    /// A copy of a closure's scope and the scopes around it.
+    pub fn scope(&mut self, scope: &ScopeRef) -> ScopeRef {
+        let key = Arc::as_ptr(scope) as usize;
+        if let Some(copy) = self.scopes.get(&key) {
+            return copy.clone();
+        }
+        let copy = new_scope();
+        self.scopes.insert(key, copy.clone());
+        copy_scope_into(scope, &copy, self);
+        copy
+    }
+}
+
+impl Drop for Object {
+    fn drop(&mut self) {
+        if let Some(fields) = self.0.take_last() {
+            drop_iteratively(fields.into_iter().map(|(_, field)| field).collect());
+        }
+    }
+}
+
+/// Objects compare by their fields, in order, and like lists through cycles.
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        if Arc::ptr_eq(&self.0, &other.0) {
+            return true;
+        }
+        match Visit::enter(&COMPARING, (self.0.key(), other.0.key())) {
+            Some(_visit) => self.to_vec() == other.to_vec(),
+            None => true,
+        }
+    }
+}
+
+impl fmt::Debug for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match Visit::enter(&PRINTING, self.0.key()) {
+            Some(_visit) => write!(f, "{:?}", self.to_vec()),
+            None => write!(f, "{{...}}"),
+        }
+    }
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Value::Number(n) => write!(f, "{}", n),
+            Value::Number(n) => write_number(f, *n),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::BigInt(b) => write!(f, "{}", b),
+            Value::Rational(r) => write!(f, "{}", r.to_literal()),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Null => write!(f, "null"),
             Value::String(s) => write!(f, "\"{}\"", s),
             Value::Identifier(id) => write!(f, "{}", id),
+            Value::Function(function) => write!(f, "{}", function),
+            Value::Generator(generator) => write!(f, "{}", generator),
+            Value::Promise(promise) => write!(f, "{}", promise),
+            Value::Channel(channel) => write!(f, "{}", channel),
+            // A list or object met again inside itself prints as `[...]`
+            // or `{...}`.
+            Value::List(list) => {
+                let Some(_visit) = Visit::enter(&PRINTING, list.0.key()) else {
+                    return write!(f, "[...]");
+                };
+                let items: Vec<String> = list.to_vec().iter().map(|item| item.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            },
+            Value::Object(object) => {
+                let Some(_visit) = Visit::enter(&PRINTING, object.0.key()) else {
+                    return write!(f, "{{...}}");
+                };
+                let fields: Vec<String> = object.to_vec().iter().map(|(name, value)| format!("{}: {}", name, value)).collect();
+                write!(f, "{{{}}}", fields.join(", "))
+            },
         }
     }
-}
\ No newline at end of file
+}
+
+/// Writes a float so that reading the output back as a literal gives the
+/// same float: integral values keep a `.0` and very large or very small
+/// magnitudes use scientific notation.
+fn write_number(f: &mut fmt::Formatter, n: f64) -> fmt::Result {
+    let magnitude = n.abs();
+    if !n.is_finite() {
+        write!(f, "{}", n)
+    } else if magnitude >= 1e16 || (magnitude != 0.0 && magnitude < 1e-4) {
+        write!(f, "{:e}", n)
+    } else if n.fract() == 0.0 {
+        write!(f, "{:.1}", n)
+    } else {
+        write!(f, "{}", n)
+    }
+}
+
+/// Value of a literal node. Integer literals too large for an `i64` become
+/// big integers.
+pub fn literal_value(stmt: &Stmt) -> Result<Value, RuntimeError> {
+    let text = stmt.value.as_ref().ok_or_else(|| RuntimeError::TypeError {
+        message: format!("{:?} is missing a value.", stmt.kind)
+    })?;
+
+    match stmt.kind {
+        StmtType::NumericLiteral => text.parse::<f64>().map(Value::Number).map_err(|e| RuntimeError::TypeError {
+            message: format!("Failed to parse number '{}': {}", text, e),
+        }),
+        StmtType::IntegerLiteral => text.parse::<BigInt>().map(big_value).map_err(|e| RuntimeError::TypeError {
+            message: format!("Failed to parse integer '{}': {}", text, e),
+        }),
+        StmtType::RationalLiteral => text.parse::<Rational>().map(Value::Rational).map_err(|e| RuntimeError::TypeError {
+            message: format!("Failed to parse rational '{}': {}", text, e),
+        }),
+        StmtType::BooleanLiteral => Ok(Value::Boolean(text == "true")),
+        StmtType::NullLiteral => Ok(Value::Null),
+        _ => Err(RuntimeError::TypeError { message: format!("{:?} is not a literal.", stmt.kind) }),
+    }
+}
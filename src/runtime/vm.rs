@@ -0,0 +1,452 @@
+use std::sync::Arc;
+use crate::runtime::builtins::{call_builtin, define_builtins};
+use crate::runtime::channel::call_channel_method;
+use crate::runtime::bytecode::{Chunk, Instruction};
+use crate::runtime::clock::Clock;
+use crate::runtime::environment::Environment;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::event_loop::{Driver, EventLoop};
+use crate::runtime::function::{as_function, bind_arguments, Arguments, Function, FunctionBody, FunctionKind};
+use crate::runtime::generator::{iterate, sent_value, Body, Frame, Generator, Step};
+use crate::runtime::limits::{Budget, Interrupt, Limits};
+use crate::runtime::native::Natives;
+use crate::runtime::operators::{apply_binary, apply_unary, get_field, get_index, is_truthy, set_field, set_index, spread_items, Overflow};
+use crate::runtime::pattern::destructure;
+use crate::runtime::value::{List, Object, Value};
+use crate::runtime::worker::spawn_worker;
+
+pub struct Vm<'a> {
+    chunk: &'a Chunk,
+    stack: Vec<Value>,
+    environment: Environment,
+    overflow: Overflow,
+    event_loop: EventLoop,
+    /// Whether the frame being executed is the task of an async call,
+    /// whose `AWAIT` suspends it instead of running the event loop.
+    in_task: bool,
+    limits: Limits,
+    interrupt: Interrupt,
+    /// What the program used of `limits`, shared with its workers.
+    budget: Budget,
+    /// Calls of script functions in progress on this vm.
+    depth: usize,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(chunk: &'a Chunk) -> Self {
+        let mut environment = Environment::new();
+        define_builtins(&mut environment);
+        Vm {
+            chunk,
+            stack: Vec::new(),
+            environment,
+            overflow: Overflow::default(),
+            event_loop: EventLoop::default(),
+            in_task: false,
+            limits: Limits::default(),
+            interrupt: Interrupt::default(),
+            budget: Budget::default(),
+            depth: 0,
+        }
+    }
+
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+    }
+
+    /// Limits the program runs with.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// The handle that stops the program at its next instruction.
+    pub fn interrupt_handle(&self) -> Interrupt {
+        self.interrupt.clone()
+    }
+
+    /// Makes the functions of `natives` available to `interop`.
+    pub fn register_natives(&mut self, natives: &Natives) {
+        self.environment.define_natives(natives);
+    }
+
+    /// The clock timers run on; real time unless set.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.event_loop = EventLoop::new(clock);
+    }
+
+    /// Runs the program, then the tasks its async calls left on the event
+    /// loop.
+    pub fn run(&mut self) -> Result<Value, RuntimeError> {
+        self.budget = Budget::new(self.limits, &self.interrupt);
+        let result = self.execute(self.chunk, &mut 0, &[]).and_then(|step| {
+            let event_loop = self.event_loop.clone();
+            event_loop.finish(self)?;
+            Ok(step.value())
+        });
+        self.budget.clone().end(result)
+    }
+
+    /// Runs `chunk` from `ip` until its `RETURN`, or a `YIELD` in a generator,
+    /// leaving `ip` after it. Function calls re-enter here with the
+    /// function's own chunk, sharing the value stack; `missing` tells which
+    /// parameters got no argument and need their default value.
+    fn execute(&mut self, chunk: &Chunk, ip: &mut usize, missing: &[bool]) -> Result<Step, RuntimeError> {
+        loop {
+            let instruction = chunk.code.get(*ip).ok_or_else(|| RuntimeError::TypeError {
+                message: "Instruction pointer ran past the end of the chunk.".to_string()
+            })?;
+            *ip += 1;
+            self.budget.step()?;
+
+            match instruction {
+                Instruction::Constant(index) => {
+                    let value = constant(chunk, *index)?;
+                    self.stack.push(value);
+                },
+                Instruction::Null => self.stack.push(Value::Null),
+                Instruction::Pop => {
+                    self.pop()?;
+                },
+                Instruction::Dup(count) => {
+                    let start = self.stack.len().checked_sub(*count).ok_or_else(|| RuntimeError::TypeError {
+                        message: "VM stack underflow.".to_string()
+                    })?;
+                    self.stack.extend_from_within(start..);
+                },
+                Instruction::Bury(depth) => {
+                    let value = self.pop()?;
+                    let at = self.stack.len().checked_sub(*depth).ok_or_else(|| RuntimeError::TypeError {
+                        message: "VM stack underflow.".to_string()
+                    })?;
+                    self.stack.insert(at, value);
+                },
+                Instruction::Binary(operator) => {
+                    let right_val = self.pop()?;
+                    let left_val = self.pop()?;
+                    let result = apply_binary(*operator, left_val, right_val, self.overflow)?;
+                    self.stack.push(self.budget.check_size(result)?);
+                },
+                Instruction::Unary(operator) => {
+                    let operand = self.pop()?;
+                    let result = apply_unary(*operator, operand, self.overflow)?;
+                    self.stack.push(self.budget.check_size(result)?);
+                },
+                Instruction::Jump(target) => *ip = *target,
+                Instruction::JumpIfFalse(target, branch) => {
+                    let condition_val = self.pop()?;
+                    if !is_truthy(&condition_val, &branch.to_string())? {
+                        *ip = *target;
+                    }
+                },
+                Instruction::JumpIfNotNull(target) => {
+                    if self.peek()? != Value::Null {
+                        *ip = *target;
+                    } else {
+                        self.pop()?;
+                    }
+                },
+                Instruction::GetField(index, optional) => {
+                    let field = constant(chunk, *index)?;
+                    let object = self.pop()?;
+                    self.stack.push(get_field(object, &field.to_string(), *optional)?);
+                },
+                Instruction::DefineGlobal(index) => {
+                    let name = constant(chunk, *index)?.to_string();
+                    let value = self.peek()?;
+                    self.environment.define_global(&name, value);
+                },
+                Instruction::DefineConstant(index) => {
+                    let name = constant(chunk, *index)?.to_string();
+                    let value = self.peek()?;
+                    self.environment.define_constant(&name, value);
+                },
+                Instruction::GetGlobal(index) => {
+                    let name = constant(chunk, *index)?.to_string();
+                    let value = self.environment.get_global(&name)?;
+                    self.stack.push(value);
+                },
+                Instruction::SetGlobal(index) => {
+                    let name = constant(chunk, *index)?.to_string();
+                    let value = self.peek()?;
+                    self.environment.set_global(&name, value)?;
+                },
+                Instruction::DefineLocal(index) => {
+                    let value = self.peek()?;
+                    self.environment.define(*index, value)?;
+                },
+                Instruction::GetLocal(slot) => {
+                    let value = self.environment.get(*slot)?;
+                    self.stack.push(value);
+                },
+                Instruction::SetLocal(slot) => {
+                    let value = self.peek()?;
+                    self.environment.set(*slot, value)?;
+                },
+                Instruction::PushScope => self.environment.push_scope(),
+                Instruction::PopScope => self.environment.pop_scope(),
+                Instruction::Closure(index) => {
+                    let prototype = chunk.functions.get(*index).cloned().ok_or_else(|| RuntimeError::TypeError {
+                        message: format!("Function index {} is out of range.", index)
+                    })?;
+                    let scope = self.environment.current_scope();
+                    self.stack.push(Value::Function(Arc::new(Function::Closure { prototype, scope })));
+                },
+                Instruction::Call(argc) => {
+                    let first_argument = self.stack.len().checked_sub(*argc).ok_or_else(|| RuntimeError::TypeError {
+                        message: "VM stack underflow.".to_string()
+                    })?;
+                    let arguments = self.stack.split_off(first_argument);
+                    let callee = self.pop()?;
+                    let result = self.call(callee, Arguments::positional(arguments))?;
+                    self.stack.push(result);
+                },
+                Instruction::CallWith(named_count) => {
+                    let mut named = Vec::with_capacity(*named_count);
+                    for _ in 0..*named_count {
+                        let value = self.pop()?;
+                        let name = self.pop()?.to_string();
+                        named.push((name, value));
+                    }
+                    named.reverse();
+                    let positional = spread_items(self.pop()?)?;
+                    let callee = self.pop()?;
+                    let result = self.call(callee, Arguments { positional, named })?;
+                    self.stack.push(result);
+                },
+                Instruction::BuildList(count) => {
+                    let first_item = self.stack.len().checked_sub(*count).ok_or_else(|| RuntimeError::TypeError {
+                        message: "VM stack underflow.".to_string()
+                    })?;
+                    let items = self.stack.split_off(first_item);
+                    self.stack.push(self.budget.check_size(Value::List(List::new(items)))?);
+                },
+                Instruction::AppendList | Instruction::ExtendList => {
+                    let value = self.pop()?;
+                    let Value::List(list) = self.peek()? else {
+                        return Err(RuntimeError::TypeError { message: "Expected a list on the VM stack.".to_string() });
+                    };
+                    match instruction {
+                        Instruction::AppendList => list.push(value),
+                        _ => spread_items(value)?.into_iter().for_each(|item| list.push(item)),
+                    }
+                    self.budget.check_size(Value::List(list))?;
+                },
+                Instruction::BuildObject(count) => {
+                    let mut fields = Vec::with_capacity(*count);
+                    for _ in 0..*count {
+                        let value = self.pop()?;
+                        let name = self.pop()?.to_string();
+                        fields.push((name, value));
+                    }
+                    fields.reverse();
+                    self.stack.push(self.budget.check_size(Value::Object(Object::new(fields)))?);
+                },
+                Instruction::Destructure(index) => {
+                    let pattern = chunk.patterns.get(*index).ok_or_else(|| RuntimeError::TypeError {
+                        message: format!("Pattern index {} is out of range.", index)
+                    })?;
+                    let binding = destructure(pattern, &self.peek()?)?;
+                    for (position, part) in binding.slots.into_iter().enumerate().rev() {
+                        self.stack.push(part);
+                        if pattern.elements[position].optional {
+                            self.stack.push(Value::Boolean(!binding.missing[position]));
+                        }
+                    }
+                },
+                Instruction::SetField(index) => {
+                    let field = constant(chunk, *index)?;
+                    let value = self.pop()?;
+                    let object = self.pop()?;
+                    set_field(object.clone(), &field.to_string(), value.clone())?;
+                    self.budget.check_size(object)?;
+                    self.stack.push(value);
+                },
+                Instruction::SetIndex => {
+                    let value = self.pop()?;
+                    let index = self.pop()?;
+                    let object = self.pop()?;
+                    set_index(object.clone(), index, value.clone())?;
+                    self.budget.check_size(object)?;
+                    self.stack.push(value);
+                },
+                Instruction::Index => {
+                    let index = self.pop()?;
+                    let object = self.pop()?;
+                    self.stack.push(get_index(object, index)?);
+                },
+                Instruction::SkipDefault(index, target) => {
+                    if !missing.get(*index).copied().unwrap_or(false) {
+                        *ip = *target;
+                    }
+                },
+                Instruction::Yield => return Ok(Step::Yield(self.pop()?)),
+                Instruction::Iterate => {
+                    let iterable = self.pop()?;
+                    self.stack.push(Value::Generator(iterate(iterable)?));
+                },
+                Instruction::ForIter(target) => {
+                    let Value::Generator(generator) = self.peek()? else {
+                        return Err(RuntimeError::TypeError { message: "Expected a generator on the VM stack.".to_string() });
+                    };
+                    match self.resume(&generator, Value::Null)? {
+                        Step::Yield(item) => self.stack.push(item),
+                        Step::Return(_) => {
+                            self.pop()?;
+                            *ip = *target;
+                        },
+                    }
+                },
+                Instruction::Await => {
+                    let awaited = self.pop()?;
+                    if self.in_task {
+                        return Ok(Step::Yield(awaited));
+                    }
+                    let event_loop = self.event_loop.clone();
+                    let value = event_loop.wait(awaited, self)?;
+                    self.stack.push(value);
+                },
+                Instruction::Work => {
+                    let callee = self.pop()?;
+                    let (overflow, limits, budget, depth) = (self.overflow, self.limits, self.budget.clone(), self.depth);
+                    let worker = spawn_worker(&callee, &self.environment, &self.event_loop, move |environment, event_loop, callee| {
+                        let chunk = Chunk::default();
+                        let mut vm = Vm { environment, overflow, event_loop, limits, budget, depth, ..Vm::new(&chunk) };
+                        let value = vm.call(callee, Arguments::default())?;
+                        let event_loop = vm.event_loop.clone();
+                        event_loop.finish(&mut vm)?;
+                        Ok(value)
+                    });
+                    self.stack.push(worker);
+                },
+                Instruction::Interop(index) => {
+                    let namespace = constant(chunk, *index)?.to_string();
+                    self.stack.push(self.environment.import_natives(&namespace)?);
+                },
+                Instruction::Return => return Ok(Step::Return(self.pop()?)),
+            }
+        }
+    }
+
+    fn call(&mut self, callee: Value, arguments: Arguments) -> Result<Value, RuntimeError> {
+        match &*as_function(&callee)? {
+            Function::Closure { prototype, scope } => {
+                let FunctionBody::Bytecode(body) = &prototype.body else {
+                    return Err(RuntimeError::TypeError {
+                        message: format!("{} was not compiled for the vm backend.", callee)
+                    });
+                };
+                let binding = bind_arguments(prototype, arguments)?;
+                self.budget.check_depth(self.depth + 1)?;
+                if prototype.kind != FunctionKind::Plain {
+                    let frame = Frame {
+                        prototype: prototype.clone(),
+                        ip: 0,
+                        stack: vec![],
+                        scope: Some(Environment::call_scope(scope.clone(), binding.slots)),
+                        missing: binding.missing,
+                        depth: self.depth,
+                    };
+                    let generator = Generator::new(prototype.display_name(), Body::Frame(frame));
+                    return Ok(match prototype.kind {
+                        FunctionKind::Async => Value::Promise(self.event_loop.spawn(generator)),
+                        _ => Value::Generator(generator),
+                    });
+                }
+
+                // `RETURN` ends `execute` directly, wherever it is in the body.
+                self.depth += 1;
+                let caller = self.environment.enter_call(scope.clone(), binding.slots);
+                let base = self.stack.len();
+                let result = self.execute(body, &mut 0, &binding.missing);
+                self.stack.truncate(base);
+                self.environment.restore_scope(caller);
+                self.depth -= 1;
+                result.map(Step::value)
+            },
+            Function::Composed { first, then } => {
+                let intermediate = self.call(first.clone(), arguments)?;
+                self.call(then.clone(), Arguments::positional(vec![intermediate]))
+            },
+            Function::Next(generator) => {
+                let sent = sent_value(arguments)?;
+                Ok(self.resume(generator, sent)?.into_object())
+            },
+            Function::Builtin(builtin) => call_builtin(*builtin, arguments, &self.event_loop),
+            Function::Channel(channel, method) => call_channel_method(channel, *method, arguments, &self.event_loop),
+            Function::Native(native) => self.budget.check_size(native.call(arguments)?),
+        }
+    }
+
+    fn resume(&mut self, generator: &Generator, sent: Value) -> Result<Step, RuntimeError> {
+        generator.resume(sent, |frame, sent| self.run_frame(frame, sent))
+    }
+
+    /// Continues a generator call, or the task of an async call, where it
+    /// was suspended: its part of the value stack and its scope are put
+    /// back, with `sent` as the value of the `YIELD` or `AWAIT` it stopped
+    /// at, and saved again if it suspends once more.
+    fn run_frame(&mut self, frame: &mut Frame, sent: Value) -> Result<Step, RuntimeError> {
+        let FunctionBody::Bytecode(body) = &frame.prototype.body else {
+            return Err(RuntimeError::TypeError {
+                message: format!("{} was not compiled for the vm backend.", frame.prototype.display_name())
+            });
+        };
+
+        // The body is nested in the call that made it, and also runs on top
+        // of whoever resumes it.
+        let depth = self.depth.max(frame.depth) + 1;
+        self.budget.check_depth(depth)?;
+        let resumer = std::mem::replace(&mut self.depth, depth);
+        let caller = self.environment.current_scope();
+        let in_task = std::mem::replace(&mut self.in_task, frame.prototype.kind == FunctionKind::Async);
+        self.environment.restore_scope(frame.scope.take());
+        let base = self.stack.len();
+        self.stack.append(&mut frame.stack);
+        if frame.ip > 0 {
+            self.stack.push(sent);
+        }
+        let result = self.execute(body, &mut frame.ip, &frame.missing);
+        if let Ok(Step::Yield(_)) = result {
+            frame.stack = self.stack.split_off(base);
+            frame.scope = self.environment.current_scope();
+        } else {
+            self.stack.truncate(base);
+        }
+        self.environment.restore_scope(caller);
+        self.in_task = in_task;
+        self.depth = resumer;
+        result
+    }
+
+    fn peek(&self) -> Result<Value, RuntimeError> {
+        self.stack.last().cloned().ok_or_else(|| RuntimeError::TypeError {
+            message: "VM stack underflow.".to_string()
+        })
+    }
+
+    fn pop(&mut self) -> Result<Value, RuntimeError> {
+        self.stack.pop().ok_or_else(|| RuntimeError::TypeError {
+            message: "VM stack underflow.".to_string()
+        })
+    }
+}
+
+impl Driver for Vm<'_> {
+    fn resume(&mut self, generator: &Generator, sent: Value) -> Result<Step, RuntimeError> {
+        Vm::resume(self, generator, sent)
+    }
+
+    fn call(&mut self, callee: Value, arguments: Arguments) -> Result<Value, RuntimeError> {
+        Vm::call(self, callee, arguments)
+    }
+
+    fn budget(&self) -> &Budget {
+        &self.budget
+    }
+}
+
+fn constant(chunk: &Chunk, index: usize) -> Result<Value, RuntimeError> {
+    chunk.constants.get(index).cloned().ok_or_else(|| RuntimeError::TypeError {
+        message: format!("Constant index {} is out of range.", index)
+    })
+}
@@ -0,0 +1,41 @@
+use std::thread;
+use crate::runtime::environment::Environment;
+use crate::runtime::error::RuntimeError;
+use crate::runtime::event_loop::EventLoop;
+use crate::runtime::limits::{guard_stack, WORKER_STACK};
+use crate::runtime::value::{DeepCopy, Value};
+
+/// Starts a `work` block: `callee`, the block as a function without
+/// parameters, runs on a thread of its own, in a fresh instance of the
+/// backend made by `run` around a copy of `environment` and an event loop
+/// of its own, where it may `await`. The block, the variables it can see
+/// and its result are all copied across, so the worker shares nothing with
+/// the program but channels. Returns the promise of its result, which is
+/// settled on `event_loop`; an error in the block rejects it, and awaiting
+/// it surfaces the error there.
+pub fn spawn_worker(
+    callee: &Value,
+    environment: &Environment,
+    event_loop: &EventLoop,
+    run: impl FnOnce(Environment, EventLoop, Value) -> Result<Value, RuntimeError> + Send + 'static,
+) -> Value {
+    let mut copy = DeepCopy::default();
+    let callee = copy.value(callee);
+    let environment = environment.isolate(&mut copy);
+
+    let promise = event_loop.start_work();
+    let worker_loop = event_loop.for_worker();
+    let (settled, program_loop, thread_loop) = (promise.clone(), event_loop.clone(), worker_loop.clone());
+    let spawned = thread::Builder::new().name("worker".to_string()).stack_size(WORKER_STACK).spawn(move || {
+        guard_stack(WORKER_STACK);
+        let result = run(environment, thread_loop.clone(), callee).map(|value| DeepCopy::default().value(&value));
+        program_loop.complete_work(&settled, result);
+        thread_loop.end_worker();
+    });
+    if let Err(error) = spawned {
+        let message = format!("Could not start a worker thread: {}.", error);
+        event_loop.complete_work(&promise, Err(RuntimeError::TypeError { message }));
+        worker_loop.end_worker();
+    }
+    Value::Promise(promise)
+}
@@ -0,0 +1,113 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use compiler::frontend::{lexer::tokenize, parser::produce_ast, resolver::Resolver};
+use compiler::runtime::bytecode::Chunk;
+use compiler::runtime::compiler::compile;
+use compiler::runtime::optimizer::optimize;
+use compiler::runtime::rbc;
+use compiler::runtime::runner::Runner;
+
+/// The chunk of `source`, or `None` if it does not compile.
+fn chunk(source: &str, optimized: bool) -> Option<Chunk> {
+    let mut program = produce_ast(&mut tokenize(source).ok()?).ok()?;
+    Resolver::new().resolve(&mut program).ok()?;
+    if optimized {
+        optimize(&mut program);
+    }
+    compile(&program).ok()
+}
+
+/// A directory of its own for each test, as they run in parallel.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rbc-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run(args: &[&str], file: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .args(args)
+        .arg(file)
+        .output()
+        .expect("failed to launch the interpreter binary");
+    assert!(output.status.success(), "{:?} crashed on {}", args, file.display());
+    String::from_utf8(output.stdout).expect("interpreter output is not UTF-8")
+}
+
+#[test]
+fn every_sample_survives_encoding_and_decoding() {
+    let samples = Path::new(env!("CARGO_MANIFEST_DIR")).join("samples");
+    let mut checked = 0;
+
+    for entry in fs::read_dir(samples).expect("samples directory is missing") {
+        let path = entry.expect("unreadable samples entry").path();
+        if path.extension().is_none_or(|extension| extension != "txt") {
+            continue;
+        }
+        let source = fs::read_to_string(&path).unwrap();
+        for optimized in [false, true] {
+            let Some(chunk) = chunk(&source, optimized) else {
+                continue;
+            };
+            let hash = Runner::cache_hash(&source, optimized);
+            let bytes = rbc::encode(&chunk, hash);
+            let decoded = rbc::decode(&bytes, hash).unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+            assert_eq!(rbc::encode(&decoded, hash), bytes, "{} changed on the way back", path.display());
+            assert!(rbc::decode(&bytes, hash ^ 1).is_err(), "{} decoded for another source", path.display());
+            checked += 1;
+        }
+    }
+
+    assert!(checked > 0, "no sample programs were found");
+}
+
+#[test]
+fn a_second_run_loads_the_cache_of_its_own_mode() {
+    let dir = scratch_dir("hit");
+    let file = dir.join("program.txt");
+    let source = "1 + 1\n";
+    fs::write(&file, source).unwrap();
+    let name = file.to_str().unwrap();
+
+    // A chunk that is not the source's own shows that the cache is what ran.
+    let planted = rbc::encode(&chunk("40 + 2", false).unwrap(), Runner::cache_hash(source, false));
+    fs::write(Runner::cache_path(name, false), &planted).unwrap();
+    assert_eq!(run(&["--backend=vm"], &file).trim(), "Ok(Integer(42))");
+
+    assert_eq!(run(&["--backend=vm", "--optimize"], &file).trim(), "Ok(Integer(2))");
+    assert!(Runner::cache_path(name, true).exists());
+    assert_eq!(fs::read(Runner::cache_path(name, false)).unwrap(), planted, "the optimized run evicted the other entry");
+    assert_eq!(run(&["--backend=vm"], &file).trim(), "Ok(Integer(42))");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn a_source_named_like_a_cache_is_left_alone() {
+    let dir = scratch_dir("source");
+    let file = dir.join("program.rbc");
+    fs::write(&file, "6 * 7\n").unwrap();
+
+    assert_eq!(run(&["--backend=vm"], &file).trim(), "Ok(Integer(42))");
+    assert_eq!(fs::read_to_string(&file).unwrap(), "6 * 7\n");
+    assert_eq!(run(&["--backend=vm"], &file).trim(), "Ok(Integer(42))");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn disasm_lists_the_chunk_of_a_file() {
+    let dir = scratch_dir("disasm");
+    let file = dir.join("program.txt");
+    fs::write(&file, "def x = 40\nx + 2\n").unwrap();
+
+    for _ in 0..2 {
+        let listing = run(&["disasm"], &file);
+        assert!(listing.starts_with("== constants ==\n"), "{}", listing);
+        assert!(listing.contains("Integer(40)"), "{}", listing);
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
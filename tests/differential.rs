@@ -0,0 +1,38 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Timers run on the virtual clock, so samples that sleep finish at once
+/// and see the same times on every run.
+fn run(sample: &Path, flags: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .arg("--virtual-clock")
+        .args(flags)
+        .arg(sample)
+        .output()
+        .expect("failed to launch the interpreter binary");
+
+    assert!(output.status.success(), "{:?} crashed on {}", flags, sample.display());
+    String::from_utf8(output.stdout).expect("interpreter output is not UTF-8")
+}
+
+#[test]
+fn vm_matches_tree_walker_on_every_sample() {
+    let samples = Path::new(env!("CARGO_MANIFEST_DIR")).join("samples");
+    let mut checked = 0;
+
+    for entry in fs::read_dir(samples).expect("samples directory is missing") {
+        let path = entry.expect("unreadable samples entry").path();
+        if path.extension().is_none_or(|extension| extension != "txt") {
+            continue;
+        }
+
+        let expected = run(&path, &["--backend=tree"]);
+        for flags in [&["--backend=vm"][..], &["--backend=tree", "--optimize"], &["--backend=vm", "--optimize"]] {
+            assert_eq!(expected, run(&path, flags), "{:?} disagrees with the tree walker on {}", flags, path.display());
+        }
+        checked += 1;
+    }
+
+    assert!(checked > 0, "no sample programs were found");
+}
@@ -0,0 +1,151 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+use compiler::{Engine, FromValue, IntoValue, RuntimeError, ScriptFunction, TraceFrame, Value};
+
+#[test]
+fn globals_and_functions_survive_between_evals() {
+    let mut engine = Engine::new();
+    engine.eval("def count = 1").unwrap();
+    engine.eval("fn bump(by = 1) { count += by\n count }").unwrap();
+    engine.eval("bump()").unwrap();
+
+    assert_eq!(engine.call_as::<i64>("bump", vec![10.into_value()]).unwrap(), 12);
+    assert_eq!(engine.global::<i64>("count").unwrap(), 12);
+}
+
+#[test]
+fn host_globals_can_be_read_and_assigned_by_scripts() {
+    let mut engine = Engine::new();
+    engine.set_global("scores", vec![3, 5, 8]).set_global("bonus", Some(2.5));
+
+    let total: f64 = engine.eval_as("def total = 0\nfor (s in scores) { total += s }\nbonus = total + bonus").unwrap();
+    assert_eq!(total, 18.5);
+    assert_eq!(engine.global::<Option<f64>>("bonus").unwrap(), Some(18.5));
+}
+
+#[test]
+fn values_convert_both_ways() {
+    let mut engine = Engine::new();
+    let point: BTreeMap<String, i64> = engine.eval_as("{ x: 1, y: 2 }").unwrap();
+    assert_eq!(point, BTreeMap::from([("x".to_string(), 1), ("y".to_string(), 2)]));
+
+    let nested: Vec<Option<bool>> = engine.eval_as("[1 < 2, null, 2 < 1]").unwrap();
+    assert_eq!(nested, vec![Some(true), None, Some(false)]);
+
+    assert_eq!(u64::MAX.into_value().to_string(), u64::MAX.to_string());
+    assert_eq!(u64::from_value(u64::MAX.into_value()).unwrap(), u64::MAX);
+    assert!(matches!(u8::from_value(Value::Integer(300)), Err(RuntimeError::UnrecognizedValueType { .. })));
+    assert!(matches!(engine.eval_as::<String>("1 + 1"), Err(RuntimeError::UnrecognizedValueType { .. })));
+}
+
+#[test]
+fn natives_are_imported_with_interop() {
+    let mut engine = Engine::new();
+    engine.register_native("geo", "dist", |arguments| match arguments {
+        [x, y] => {
+            let (x, y) = (f64::from_value(x.clone())?, f64::from_value(y.clone())?);
+            Ok((x * x + y * y).sqrt().into_value())
+        },
+        _ => Err(RuntimeError::TypeError { message: "dist takes x and y.".to_string() }),
+    });
+
+    assert_eq!(engine.eval_as::<f64>("interop geo { dist }\ndist(3, 4)").unwrap(), 5.0);
+    assert!(matches!(engine.eval("geo.dist(1)"), Err(RuntimeError::UndefinedVariable { .. })));
+    assert!(matches!(engine.eval("interop geo\ngeo.dist(1)"), Err(RuntimeError::TypeError { .. })));
+}
+
+#[test]
+fn natives_run_on_the_thread_the_host_calls_from() {
+    thread_local! {
+        static HOST: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    }
+    let mut engine = Engine::new();
+    engine.register_native("host", "here", |_| Ok(HOST.get().into_value()));
+    HOST.set(true);
+
+    assert!(engine.eval_as::<bool>("interop host\nfn here() { host.here() }\nhere()").unwrap());
+    assert!(engine.call_as::<bool>("here", vec![]).unwrap());
+    let here: ScriptFunction<(), bool> = engine.function("here").unwrap();
+    assert!(here.call(&mut engine, ()).unwrap());
+}
+
+#[test]
+fn errors_leave_the_engine_usable() {
+    let mut engine = Engine::new();
+    engine.eval("fn fail(x) { x / 0 }").unwrap();
+
+    let error = engine.call("fail", vec![1.into_value()]).unwrap_err();
+    assert!(matches!(error.root(), RuntimeError::DivisionByZero));
+    assert_eq!(error.trace(), [TraceFrame { function: "fail".to_string(), line: None }]);
+    assert!(matches!(engine.call("missing", vec![]), Err(RuntimeError::UndefinedVariable { .. })));
+    assert_eq!(engine.eval_as::<i64>("fail |> fn (f) { 7 }").unwrap(), 7);
+}
+
+#[test]
+fn script_functions_are_kept_and_called_with_typed_signatures() {
+    let mut engine = Engine::new();
+    engine.eval("def total = 0\nfn add(x, by = 1) { total += x * by\n total }").unwrap();
+    let add: ScriptFunction<(i64, i64), i64> = engine.function("add").unwrap();
+    engine.eval("add = null").unwrap();
+
+    assert_eq!(add.call(&mut engine, (2, 3)).unwrap(), 6);
+    assert_eq!(add.call(&mut engine, (4, 1)).unwrap(), 10);
+    assert_eq!(add.name(), "add");
+
+    let twice: ScriptFunction<(ScriptFunction<(i64,), i64>, i64), i64> = engine.eval_as("fn (f, x) { f(f(x)) }").unwrap();
+    let double = engine.eval_as("fn (x) { x * 2 }").unwrap();
+    assert_eq!(twice.call(&mut engine, (double, 5)).unwrap(), 20);
+    assert!(matches!(engine.function::<(), ()>("total"), Err(RuntimeError::UnrecognizedValueType { .. })));
+}
+
+#[test]
+fn errors_of_script_functions_carry_the_stack_trace() {
+    let mut engine = Engine::new();
+    engine.eval("fn inner(x) { x / 0 }\nfn outer(x) {\n  inner(x)\n}\nfn main() {\n  def y = 1\n  outer(y)\n}").unwrap();
+    let main: ScriptFunction<(), Value> = engine.function("main").unwrap();
+
+    let error = main.call(&mut engine, ()).unwrap_err();
+    assert!(matches!(error.root(), RuntimeError::DivisionByZero));
+    assert_eq!(error.trace(), [
+        TraceFrame { function: "inner".to_string(), line: Some(3) },
+        TraceFrame { function: "outer".to_string(), line: Some(7) },
+        TraceFrame { function: "main".to_string(), line: None },
+    ]);
+    assert_eq!(error.to_string(), "Erro de Execução: Divisão por zero.\n  em inner, chamada na linha 3\n  em outer, chamada na linha 7\n  em main, chamada pelo host");
+
+    let again = main.call(&mut engine, ()).unwrap_err();
+    assert_eq!(again.trace().len(), 3);
+    let by_name = engine.call("main", vec![]).unwrap_err();
+    assert_eq!(by_name.trace(), error.trace());
+    assert_eq!(by_name.to_string(), error.to_string());
+}
+
+#[test]
+fn cyclic_values_print_and_compare() {
+    let mut engine = Engine::new();
+    let first = engine.eval("def c = [0]\nc[0] = c\nc").unwrap();
+    let second = engine.eval("def d = [0]\nd[0] = d\nd").unwrap();
+
+    assert_eq!(first.to_string(), "[[...]]");
+    assert_eq!(engine.eval("def o = { a: 1 }\no.me = o\no").unwrap().to_string(), "{a: 1, me: {...}}");
+    assert_eq!(first, second);
+    assert_ne!(first, engine.eval("[[1]]").unwrap());
+}
+
+/// The tree walker runs each generator on a thread of its own; those of
+/// finished generators are reused instead of one being started per call.
+#[test]
+fn finished_generators_give_their_threads_back() {
+    let threads = Arc::new(Mutex::new(HashSet::new()));
+    let seen = threads.clone();
+    let mut engine = Engine::new();
+    engine.register_native("probe", "thread", move |_| {
+        seen.lock().unwrap().insert(std::thread::current().id());
+        Ok(Value::Null)
+    });
+
+    let program = "interop probe\nfn* one() {\n  probe.thread()\n  yield 1\n}\ndef total = 0\ndef i = 0\nwhile (i < 2000) {\n  for (x in one()) { total += x }\n  i += 1\n}\ntotal";
+    assert_eq!(engine.eval_as::<i64>(program).unwrap(), 2000);
+    let used = threads.lock().unwrap().len();
+    assert!(used <= 16, "2000 generators in a row ran on {} threads", used);
+}
@@ -0,0 +1,59 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn manifest_dir() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// `include/compiler.h` is generated from `src/ffi.rs`. Run this test with
+/// `UPDATE_HEADER=1` after changing the C API to write it again.
+#[test]
+fn header_matches_the_c_api() {
+    let config = cbindgen::Config::from_file(manifest_dir().join("cbindgen.toml")).expect("cbindgen.toml is invalid");
+    let mut generated = Vec::new();
+    cbindgen::generate_with_config(manifest_dir(), config).expect("cbindgen failed on the crate").write(&mut generated);
+    let generated = String::from_utf8(generated).expect("header is not UTF-8");
+
+    let path = manifest_dir().join("include/compiler.h");
+    if env::var_os("UPDATE_HEADER").is_some() {
+        fs::write(&path, &generated).expect("cannot write the header");
+    }
+    let committed = fs::read_to_string(&path).unwrap_or_default();
+    assert!(committed == generated, "include/compiler.h is out of date; run the tests with UPDATE_HEADER=1");
+}
+
+/// The directory cargo builds the cdylib in, `deps`, next to the test
+/// binary. `cargo test` does not copy it up to the profile directory.
+fn library_dir() -> PathBuf {
+    let exe = env::current_exe().expect("no path to the test binary");
+    exe.parent().expect("test binary outside the target directory").to_path_buf()
+}
+
+#[cfg(unix)]
+#[test]
+fn c_program_embeds_the_library() {
+    let library_dir = library_dir();
+    let program = Path::new(env!("CARGO_TARGET_TMPDIR")).join("embed");
+    let compiled = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg(manifest_dir().join("tests/ffi/embed.c"))
+        .arg("-I").arg(manifest_dir().join("include"))
+        .arg("-L").arg(&library_dir)
+        .arg(format!("-Wl,-rpath,{}", library_dir.display()))
+        .arg("-lcompiler")
+        .arg("-o").arg(&program)
+        .status()
+        .expect("failed to launch the C compiler");
+    assert!(compiled.success(), "tests/ffi/embed.c does not compile");
+
+    // Cargo puts the profile directory, which may hold an older build of
+    // the library, on the search path first; the rpath comes after it.
+    let output = Command::new(&program)
+        .env("LD_LIBRARY_PATH", &library_dir)
+        .output()
+        .expect("failed to launch the C program");
+    assert!(output.status.success(), "the C program failed: {}", String::from_utf8_lossy(&output.stderr));
+    let expected = fs::read_to_string(manifest_dir().join("tests/ffi/embed.out")).expect("tests/ffi/embed.out is missing");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}
@@ -0,0 +1,97 @@
+/* Embeds the interpreter through include/compiler.h; tests/ffi.rs builds
+ * and runs it, and compares its output with embed.out. */
+#include <stdio.h>
+#include <string.h>
+#include "compiler.h"
+
+static CompilerValue *scale(const CompilerValue *const *arguments, size_t count, void *user_data) {
+    double factor = *(const double *)user_data;
+    double x;
+    if (count != 1 || !compiler_value_number(arguments[0], &x)) {
+        return compiler_value_new_error("scale takes one number");
+    }
+    return compiler_value_new_number(x * factor);
+}
+
+static CompilerValue *shout(const CompilerValue *const *arguments, size_t count, void *user_data) {
+    (void)user_data;
+    char *text = count == 1 ? compiler_value_string(arguments[0]) : NULL;
+    if (text == NULL) {
+        return NULL;
+    }
+    char buffer[64];
+    snprintf(buffer, sizeof buffer, "%s!", text);
+    compiler_string_free(text);
+    return compiler_value_new_string(buffer);
+}
+
+static void show(CompilerEngine *engine, const char *source) {
+    CompilerValue *value = compiler_eval(engine, source);
+    if (value == NULL) {
+        printf("error: %s\n", compiler_last_error(engine));
+        return;
+    }
+
+    int64_t integer;
+    double number;
+    bool boolean;
+    char *text;
+    switch (compiler_value_type(value)) {
+    case COMPILER_TYPE_INTEGER:
+        compiler_value_integer(value, &integer);
+        printf("integer: %lld\n", (long long)integer);
+        break;
+    case COMPILER_TYPE_NUMBER:
+        compiler_value_number(value, &number);
+        printf("number: %.2f\n", number);
+        break;
+    case COMPILER_TYPE_BOOLEAN:
+        compiler_value_bool(value, &boolean);
+        printf("boolean: %s\n", boolean ? "true" : "false");
+        break;
+    case COMPILER_TYPE_STRING:
+        text = compiler_value_string(value);
+        printf("string: %s\n", text);
+        compiler_string_free(text);
+        break;
+    default:
+        text = compiler_value_display(value);
+        printf("other: %s\n", text);
+        compiler_string_free(text);
+        break;
+    }
+    compiler_value_free(value);
+}
+
+int main(void) {
+    CompilerEngine *engine = compiler_engine_new();
+    double factor = 2.5;
+    if (!compiler_register_native(engine, "host", "scale", scale, &factor)
+        || !compiler_register_native(engine, "host", "shout", shout, NULL)) {
+        return 1;
+    }
+
+    CompilerValue *name = compiler_value_new_string("hello");
+    compiler_set_global(engine, "greeting", name);
+    compiler_value_free(name);
+
+    show(engine, "def base = 40\nbase + 2");
+    show(engine, "interop host\nhost.scale(base)");
+    show(engine, "base < 41");
+    show(engine, "host.shout(greeting)");
+    show(engine, "[base, null]");
+    show(engine, "host.scale(greeting)");
+    show(engine, "host.shout(base)");
+    show(engine, "base / 0");
+    CompilerValue *after_error = compiler_eval(engine, "base");
+    if (after_error == NULL || compiler_last_error(engine) != NULL) {
+        return 1;
+    }
+    compiler_value_free(after_error);
+
+    compiler_set_limits(engine, 1000, 0, 0, 0, 0);
+    show(engine, "while (base > 0) { base }");
+
+    compiler_engine_free(engine);
+    return 0;
+}
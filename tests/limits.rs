@@ -0,0 +1,175 @@
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use compiler::runtime::limits::DEFAULT_CALL_DEPTH;
+use compiler::{Engine, Limits, Options, RuntimeError};
+
+fn engine_with(limits: Limits) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_limits(limits);
+    engine
+}
+
+#[test]
+fn fuel_stops_endless_loops_and_refills_for_every_eval() {
+    let mut engine = engine_with(Limits { fuel: Some(1_000), ..Limits::default() });
+
+    assert!(matches!(engine.eval("while (7 == 7) { 7 }"), Err(RuntimeError::OutOfFuel { fuel: 1_000 })));
+    assert_eq!(engine.eval_as::<i64>("def total = 0\nfor (i in [1, 2, 3]) { total += i }\ntotal").unwrap(), 6);
+    engine.eval("fn spin() { while (7 == 7) { 7 } }").unwrap();
+    assert!(matches!(engine.call("spin", vec![]).unwrap_err().root(), RuntimeError::OutOfFuel { .. }));
+}
+
+#[test]
+fn call_depth_is_capped_before_the_stack_overflows() {
+    // Programs run on the host's thread, so it needs the stack for the calls.
+    let host = std::thread::Builder::new().stack_size(64 * 1024 * 1024).spawn(|| {
+        let mut engine = engine_with(Limits { call_depth: 40, ..Limits::default() });
+        engine.eval("fn down(n) { if (n == 0) { 0 } else { down(n - 1) + 1 } }").unwrap();
+
+        assert_eq!(engine.eval_as::<i64>("down(39)").unwrap(), 39);
+        assert!(matches!(engine.eval("down(40)"), Err(RuntimeError::CallDepthExceeded { limit: 40 })));
+        assert_eq!(engine.eval_as::<i64>("down(10)").unwrap(), 10);
+    });
+    host.unwrap().join().unwrap();
+}
+
+#[test]
+fn runaway_recursion_stops_at_the_default_call_depth() {
+    let mut engine = Engine::new();
+
+    for program in ["fn f(n) { f(n + 1) }\nf(0)", "fn* g(n) { yield g(n + 1).next() }\ng(0).next()"] {
+        assert!(matches!(engine.eval(program), Err(RuntimeError::CallDepthExceeded { limit: DEFAULT_CALL_DEPTH })), "{}", program);
+    }
+    assert!(matches!(
+        engine.eval("async fn w(n) { await work { await w(n + 1) } }\nawait w(0)"),
+        Err(RuntimeError::CallDepthExceeded { limit: DEFAULT_CALL_DEPTH })
+    ));
+
+    let runaway = std::env::temp_dir().join(format!("runaway-{}.txt", std::process::id()));
+    std::fs::write(&runaway, "fn f(n) { f(n + 1) }\nf(0)\n").unwrap();
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_compiler"))
+            .arg(backend)
+            .arg(&runaway)
+            .output()
+            .expect("failed to launch the interpreter binary");
+        let stdout = String::from_utf8(output.stdout).expect("interpreter output is not UTF-8");
+        assert_eq!(stdout.trim(), "Err(CallDepthExceeded { limit: 1000 })", "{} overflowed", backend);
+    }
+    std::fs::remove_file(runaway).unwrap();
+}
+
+#[test]
+fn values_cannot_grow_past_the_value_size_limit() {
+    let mut engine = engine_with(Limits { value_size: Some(4_096), ..Limits::default() });
+
+    assert!(matches!(
+        engine.eval("def a = [1]\nwhile (7 == 7) { a = [...a, ...a] }"),
+        Err(RuntimeError::ValueTooLarge { limit: 4_096 })
+    ));
+    assert_eq!(engine.eval_as::<i64>("def o = { a: 0 }\nfor (i in [1, 2, 3]) { o.a = i }\no.a").unwrap(), 3);
+}
+
+#[test]
+fn lists_and_objects_together_cannot_take_more_than_the_heap_limit() {
+    let limits = Limits { value_size: Some(65_536), heap: Some(4_000_000), ..Limits::default() };
+    let row = "def row = []\ndef n = 0\nwhile (n < 1000) {\n    row = [...row, n]\n    n += 1\n}\n";
+
+    let mut engine = engine_with(limits);
+    let program = format!("{}def rows = []\nwhile (7 == 7) {{\n    rows = [...rows, [...row]]\n}}", row);
+    assert!(matches!(engine.eval(&program), Err(RuntimeError::HeapLimitExceeded { limit: 4_000_000 })));
+
+    // What is gone no longer counts, however much was built along the way.
+    let mut engine = engine_with(limits);
+    let program = format!("{}def m = 0\nwhile (m < 1000) {{\n    def copy = [...row]\n    m += 1\n}}\nm", row);
+    assert_eq!(engine.eval_as::<i64>(&program).unwrap(), 1000);
+
+    let mut engine = Engine::with_options(&Options { big_integers: true, ..Options::default() });
+    engine.set_limits(Limits { heap: Some(1_000), ..Limits::default() });
+    assert!(matches!(
+        engine.eval("def o = { a: 0 }\ndef s = 0\nwhile (7 == 7) {\n    s = 2 ** 64 * s + 2 ** 64\n    o.a = s\n}"),
+        Err(RuntimeError::HeapLimitExceeded { limit: 1_000 })
+    ));
+}
+
+#[test]
+fn timeout_stops_programs_that_run_too_long() {
+    let mut engine = engine_with(Limits { timeout: Some(Duration::from_millis(50)), ..Limits::default() });
+
+    assert!(matches!(engine.eval("while (7 == 7) { 7 }"), Err(RuntimeError::ExecutionTimeout { milliseconds: 50 })));
+    assert_eq!(engine.eval_as::<i64>("1 + 1").unwrap(), 2);
+}
+
+#[test]
+fn timeout_also_stops_programs_waiting_for_timers_and_workers() {
+    let mut engine = engine_with(Limits { timeout: Some(Duration::from_millis(200)), ..Limits::default() });
+
+    for program in [
+        "await sleep(3000)\n1",
+        "def w = work { await sleep(3000) }\nawait w",
+        "def c = channel()\ndef w = work { await sleep(3000) }\nawait c.recv()",
+    ] {
+        let started = Instant::now();
+        let result = engine.eval(program);
+        assert!(matches!(result, Err(RuntimeError::ExecutionTimeout { milliseconds: 200 })), "{}: {:?}", program, result);
+        assert!(started.elapsed() < Duration::from_millis(2_000), "{} waited past its timeout", program);
+    }
+    // The timers the programs left behind do not hold up the next one.
+    let started = Instant::now();
+    assert_eq!(engine.eval_as::<i64>("1 + 1").unwrap(), 2);
+    assert!(started.elapsed() < Duration::from_millis(2_000));
+}
+
+#[test]
+fn command_line_limits_apply_to_both_backends() {
+    let endless = Path::new(env!("CARGO_MANIFEST_DIR")).join("test.txt");
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_compiler"))
+            .args([backend, "--fuel=5000"])
+            .arg(&endless)
+            .output()
+            .expect("failed to launch the interpreter binary");
+        let stdout = String::from_utf8(output.stdout).expect("interpreter output is not UTF-8");
+        assert_eq!(stdout.trim(), "Err(OutOfFuel { fuel: 5000 })", "{} did not run out of fuel", backend);
+    }
+}
+
+#[test]
+fn an_interrupt_stops_only_the_evaluation_in_progress() {
+    let mut engine = Engine::new();
+    let interrupt = engine.interrupt_handle();
+    let interrupter = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        interrupt.interrupt();
+    });
+
+    assert!(matches!(engine.eval("while (7 == 7) { 7 }"), Err(RuntimeError::Interrupted)));
+    interrupter.join().unwrap();
+    assert_eq!(engine.eval_as::<i64>("2 * 21").unwrap(), 42);
+}
+
+#[test]
+fn an_interrupt_before_the_evaluation_is_not_lost() {
+    let mut engine = Engine::new();
+    engine.interrupt_handle().interrupt();
+
+    assert!(matches!(engine.eval("1 + 1"), Err(RuntimeError::Interrupted)));
+    assert_eq!(engine.eval_as::<i64>("2 * 21").unwrap(), 42);
+}
+
+#[test]
+fn an_interrupt_stops_a_program_waiting_for_a_timer() {
+    let mut engine = Engine::new();
+    let interrupt = engine.interrupt_handle();
+    let interrupter = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        interrupt.interrupt();
+    });
+
+    let started = Instant::now();
+    assert!(matches!(engine.eval("await sleep(4000)\n1"), Err(RuntimeError::Interrupted)));
+    assert!(started.elapsed() < Duration::from_millis(2_000));
+    interrupter.join().unwrap();
+    assert_eq!(engine.eval_as::<i64>("2 * 21").unwrap(), 42);
+}
@@ -0,0 +1,123 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn start_repl() -> std::process::Child {
+    Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to launch the REPL")
+}
+
+#[test]
+fn end_of_input_ends_the_session() {
+    let mut repl = start_repl();
+    repl.stdin.take().unwrap().write_all(b"def x = 6\nx * 7\n").unwrap();
+
+    let output = repl.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Repl v1.0\n> 6\n> 42\n> \n");
+}
+
+/// Reads what `reader` prints on its own thread, so that the test can
+/// wait for it with a deadline.
+fn read_in_background(mut reader: impl Read + Send + 'static) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 256];
+        while let Ok(read @ 1..) = reader.read(&mut buffer) {
+            if sender.send(buffer[..read].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Collects what `chunks` receives until `done` says it is enough, or the
+/// output ends; fails the test if that takes too long.
+fn collect_until(chunks: &Receiver<Vec<u8>>, output: &mut Vec<u8>, done: impl Fn(&[u8]) -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !done(output) {
+        match chunks.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(chunk) => output.extend(chunk),
+            Err(RecvTimeoutError::Disconnected) => return,
+            Err(RecvTimeoutError::Timeout) => panic!("the REPL stopped at {:?}", String::from_utf8_lossy(output)),
+        }
+    }
+}
+
+/// Runs `line` in a REPL and presses Ctrl-C until it is interrupted: the
+/// session goes on after it.
+#[cfg(unix)]
+fn assert_ctrl_c_interrupts(line: &str) {
+    let mut repl = start_repl();
+    let mut stdin = repl.stdin.take().unwrap();
+    let chunks = read_in_background(repl.stdout.take().unwrap());
+    let errors = read_in_background(repl.stderr.take().unwrap());
+    let (mut stdout, mut stderr) = (vec![], vec![]);
+
+    // The handler is in place once the banner is out. A press before `line`
+    // starts is dropped, so it is repeated until one stops it.
+    stdin.write_all(b"def x = 6\n").unwrap();
+    collect_until(&chunks, &mut stdout, |output| output.ends_with(b"> 6\n> "));
+    stdin.write_all(format!("{}\n", line).as_bytes()).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !stderr.ends_with(b"\n") {
+        assert!(Instant::now() < deadline, "{} was not interrupted", line);
+        let signalled = Command::new("kill").args(["-INT", &repl.id().to_string()]).status().unwrap();
+        assert!(signalled.success());
+        if let Ok(chunk) = errors.recv_timeout(Duration::from_millis(100)) {
+            stderr.extend(chunk);
+        }
+    }
+    stdin.write_all(b"x * 7\n").unwrap();
+    drop(stdin);
+    collect_until(&chunks, &mut stdout, |_| false);
+    collect_until(&errors, &mut stderr, |_| false);
+
+    assert!(repl.wait().unwrap().success());
+    assert_eq!(String::from_utf8_lossy(&stdout), "Repl v1.0\n> 6\n> > 42\n> \n", "after {}", line);
+    assert_eq!(String::from_utf8_lossy(&stderr), "Erro de Execução: Execução interrompida.\n", "after {}", line);
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_interrupts_the_evaluation_but_not_the_session() {
+    assert_ctrl_c_interrupts("while (7 == 7) { x }");
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_at_the_prompt_does_not_stop_the_next_line() {
+    let mut repl = start_repl();
+    let mut stdin = repl.stdin.take().unwrap();
+    let chunks = read_in_background(repl.stdout.take().unwrap());
+    let mut stdout = vec![];
+
+    stdin.write_all(b"def x = 6\n").unwrap();
+    collect_until(&chunks, &mut stdout, |output| output.ends_with(b"> 6\n> "));
+    let signalled = Command::new("kill").args(["-INT", &repl.id().to_string()]).status().unwrap();
+    assert!(signalled.success());
+    thread::sleep(Duration::from_millis(100));
+    stdin.write_all(b"x * 7\n").unwrap();
+    drop(stdin);
+    collect_until(&chunks, &mut stdout, |_| false);
+
+    let output = repl.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&stdout), "Repl v1.0\n> 6\n> 42\n> \n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_interrupts_a_wait_for_a_timer() {
+    let started = Instant::now();
+    assert_ctrl_c_interrupts("await sleep(4000)");
+    assert!(started.elapsed() < Duration::from_secs(4));
+}